@@ -0,0 +1,46 @@
+use std::{fs::File, io::Write};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use payment_transaction_engine::{
+    input::Input,
+    reader::{self, CsvTxReader},
+};
+
+const ROWS: usize = 200_000;
+
+fn write_transactions() -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(file, "type,client,tx,amount").unwrap();
+    for tx_id in 1..=ROWS {
+        writeln!(file, "deposit,{},{},1.5", tx_id % 100, tx_id).unwrap();
+    }
+    file
+}
+
+fn parse(mut input: Input) -> usize {
+    let mut csv_reader = reader::new_csv_reader(input.reader());
+    let mut rows = 0;
+    for tx in CsvTxReader::new(&mut csv_reader) {
+        black_box(tx.unwrap());
+        rows += 1;
+    }
+    rows
+}
+
+fn read_and_parse(c: &mut Criterion) {
+    let file = write_transactions();
+    let path = file.path().to_str().unwrap();
+    let mut group = c.benchmark_group("read_and_parse");
+
+    group.bench_function("buffered", |b| {
+        b.iter(|| parse(Input::Stream(Box::new(File::open(path).unwrap()))))
+    });
+    group.bench_function("io_uring", |b| {
+        b.iter(|| parse(Input::uring(path).unwrap()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, read_and_parse);
+criterion_main!(benches);
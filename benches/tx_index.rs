@@ -0,0 +1,76 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use payment_transaction_engine::amount::Amount;
+use payment_transaction_engine::{
+    lmdb::LmdbTxStore,
+    store::{TxIndex, TxStore},
+    types::{ClientId, StoredTx, TxId, TxType},
+};
+
+const TXS: TxId = 10_000;
+const MAP_SIZE: usize = 256 * 1024 * 1024;
+
+fn fill(index: &mut impl TxIndex) {
+    for tx_id in 0..TXS {
+        index
+            .insert(
+                tx_id,
+                StoredTx::new(
+                    TxType::Deposit,
+                    (tx_id % 100) as ClientId,
+                    Amount::new(15, 1),
+                ),
+            )
+            .unwrap();
+    }
+}
+
+fn lookup(index: &impl TxIndex) {
+    // Disputes typically reference a small fraction of transactions, many of them unknown.
+    for tx_id in (0..TXS * 2).step_by(7) {
+        black_box(index.get(tx_id).unwrap());
+    }
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+
+    group.bench_function("memory", |b| {
+        b.iter_batched(
+            TxStore::default,
+            |mut index| fill(&mut index),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("lmdb", |b| {
+        b.iter_batched(
+            || {
+                let dir = tempfile::tempdir().unwrap();
+                let index = LmdbTxStore::open(dir.path(), MAP_SIZE).unwrap();
+                (dir, index)
+            },
+            |(_dir, mut index)| fill(&mut index),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn dispute_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispute_lookup");
+
+    let mut memory = TxStore::default();
+    fill(&mut memory);
+    group.bench_function("memory", |b| b.iter(|| lookup(&memory)));
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut lmdb = LmdbTxStore::open(dir.path(), MAP_SIZE).unwrap();
+    fill(&mut lmdb);
+    group.bench_function("lmdb", |b| b.iter(|| lookup(&lmdb)));
+
+    group.finish();
+}
+
+criterion_group!(benches, insert, dispute_lookup);
+criterion_main!(benches);
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::anyhow;
+
+use crate::types::ClientId;
+
+/// Restricts which clients may transact, checked before any account is touched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientAccess {
+    /// Only these clients may transact.
+    Allow(HashSet<ClientId>),
+    /// These clients may not transact.
+    Block(HashSet<ClientId>),
+}
+
+impl ClientAccess {
+    pub fn permits(&self, client_id: ClientId) -> bool {
+        match self {
+            ClientAccess::Allow(clients) => clients.contains(&client_id),
+            ClientAccess::Block(clients) => !clients.contains(&client_id),
+        }
+    }
+
+    /// Applies `f` to every listed client, such as to match pseudonymized client ids.
+    pub fn map_clients(self, f: impl Fn(ClientId) -> ClientId) -> Self {
+        match self {
+            ClientAccess::Allow(clients) => {
+                ClientAccess::Allow(clients.into_iter().map(f).collect())
+            }
+            ClientAccess::Block(clients) => {
+                ClientAccess::Block(clients.into_iter().map(f).collect())
+            }
+        }
+    }
+
+    /// Loads an allowlist from a file with one client id per line.
+    pub fn load_allowlist(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(ClientAccess::Allow(read_client_ids(File::open(path)?)?))
+    }
+
+    /// Loads a blocklist from a file with one client id per line.
+    pub fn load_blocklist(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(ClientAccess::Block(read_client_ids(File::open(path)?)?))
+    }
+}
+
+/// Reads one client id per line, skipping blank lines and `#` comments.
+pub fn read_client_ids(r: impl io::Read) -> anyhow::Result<HashSet<ClientId>> {
+    let mut clients = HashSet::new();
+
+    for (index, line) in BufReader::new(r).lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let client_id = line
+            .parse()
+            .map_err(|_| anyhow!("Invalid client id {} on line {}", line, index + 1))?;
+        clients.insert(client_id);
+    }
+
+    Ok(clients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_client_ids_skipping_comments() {
+        let clients =
+            read_client_ids("# blocked after fraud review\n3\n\n7 # duplicate\n".as_bytes())
+                .unwrap();
+
+        assert_eq!(clients, HashSet::from([3, 7]));
+    }
+
+    #[test]
+    fn rejects_invalid_client_ids() {
+        assert!(read_client_ids("3\nabc\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn permits_only_allowed_or_unblocked_clients() {
+        let allow = ClientAccess::Allow(HashSet::from([1]));
+        let block = ClientAccess::Block(HashSet::from([1]));
+
+        assert!(allow.permits(1) && !allow.permits(2));
+        assert!(!block.permits(1) && block.permits(2));
+    }
+}
@@ -1,44 +1,168 @@
-use std::collections::HashMap;
-
-use anyhow::anyhow;
-use rust_decimal::Decimal;
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::ops::Bound;
+
+use crate::{
+    amount::Amount,
+    rejection::RejectCode,
+    types::{Account, ClientId},
+};
+
+/// Holds client accounts and applies balance changes to them.
+///
+/// Only depends on `core` and `alloc` so the balance logic can be used without the standard
+/// library.
+pub trait Manager {
+    type Error: core::error::Error + Send + Sync + 'static;
 
-use crate::types::Account;
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error>;
 
-pub trait Manager {
-    fn ensure_account(&mut self, client_id: u16) -> anyhow::Result<()>;
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error>;
 
-    fn deposit(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()>;
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error>;
 
-    fn withdraw(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()>;
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error>;
 
-    fn withdraw_held(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()>;
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error>;
 
-    fn hold(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()>;
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error>;
 
-    fn release(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()>;
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error>;
 
-    fn lock(&mut self, client_id: u16) -> anyhow::Result<()>;
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error>;
 
-    fn is_locked(&mut self, client_id: u16) -> anyhow::Result<bool>;
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error>;
 
     fn all(&self) -> Vec<&Account>;
+
+    /// Returns the account of `client_id`, if it exists. The default implementation searches
+    /// [`Manager::all`].
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.all()
+            .into_iter()
+            .find(|acc| acc.client_id == client_id)
+    }
+
+    /// Returns up to `limit` accounts in client id order, starting after the `after` cursor, so
+    /// large account sets can be listed a page at a time.
+    ///
+    /// The default implementation filters [`Manager::all`], which must then be in client id order.
+    fn page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        self.all()
+            .into_iter()
+            .filter(|acc| after.is_none_or(|after| acc.client_id > after))
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the accounts matching every condition of `filter`. Backends that can evaluate the
+    /// filter natively should override this rather than loading every account.
+    fn filter(&self, filter: &AccountFilter) -> Vec<&Account> {
+        self.all()
+            .into_iter()
+            .filter(|acc| filter.matches(acc))
+            .collect()
+    }
+}
+
+/// Conditions an account must meet to be returned by [`Manager::filter`]. Unset conditions match
+/// every account.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountFilter {
+    pub locked: Option<bool>,
+    pub available_below: Option<Amount>,
+    pub held_above: Option<Amount>,
+}
+
+impl AccountFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = Some(locked);
+        self
+    }
+
+    pub fn available_below(mut self, amount: Amount) -> Self {
+        self.available_below = Some(amount);
+        self
+    }
+
+    pub fn held_above(mut self, amount: Amount) -> Self {
+        self.held_above = Some(amount);
+        self
+    }
+
+    pub fn matches(&self, acc: &Account) -> bool {
+        self.locked.is_none_or(|locked| acc.is_locked == locked)
+            && self
+                .available_below
+                .is_none_or(|amount| acc.available_amount < amount)
+            && self
+                .held_above
+                .is_none_or(|amount| acc.held_amount > amount)
+    }
+}
+
+/// Why [`SimpleManager`] (and the managers built on its rules) refused a balance change.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AccountError {
+    #[error("The amount is not positive")]
+    NonPositiveAmount,
+    #[error("Available amount is too low")]
+    InsufficientAvailable,
+    #[error("Held amount is too low")]
+    InsufficientHeld,
+    /// The change would make the `balance` amount too large to represent.
+    #[error("Cannot {operation} amount as the resulting {balance} amount is too large")]
+    Overflow {
+        operation: &'static str,
+        balance: &'static str,
+    },
+    #[error("Account for client {0} not found")]
+    AccountNotFound(ClientId),
+}
+
+impl AccountError {
+    pub fn code(&self) -> RejectCode {
+        match self {
+            AccountError::NonPositiveAmount => RejectCode::BadAmount,
+            AccountError::InsufficientAvailable | AccountError::InsufficientHeld => {
+                RejectCode::InsufficientFunds
+            }
+            AccountError::Overflow { .. } => RejectCode::Overflow,
+            AccountError::AccountNotFound(_) => RejectCode::AccountNotFound,
+        }
+    }
 }
 
+#[derive(Default)]
 pub struct SimpleManager {
-    accounts: HashMap<u16, Account>,
+    accounts: BTreeMap<ClientId, Account>,
 }
 
 impl SimpleManager {
     pub fn new() -> Self {
         Self {
-            accounts: HashMap::new(),
+            accounts: BTreeMap::new(),
         }
     }
+
+    pub fn get(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    /// Adds the account, replacing any existing account of the same client.
+    pub fn insert(&mut self, account: Account) {
+        self.accounts.insert(account.client_id, account);
+    }
 }
 
 impl Manager for SimpleManager {
-    fn ensure_account(&mut self, client_id: u16) -> anyhow::Result<()> {
+    type Error = AccountError;
+
+    #[allow(clippy::map_entry)]
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
         if !self.accounts.contains_key(&client_id) {
             self.accounts.insert(client_id, Account::new(client_id));
         }
@@ -46,7 +170,7 @@ impl Manager for SimpleManager {
         Ok(())
     }
 
-    fn deposit(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()> {
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
         check_positive(amount)?;
 
         match self.accounts.get_mut(&client_id) {
@@ -55,53 +179,54 @@ impl Manager for SimpleManager {
                     acc.available_amount = new_amount;
                     Ok(())
                 }
-                None => Err(anyhow!(
-                    "Cannot deposit amount as the resulting available amount is too large"
-                )),
+                None => Err(AccountError::Overflow {
+                    operation: "deposit",
+                    balance: "available",
+                }),
             },
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn withdraw(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()> {
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
         check_positive(amount)?;
 
         match self.accounts.get_mut(&client_id) {
             Some(acc) => {
-                if acc.available_amount - amount < Decimal::ZERO {
-                    return Err(anyhow!("Available amount is too low"));
+                if acc.available_amount - amount < Amount::ZERO {
+                    return Err(AccountError::InsufficientAvailable);
                 }
 
                 acc.available_amount -= amount;
                 Ok(())
             }
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn withdraw_held(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()> {
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
         check_positive(amount)?;
 
         match self.accounts.get_mut(&client_id) {
             Some(acc) => {
-                if acc.held_amount - amount < Decimal::ZERO {
-                    return Err(anyhow!("Held amount is too low"));
+                if acc.held_amount - amount < Amount::ZERO {
+                    return Err(AccountError::InsufficientHeld);
                 }
 
                 acc.held_amount -= amount;
                 Ok(())
             }
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn hold(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()> {
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
         check_positive(amount)?;
 
         match self.accounts.get_mut(&client_id) {
             Some(acc) => {
-                if acc.available_amount - amount < Decimal::ZERO {
-                    return Err(anyhow!("Available amount is too low"));
+                if acc.available_amount - amount < Amount::ZERO {
+                    return Err(AccountError::InsufficientAvailable);
                 }
 
                 match acc.held_amount.checked_add(amount) {
@@ -110,22 +235,23 @@ impl Manager for SimpleManager {
                         acc.held_amount = new_amount;
                         Ok(())
                     }
-                    None => Err(anyhow!(
-                        "Cannot hold amount as the resulting held amount is too large"
-                    )),
+                    None => Err(AccountError::Overflow {
+                        operation: "hold",
+                        balance: "held",
+                    }),
                 }
             }
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn release(&mut self, client_id: u16, amount: Decimal) -> anyhow::Result<()> {
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
         check_positive(amount)?;
 
         match self.accounts.get_mut(&client_id) {
             Some(acc) => {
-                if acc.held_amount - amount < Decimal::ZERO {
-                    return Err(anyhow!("Held amount is too low"));
+                if acc.held_amount - amount < Amount::ZERO {
+                    return Err(AccountError::InsufficientHeld);
                 }
 
                 match acc.available_amount.checked_add(amount) {
@@ -134,49 +260,166 @@ impl Manager for SimpleManager {
                         acc.held_amount -= amount;
                         Ok(())
                     }
-                    None => Err(anyhow!(
-                        "Cannot release amount as the resulting available amount is too large"
-                    )),
+                    None => Err(AccountError::Overflow {
+                        operation: "release",
+                        balance: "available",
+                    }),
                 }
             }
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn lock(&mut self, client_id: u16) -> anyhow::Result<()> {
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
         match self.accounts.get_mut(&client_id) {
             Some(acc) => {
                 acc.is_locked = true;
                 Ok(())
             }
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
-    fn is_locked(&mut self, client_id: u16) -> anyhow::Result<bool> {
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        match self.accounts.get_mut(&client_id) {
+            Some(acc) => {
+                acc.is_locked = false;
+                Ok(())
+            }
+            None => Err(AccountError::AccountNotFound(client_id)),
+        }
+    }
+
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
         match self.accounts.get_mut(&client_id) {
             Some(acc) => Ok(acc.is_locked),
-            None => Err(anyhow!("Account for client {} not found", client_id)),
+            None => Err(AccountError::AccountNotFound(client_id)),
         }
     }
 
     fn all(&self) -> Vec<&Account> {
         self.accounts.values().collect()
     }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    fn page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        self.accounts
+            .range((start, Bound::Unbounded))
+            .map(|(_, acc)| acc)
+            .take(limit)
+            .collect()
+    }
+}
+
+/// A copy-on-write view over another manager, for trying out changes without applying them.
+///
+/// Accounts are copied from the underlying manager on their first change and changed with the
+/// rules of [`SimpleManager`], so the underlying manager is never modified.
+pub struct CowManager<'a, M: Manager> {
+    base: &'a M,
+    changed: SimpleManager,
+}
+
+impl<'a, M: Manager> CowManager<'a, M> {
+    pub fn new(base: &'a M) -> Self {
+        Self {
+            base,
+            changed: SimpleManager::new(),
+        }
+    }
+
+    fn copy(&mut self, client_id: ClientId) {
+        if self.changed.get(client_id).is_none() {
+            if let Some(acc) = self.base.account(client_id) {
+                self.changed.insert(acc.clone());
+            }
+        }
+    }
+}
+
+impl<M: Manager> Manager for CowManager<'_, M> {
+    type Error = AccountError;
+
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.ensure_account(client_id)
+    }
+
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.deposit(client_id, amount)
+    }
+
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.withdraw(client_id, amount)
+    }
+
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.withdraw_held(client_id, amount)
+    }
+
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.hold(client_id, amount)
+    }
+
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.release(client_id, amount)
+    }
+
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.lock(client_id)
+    }
+
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.copy(client_id);
+        self.changed.unlock(client_id)
+    }
+
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
+        self.account(client_id)
+            .map(|acc| acc.is_locked)
+            .ok_or(AccountError::AccountNotFound(client_id))
+    }
+
+    fn all(&self) -> Vec<&Account> {
+        let mut accounts: Vec<&Account> = self
+            .base
+            .all()
+            .into_iter()
+            .filter(|acc| self.changed.get(acc.client_id).is_none())
+            .chain(self.changed.all())
+            .collect();
+        accounts.sort_by_key(|acc| acc.client_id);
+        accounts
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.changed
+            .get(client_id)
+            .or_else(|| self.base.account(client_id))
+    }
 }
 
-fn check_positive(amount: Decimal) -> anyhow::Result<()> {
+fn check_positive(amount: Amount) -> Result<(), AccountError> {
     match amount.is_sign_positive() {
         true => Ok(()),
-        false => Err(anyhow!("The amount is not positive")),
+        false => Err(AccountError::NonPositiveAmount),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use rust_decimal_macros::dec;
-
     use super::*;
+    use crate::amount::dec;
 
     #[test]
     fn check_positive_for_positive_amount_is_ok() {
@@ -186,7 +429,8 @@ mod tests {
 
     #[test]
     fn check_positive_for_negative_amount_is_err() {
-        assert!(check_positive(dec!(-1)).is_err());
+        let err = check_positive(dec!(-1)).err().unwrap();
+        assert_eq!(err.code(), RejectCode::BadAmount);
     }
 
     #[test]
@@ -204,11 +448,12 @@ mod tests {
     fn deposit_returns_error_when_account_not_found() {
         let mut manager = SimpleManager::new();
         let result = manager.deposit(1, dec!(10.0));
-        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().code(), RejectCode::AccountNotFound);
         assert_eq!(manager.accounts.len(), 0);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn deposit_adds_to_available_amount() {
         let mut manager = SimpleManager::new();
         let client_id = 1;
@@ -235,12 +480,13 @@ mod tests {
         let client_id = 1;
 
         assert!(manager.ensure_account(client_id).is_ok());
-        assert!(manager.deposit(client_id, Decimal::MAX).is_ok());
-        assert!(manager.deposit(client_id, dec!(1.0)).is_err());
+        assert!(manager.deposit(client_id, Amount::MAX).is_ok());
+        let err = manager.deposit(client_id, dec!(1.0)).err().unwrap();
+        assert_eq!(err.code(), RejectCode::Overflow);
 
         let acc = manager.accounts.get(&1).expect("Account not found");
 
-        assert_eq!(acc.available_amount, Decimal::MAX);
+        assert_eq!(acc.available_amount, Amount::MAX);
     }
 
     #[test]
@@ -271,7 +517,8 @@ mod tests {
 
         assert!(manager.ensure_account(client_id).is_ok());
         assert!(manager.deposit(client_id, dec!(10.0)).is_ok());
-        assert!(manager.withdraw(client_id, dec!(11.0)).is_err());
+        let err = manager.withdraw(client_id, dec!(11.0)).err().unwrap();
+        assert_eq!(err.code(), RejectCode::InsufficientFunds);
 
         let acc = manager.accounts.get(&client_id).expect("Account not found");
 
@@ -313,15 +560,15 @@ mod tests {
         let client_id = 1;
 
         assert!(manager.ensure_account(client_id).is_ok());
-        assert!(manager.deposit(client_id, Decimal::MAX).is_ok());
-        assert!(manager.hold(client_id, Decimal::MAX).is_ok());
+        assert!(manager.deposit(client_id, Amount::MAX).is_ok());
+        assert!(manager.hold(client_id, Amount::MAX).is_ok());
         assert!(manager.deposit(client_id, dec!(1)).is_ok());
         assert!(manager.hold(client_id, dec!(1)).is_err());
 
         let acc = manager.accounts.get(&1).expect("Account not found");
 
         assert_eq!(acc.available_amount, dec!(1));
-        assert_eq!(acc.held_amount, Decimal::MAX);
+        assert_eq!(acc.held_amount, Amount::MAX);
     }
 
     #[test]
@@ -362,12 +609,12 @@ mod tests {
         assert!(manager.ensure_account(client_id).is_ok());
         assert!(manager.deposit(client_id, dec!(1)).is_ok());
         assert!(manager.hold(client_id, dec!(1)).is_ok());
-        assert!(manager.deposit(client_id, Decimal::MAX).is_ok());
+        assert!(manager.deposit(client_id, Amount::MAX).is_ok());
         assert!(manager.release(client_id, dec!(1)).is_err());
 
         let acc = manager.accounts.get(&1).expect("Account not found");
 
-        assert_eq!(acc.available_amount, Decimal::MAX);
+        assert_eq!(acc.available_amount, Amount::MAX);
         assert_eq!(acc.held_amount, dec!(1));
     }
 
@@ -429,6 +676,62 @@ mod tests {
         assert!(acc.is_locked);
     }
 
+    #[test]
+    fn unlock_unlocks_account() {
+        let mut manager = SimpleManager::new();
+        let client_id = 1;
+        assert!(manager.ensure_account(client_id).is_ok());
+        assert!(manager.lock(client_id).is_ok());
+
+        let result = manager.unlock(client_id);
+        assert!(result.is_ok());
+
+        let acc = manager.accounts.get(&client_id).unwrap();
+        assert!(!acc.is_locked);
+    }
+
+    #[test]
+    fn page_returns_accounts_after_cursor() {
+        let mut manager = SimpleManager::new();
+        for client_id in [3, 1, 2, 5] {
+            manager.ensure_account(client_id).unwrap();
+        }
+
+        let ids = |page: Vec<&Account>| page.iter().map(|acc| acc.client_id).collect::<Vec<_>>();
+        assert_eq!(ids(manager.page(None, 2)), vec![1, 2]);
+        assert_eq!(ids(manager.page(Some(2), 2)), vec![3, 5]);
+        assert_eq!(ids(manager.page(Some(5), 2)), Vec::<ClientId>::new());
+    }
+
+    #[test]
+    fn filter_returns_accounts_matching_all_conditions() {
+        let mut manager = SimpleManager::new();
+        for client_id in 1..=3 {
+            manager.ensure_account(client_id).unwrap();
+            manager.deposit(client_id, dec!(10.0)).unwrap();
+        }
+        manager.hold(1, dec!(6.0)).unwrap();
+        manager.hold(2, dec!(6.0)).unwrap();
+        manager.lock(2).unwrap();
+
+        let ids = |filter: AccountFilter| {
+            manager
+                .filter(&filter)
+                .iter()
+                .map(|acc| acc.client_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(AccountFilter::new()), vec![1, 2, 3]);
+        assert_eq!(ids(AccountFilter::new().locked(true)), vec![2]);
+        assert_eq!(ids(AccountFilter::new().held_above(dec!(5.0))), vec![1, 2]);
+        assert_eq!(
+            ids(AccountFilter::new()
+                .available_below(dec!(5.0))
+                .locked(false)),
+            vec![1]
+        );
+    }
+
     #[test]
     fn is_locked_returns_error_when_account_not_found() {
         let mut manager = SimpleManager::new();
@@ -436,6 +739,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn is_locked_returns_false_when_account_is_not_locked() {
         let mut manager = SimpleManager::new();
         let client_id = 1;
@@ -449,6 +753,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn is_locked_returns_true_when_account_is_locked() {
         let mut manager = SimpleManager::new();
         let client_id = 1;
@@ -461,4 +766,21 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), true);
     }
+
+    #[test]
+    fn cow_manager_leaves_the_underlying_manager_unchanged() {
+        let mut base = SimpleManager::new();
+        base.ensure_account(1).unwrap();
+        base.deposit(1, dec!(10)).unwrap();
+
+        let mut view = CowManager::new(&base);
+        view.withdraw(1, dec!(4)).unwrap();
+        view.ensure_account(2).unwrap();
+
+        assert_eq!(view.account(1).unwrap().available_amount, dec!(6));
+        assert_eq!(view.all().len(), 2);
+        assert!(view.withdraw(1, dec!(7)).is_err());
+        assert_eq!(base.account(1).unwrap().available_amount, dec!(10));
+        assert!(base.account(2).is_none());
+    }
 }
@@ -0,0 +1,509 @@
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// Monetary amount type used throughout the engine.
+///
+/// `Decimal` by default. Build with `--features amount-minor-units` to use [`MinorUnits`], a
+/// faster fixed-point representation with exactly 4 decimal places.
+#[cfg(not(feature = "amount-minor-units"))]
+pub type Amount = Decimal;
+#[cfg(feature = "amount-minor-units")]
+pub type Amount = MinorUnits;
+
+/// Operations the engine needs from an amount type, beyond the `Decimal`-compatible constants
+/// and methods (`ZERO`, `MAX`, `new`, `checked_add`, `round_dp`, `is_sign_positive`) it also
+/// provides.
+pub trait AmountArithmetic:
+    Copy
+    + Default
+    + Ord
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + AddAssign
+    + SubAssign
+    + Send
+    + Sync
+    + 'static
+{
+    /// Decimal places every amount is held with, or `None` if amounts keep their own scale.
+    const SCALE: Option<u32>;
+
+    /// Fixed-size encoding for storing amounts outside the process.
+    fn to_bytes(self) -> [u8; 16];
+
+    fn from_bytes(bytes: [u8; 16]) -> Self;
+
+    /// The same amount in a single canonical form, so amounts written differently, such as `1.50`
+    /// and `1.5`, are identical in every respect including their text.
+    fn canonical(self) -> Self;
+
+    /// Rounds to `dp` decimal places with `rounding`. Use [`Rounding::round`] instead.
+    fn round_with(self, dp: u32, rounding: Rounding) -> Self;
+}
+
+/// How amounts are rounded to fewer decimal places.
+///
+/// Jurisdictions mandate different rules, so every rounding of a computed or written amount, such
+/// as reward payouts and the accounts output, goes through [`Rounding::round`] with the strategy
+/// the run was configured with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Half to even, also known as banker's rounding.
+    #[default]
+    HalfEven,
+    /// Half away from zero.
+    HalfUp,
+    /// Toward zero, dropping the extra decimal places.
+    Truncate,
+}
+
+impl Rounding {
+    pub fn round(self, amount: Amount, dp: u32) -> Amount {
+        amount.round_with(dp, self)
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-even" | "bankers" => Ok(Rounding::HalfEven),
+            "half-up" => Ok(Rounding::HalfUp),
+            "truncate" => Ok(Rounding::Truncate),
+            _ => Err(ParseAmountError("Unknown rounding strategy")),
+        }
+    }
+}
+
+impl AmountArithmetic for Decimal {
+    const SCALE: Option<u32> = None;
+
+    fn to_bytes(self) -> [u8; 16] {
+        self.serialize()
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        Decimal::deserialize(bytes)
+    }
+
+    fn canonical(self) -> Self {
+        self.normalize()
+    }
+
+    fn round_with(self, dp: u32, rounding: Rounding) -> Self {
+        let strategy = match rounding {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::Truncate => RoundingStrategy::ToZero,
+        };
+        self.round_dp_with_strategy(dp, strategy)
+    }
+}
+
+const SCALE: u32 = 4;
+const UNIT: i128 = 10_i128.pow(SCALE);
+
+/// A fixed-point amount held as an `i128` count of 1/10,000ths.
+///
+/// Parsing rejects amounts with more than 4 significant decimal places rather than rounding them.
+/// Arithmetic panics on overflow, like `Decimal`; use [`MinorUnits::checked_add`] where overflow
+/// is possible.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MinorUnits(i128);
+
+impl MinorUnits {
+    pub const ZERO: MinorUnits = MinorUnits(0);
+    pub const ONE: MinorUnits = MinorUnits(UNIT);
+    pub const MAX: MinorUnits = MinorUnits(i128::MAX);
+    pub const MIN: MinorUnits = MinorUnits(i128::MIN);
+
+    /// Creates `num * 10^-scale`, rounding half to even beyond 4 decimal places.
+    pub fn new(num: i64, scale: u32) -> Self {
+        match scale <= SCALE {
+            true => MinorUnits(num as i128 * 10_i128.pow(SCALE - scale)),
+            false => MinorUnits(div_round(
+                num as i128,
+                10_i128.pow(scale - SCALE),
+                Rounding::HalfEven,
+            )),
+        }
+    }
+
+    pub const fn from_units(units: i128) -> Self {
+        MinorUnits(units)
+    }
+
+    pub const fn units(self) -> i128 {
+        self.0
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(MinorUnits)
+    }
+
+    /// Rounds half to even to `dp` decimal places.
+    pub fn round_dp(self, dp: u32) -> Self {
+        self.round_with(dp, Rounding::HalfEven)
+    }
+
+    pub fn is_sign_positive(self) -> bool {
+        self.0 >= 0
+    }
+}
+
+fn div_round(n: i128, d: i128, rounding: Rounding) -> i128 {
+    let quotient = n / d;
+    let twice_remainder = (n % d).abs() * 2;
+    let away_from_zero = match rounding {
+        Rounding::HalfEven => twice_remainder > d || (twice_remainder == d && quotient % 2 != 0),
+        Rounding::HalfUp => twice_remainder >= d,
+        Rounding::Truncate => false,
+    };
+    match away_from_zero {
+        true => quotient + n.signum(),
+        false => quotient,
+    }
+}
+
+impl AmountArithmetic for MinorUnits {
+    const SCALE: Option<u32> = Some(SCALE);
+
+    fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        MinorUnits(i128::from_be_bytes(bytes))
+    }
+
+    /// Minor units have a fixed scale, so are always canonical.
+    fn canonical(self) -> Self {
+        self
+    }
+
+    fn round_with(self, dp: u32, rounding: Rounding) -> Self {
+        match dp >= SCALE {
+            true => self,
+            false => {
+                let step = 10_i128.pow(SCALE - dp);
+                MinorUnits(div_round(self.0, step, rounding).saturating_mul(step))
+            }
+        }
+    }
+}
+
+impl Add for MinorUnits {
+    type Output = MinorUnits;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        MinorUnits(self.0 + rhs.0)
+    }
+}
+
+impl Sub for MinorUnits {
+    type Output = MinorUnits;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        MinorUnits(self.0 - rhs.0)
+    }
+}
+
+/// Rounds the product half to even to 4 decimal places.
+impl Mul for MinorUnits {
+    type Output = MinorUnits;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        MinorUnits(div_round(self.0 * rhs.0, UNIT, Rounding::HalfEven))
+    }
+}
+
+impl AddAssign for MinorUnits {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for MinorUnits {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for MinorUnits {
+    type Output = MinorUnits;
+
+    fn neg(self) -> Self::Output {
+        MinorUnits(-self.0)
+    }
+}
+
+/// Writes the amount without trailing zeros, or with exactly the requested precision (e.g.
+/// `{:.2}`), rounding half to even.
+impl fmt::Display for MinorUnits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = match f.precision() {
+            Some(dp) => self.round_dp(dp.min(SCALE as usize) as u32).0,
+            None => self.0,
+        };
+
+        if units < 0 {
+            f.write_str("-")?;
+        }
+        let integer = units.unsigned_abs() / UNIT as u128;
+        let fraction = units.unsigned_abs() % UNIT as u128;
+        write!(f, "{}", integer)?;
+
+        let digits = match f.precision() {
+            Some(dp) => dp,
+            None if fraction == 0 => 0,
+            None => {
+                let mut digits = SCALE as usize;
+                while fraction.is_multiple_of(10_u128.pow(SCALE - digits as u32 + 1)) {
+                    digits -= 1;
+                }
+                digits
+            }
+        };
+
+        if digits > 0 {
+            f.write_str(".")?;
+            for position in 0..digits {
+                let digit = match position < SCALE as usize {
+                    true => fraction / 10_u128.pow(SCALE - 1 - position as u32) % 10,
+                    false => 0,
+                };
+                write!(f, "{}", digit)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseAmountError(&'static str);
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl core::error::Error for ParseAmountError {}
+
+impl FromStr for MinorUnits {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(ParseAmountError("Invalid amount"));
+        }
+
+        if fraction.len() > SCALE as usize
+            && fraction.bytes().skip(SCALE as usize).any(|b| b != b'0')
+        {
+            return Err(ParseAmountError(
+                "Amount has more than 4 significant decimal places",
+            ));
+        }
+
+        let mut units: i128 = 0;
+        let fraction_digits = fraction.bytes().chain(core::iter::repeat(b'0'));
+        for digit in integer.bytes().chain(fraction_digits.take(SCALE as usize)) {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add((digit - b'0') as i128))
+                .ok_or(ParseAmountError("Amount is too large"))?;
+        }
+
+        Ok(MinorUnits(if negative { -units } else { units }))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for MinorUnits {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MinorUnits {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = MinorUnits;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal amount with at most 4 decimal places")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<MinorUnits, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<MinorUnits, E> {
+                Ok(MinorUnits::new(v, 0))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<MinorUnits, E> {
+                Ok(MinorUnits(v as i128 * UNIT))
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Builds an [`Amount`] from a decimal literal in tests, whichever amount type is selected.
+#[cfg(all(test, not(feature = "amount-minor-units")))]
+pub(crate) use rust_decimal_macros::dec;
+
+#[cfg(all(test, feature = "amount-minor-units"))]
+macro_rules! dec {
+    ($($amount:tt)+) => {
+        alloc::string::ToString::to_string(stringify!($($amount)+))
+            .replace(' ', "")
+            .parse::<$crate::amount::MinorUnits>()
+            .unwrap()
+    };
+}
+#[cfg(all(test, feature = "amount-minor-units"))]
+pub(crate) use dec;
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, string::ToString};
+
+    use super::*;
+
+    fn units(s: &str) -> i128 {
+        s.parse::<MinorUnits>().unwrap().units()
+    }
+
+    #[test]
+    fn parses_up_to_four_decimal_places() {
+        assert_eq!(units("1"), 10_000);
+        assert_eq!(units("1.5"), 15_000);
+        assert_eq!(units("-0.0001"), -1);
+        assert_eq!(units("+.25"), 2_500);
+        assert_eq!(units("2.123400"), 21_234);
+
+        assert!("1.23456".parse::<MinorUnits>().is_err());
+        assert!("1.2.3".parse::<MinorUnits>().is_err());
+        assert!("".parse::<MinorUnits>().is_err());
+        assert!("-".parse::<MinorUnits>().is_err());
+    }
+
+    #[test]
+    fn displays_without_trailing_zeros() {
+        assert_eq!(MinorUnits::from_units(15_000).to_string(), "1.5");
+        assert_eq!(MinorUnits::from_units(20_000).to_string(), "2");
+        assert_eq!(MinorUnits::from_units(-1).to_string(), "-0.0001");
+        assert_eq!(MinorUnits::from_units(12_340).to_string(), "1.234");
+    }
+
+    #[test]
+    fn displays_with_precision() {
+        assert_eq!(format!("{:.2}", MinorUnits::from_units(12_345)), "1.23");
+        assert_eq!(format!("{:.2}", MinorUnits::from_units(12_350)), "1.24");
+        assert_eq!(format!("{:.6}", MinorUnits::from_units(12_345)), "1.234500");
+        assert_eq!(format!("{:.0}", MinorUnits::from_units(25_000)), "2");
+    }
+
+    #[test]
+    fn rounds_half_to_even() {
+        assert_eq!(MinorUnits::from_units(12_250).round_dp(2).units(), 12_200);
+        assert_eq!(MinorUnits::from_units(12_350).round_dp(2).units(), 12_400);
+        assert_eq!(MinorUnits::from_units(-12_351).round_dp(2).units(), -12_400);
+        assert_eq!(MinorUnits::new(123_455, 5).units(), 12_346);
+        assert_eq!(MinorUnits::new(-5, 1).units(), -5_000);
+    }
+
+    #[test]
+    fn rounds_with_each_strategy() {
+        let cases = [
+            (Rounding::HalfEven, [12_200, 12_300, -12_200]),
+            (Rounding::HalfUp, [12_300, 12_300, -12_300]),
+            (Rounding::Truncate, [12_200, 12_200, -12_200]),
+        ];
+        for (rounding, expected) in cases {
+            let rounded = [12_250, 12_251, -12_250].map(|units| {
+                MinorUnits::from_units(units)
+                    .round_with(2, rounding)
+                    .units()
+            });
+            assert_eq!(rounded, expected, "{:?}", rounding);
+
+            let rounded = [12_250, 12_251, -12_250]
+                .map(|units| Decimal::new(units, 4).round_with(2, rounding));
+            assert_eq!(
+                rounded,
+                expected.map(|units| Decimal::new(units as i64, 4)),
+                "{:?}",
+                rounding
+            );
+        }
+    }
+
+    #[test]
+    fn multiplies_with_rounding() {
+        assert_eq!(
+            (MinorUnits::from_units(25_000) * MinorUnits::from_units(100)).units(),
+            250
+        );
+        assert_eq!(
+            (MinorUnits::from_units(12_345) * MinorUnits::from_units(5_000)).units(),
+            6_172
+        );
+    }
+
+    #[test]
+    fn checked_add_detects_overflow() {
+        assert_eq!(MinorUnits::MAX.checked_add(MinorUnits::from_units(1)), None);
+        assert_eq!(
+            MinorUnits::ONE.checked_add(MinorUnits::ONE),
+            Some(MinorUnits::from_units(20_000))
+        );
+    }
+
+    #[test]
+    fn canonical_strips_trailing_zeros() {
+        let amount = Decimal::new(15_000, 4).canonical();
+        assert_eq!(amount.to_string(), "1.5");
+        assert_eq!(amount.to_bytes(), Decimal::new(15, 1).to_bytes());
+        assert_eq!(Decimal::new(0, 2).canonical().to_string(), "0");
+        assert_eq!(MinorUnits::ONE.canonical(), MinorUnits::ONE);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let amount = MinorUnits::from_units(-123_456);
+        assert_eq!(MinorUnits::from_bytes(amount.to_bytes()), amount);
+
+        let amount = Decimal::new(-123_456, 4);
+        assert_eq!(Decimal::from_bytes(amount.to_bytes()), amount);
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+
+use log::warn;
+
+use crate::{
+    amount::Amount,
+    types::{ClientId, Transaction, TxId, TxType},
+};
+
+#[derive(Debug, PartialEq)]
+pub struct SuspectedDuplicate {
+    pub row: usize,
+    pub client_id: ClientId,
+    pub tx_id: TxId,
+    pub amount: Amount,
+    pub occurrences: usize,
+}
+
+/// Flags deposits that repeat the same amount for the same client at least `threshold` times
+/// within `window` transactions of each other.
+///
+/// Flagged deposits are only reported unless `strict` is set, in which case they should be
+/// rejected.
+pub struct DuplicateDetector {
+    threshold: usize,
+    window: usize,
+    strict: bool,
+    row: usize,
+    recent: HashMap<(ClientId, Amount), VecDeque<usize>>,
+    suspects: Vec<SuspectedDuplicate>,
+}
+
+impl DuplicateDetector {
+    pub fn new(threshold: usize, window: usize, strict: bool) -> Self {
+        Self {
+            threshold,
+            window,
+            strict,
+            row: 0,
+            recent: HashMap::new(),
+            suspects: Vec::new(),
+        }
+    }
+
+    /// Records the transaction and returns `false` if it should be blocked.
+    pub fn check(&mut self, tx: &Transaction) -> bool {
+        self.row += 1;
+
+        if self.window > 0 && self.row.is_multiple_of(self.window) {
+            self.prune();
+        }
+
+        if tx.tx_type != TxType::Deposit {
+            return true;
+        }
+
+        let row = self.row;
+        let window = self.window;
+        let rows = self.recent.entry((tx.client_id, tx.amount)).or_default();
+
+        while rows.front().is_some_and(|first| row - first > window) {
+            rows.pop_front();
+        }
+
+        rows.push_back(row);
+
+        if rows.len() < self.threshold {
+            return true;
+        }
+
+        warn!(
+            "[{}] Suspected duplicate deposit {} of {} for client id {} ({} identical deposits within {} transactions)",
+            tx.trace_id(),
+            tx.tx_id,
+            tx.amount,
+            tx.client_id,
+            rows.len(),
+            window
+        );
+
+        self.suspects.push(SuspectedDuplicate {
+            row,
+            client_id: tx.client_id,
+            tx_id: tx.tx_id,
+            amount: tx.amount,
+            occurrences: rows.len(),
+        });
+
+        !self.strict
+    }
+
+    /// Whether [`DuplicateDetector::check`] would block the transaction, without recording it.
+    pub fn would_block(&self, tx: &Transaction) -> bool {
+        if !self.strict || tx.tx_type != TxType::Deposit {
+            return false;
+        }
+
+        let row = self.row + 1;
+        let recent = self
+            .recent
+            .get(&(tx.client_id, tx.amount))
+            .map_or(0, |rows| {
+                rows.iter()
+                    .filter(|&&first| row - first <= self.window)
+                    .count()
+            });
+        recent + 1 >= self.threshold
+    }
+
+    pub fn suspects(&self) -> &[SuspectedDuplicate] {
+        &self.suspects
+    }
+
+    fn prune(&mut self) {
+        let row = self.row;
+        let window = self.window;
+        self.recent
+            .retain(|_, rows| rows.back().is_some_and(|last| row - last <= window));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    #[test]
+    fn flags_identical_deposits_within_window() {
+        let mut detector = DuplicateDetector::new(3, 10, false);
+
+        assert!(detector.check(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0))));
+        assert!(detector.check(&Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))));
+        assert!(detector.check(&Transaction::new(TxType::Deposit, 1, 3, dec!(5.0))));
+
+        assert_eq!(
+            detector.suspects(),
+            &[SuspectedDuplicate {
+                row: 3,
+                client_id: 1,
+                tx_id: 3,
+                amount: dec!(5.0),
+                occurrences: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn ignores_other_clients_amounts_and_types() {
+        let mut detector = DuplicateDetector::new(2, 10, false);
+
+        detector.check(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 2, 2, dec!(5.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 1, 3, dec!(6.0)));
+        detector.check(&Transaction::new(TxType::Withdrawal, 1, 4, dec!(5.0)));
+
+        assert!(detector.suspects().is_empty());
+    }
+
+    #[test]
+    fn ignores_deposits_outside_window() {
+        let mut detector = DuplicateDetector::new(2, 2, false);
+
+        detector.check(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 2, 2, dec!(1.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 2, 3, dec!(2.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 1, 4, dec!(5.0)));
+
+        assert!(detector.suspects().is_empty());
+    }
+
+    #[test]
+    fn strict_mode_blocks_flagged_deposits() {
+        let mut detector = DuplicateDetector::new(2, 10, true);
+
+        assert!(detector.check(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0))));
+        assert!(!detector.check(&Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))));
+        assert_eq!(detector.suspects().len(), 1);
+    }
+
+    #[test]
+    fn would_block_does_not_record_the_transaction() {
+        let mut detector = DuplicateDetector::new(2, 2, true);
+        let repeat = Transaction::new(TxType::Deposit, 1, 3, dec!(5.0));
+
+        detector.check(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)));
+        assert!(detector.would_block(&repeat));
+        assert!(detector.would_block(&repeat));
+        assert!(!detector.would_block(&Transaction::new(TxType::Deposit, 1, 3, dec!(6.0))));
+
+        detector.check(&Transaction::new(TxType::Deposit, 2, 2, dec!(1.0)));
+        detector.check(&Transaction::new(TxType::Deposit, 2, 3, dec!(2.0)));
+        assert!(!detector.would_block(&repeat));
+        assert!(detector.suspects().is_empty());
+    }
+}
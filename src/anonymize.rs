@@ -0,0 +1,90 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::types::ClientId;
+
+/// Feistel rounds used to permute client ids. Four rounds make the permutation indistinguishable
+/// from random to anyone without the key.
+const ROUNDS: u8 = 4;
+const HALF_BITS: u32 = ClientId::BITS / 2;
+const HALF_MASK: u64 = (1 << HALF_BITS) - 1;
+
+/// Replaces client ids with pseudonyms derived from a secret key.
+///
+/// The same key always gives the same pseudonym for a client, and no two clients share one, since
+/// ids are permuted with a Feistel network keyed with HMAC-SHA256 rather than hashed.
+#[derive(Clone)]
+pub struct Anonymizer {
+    mac: Hmac<Sha256>,
+}
+
+impl Anonymizer {
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        if key.is_empty() {
+            return Err(anyhow!("The anonymization key is empty"));
+        }
+        let mac = Hmac::new_from_slice(key).map_err(|err| anyhow!(err.to_string()))?;
+        Ok(Self { mac })
+    }
+
+    /// Loads the key from a file, ignoring surrounding whitespace.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new(fs::read_to_string(path)?.trim().as_bytes())
+    }
+
+    pub fn pseudonym(&self, client_id: ClientId) -> ClientId {
+        // ClientId is already u64 with the client-id-u64 feature.
+        #[allow(clippy::unnecessary_cast)]
+        let id = client_id as u64;
+        let (mut left, mut right) = (id >> HALF_BITS, id & HALF_MASK);
+        for round in 0..ROUNDS {
+            (left, right) = (right, left ^ self.round(round, right));
+        }
+        ((left << HALF_BITS) | right) as ClientId
+    }
+
+    fn round(&self, round: u8, half: u64) -> u64 {
+        let mut mac = self.mac.clone();
+        mac.update(&[round]);
+        mac.update(&half.to_le_bytes());
+        let digest = mac.finalize().into_bytes();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(bytes) & HALF_MASK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn pseudonyms_are_deterministic_per_key() {
+        let anonymizer = Anonymizer::new(b"secret").unwrap();
+
+        assert_eq!(anonymizer.pseudonym(7), anonymizer.pseudonym(7));
+        assert_ne!(
+            anonymizer.pseudonym(7),
+            Anonymizer::new(b"other").unwrap().pseudonym(7)
+        );
+    }
+
+    #[test]
+    fn distinct_clients_get_distinct_pseudonyms() {
+        let anonymizer = Anonymizer::new(b"secret").unwrap();
+
+        let pseudonyms: HashSet<_> = (0..5000).map(|id| anonymizer.pseudonym(id)).collect();
+
+        assert_eq!(pseudonyms.len(), 5000);
+    }
+
+    #[test]
+    fn rejects_empty_keys() {
+        assert!(Anonymizer::new(b"").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    account::SimpleManager,
+    amount::Amount,
+    engine::Engine,
+    types::{ClientId, Transaction, TxId, TxType},
+};
+
+const CLIENTS: u64 = 1_000;
+
+/// Rows per client block: 12 deposits, 6 withdrawals, then a dispute and resolve of the first
+/// deposit.
+const BLOCK: u64 = 20;
+
+#[derive(Debug)]
+pub struct BenchReport {
+    pub rows: usize,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn rows_per_sec(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Lazily generates `rows` valid transactions without any I/O or parsing.
+///
+/// Generating a row only takes a few integer operations, so the time spent processing them is
+/// almost entirely engine time.
+pub fn synthetic(rows: usize) -> impl Iterator<Item = anyhow::Result<Transaction>> {
+    (0..rows as u64).map(|row| {
+        let block_start = row - row % BLOCK;
+        let client_id = ((row / BLOCK) % CLIENTS + 1) as ClientId;
+        let tx_id = (row + 1) as TxId;
+
+        let tx = match row % BLOCK {
+            0..=11 => Transaction::new(TxType::Deposit, client_id, tx_id, Amount::ONE),
+            12..=17 => Transaction::new(TxType::Withdrawal, client_id, tx_id, Amount::new(5, 1)),
+            18 => Transaction::new(TxType::Dispute, client_id, block_start + 1, Amount::ZERO),
+            _ => Transaction::new(TxType::Resolve, client_id, block_start + 1, Amount::ZERO),
+        };
+
+        Ok(tx)
+    })
+}
+
+/// Processes `rows` synthetic transactions and measures the engine's throughput.
+///
+/// Without dispute tracking the disputes and resolves are ignored, measuring the fast path for
+/// deposit and withdrawal only runs.
+pub fn bench_run(rows: usize, max_stored_txs: Option<usize>, track_disputes: bool) -> BenchReport {
+    let mut builder = Engine::builder(SimpleManager::new());
+    if let Some(max_stored_txs) = max_stored_txs {
+        builder = builder.max_stored_transactions(max_stored_txs);
+    }
+    if !track_disputes {
+        builder = builder.disputable_types(Vec::new());
+    }
+    let mut engine = builder.build();
+
+    let start = Instant::now();
+    engine.process_all(synthetic(rows));
+
+    BenchReport {
+        rows,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::dec, engine::TxOutcome};
+
+    #[test]
+    fn synthetic_transactions_are_all_applied() {
+        let mut engine = Engine::new(SimpleManager::new());
+
+        let outcomes: Vec<_> = synthetic(100)
+            .map(|tx| engine.process(&tx.unwrap()))
+            .collect();
+
+        assert!(outcomes
+            .iter()
+            .all(|outcome| *outcome == TxOutcome::Applied));
+
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts.len(), 5);
+        assert_eq!(accounts[0].available_amount, dec!(9.0));
+        assert_eq!(accounts[0].held_amount, dec!(0));
+    }
+
+    #[test]
+    fn bench_run_without_disputes_processes_all_rows() {
+        assert_eq!(bench_run(100, None, false).rows, 100);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{fs, mem, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{
+    amount::Amount,
+    types::{ClientId, StoredTx, Transaction, TxId},
+};
+
+/// Approximate bytes used by each transaction held in memory by the transaction index: the hash
+/// map entry at its maximum load factor plus its place in the eviction order.
+pub const STORED_TX_BYTES: usize =
+    (mem::size_of::<TxId>() + mem::size_of::<StoredTx>() + 1) * 8 / 7 + mem::size_of::<TxId>();
+
+/// Approximate bytes used by each row of the duplicate detector's window, assuming every row has a
+/// distinct client and amount.
+pub const DUPLICATE_WINDOW_ROW_BYTES: usize =
+    (mem::size_of::<(ClientId, Amount)>() + 4 * mem::size_of::<usize>() + 1) * 8 / 7
+        + mem::size_of::<usize>();
+
+/// A memory limit requested by the operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryLimit {
+    /// Use the memory limit of the container the process runs in.
+    Auto,
+    Bytes(u64),
+}
+
+/// Parses `auto` or a size such as `4GiB`, `512MB` or `1048576`.
+impl FromStr for MemoryLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(MemoryLimit::Auto);
+        }
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let multiplier: u64 = match unit.trim() {
+            "" | "B" => 1,
+            "KB" => 1000,
+            "MB" => 1000_u64.pow(2),
+            "GB" => 1000_u64.pow(3),
+            "TB" => 1000_u64.pow(4),
+            "KiB" => 1 << 10,
+            "MiB" => 1 << 20,
+            "GiB" => 1 << 30,
+            "TiB" => 1 << 40,
+            _ => return Err(anyhow!("Unknown memory unit in {}", s)),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .map(MemoryLimit::Bytes)
+            .ok_or_else(|| anyhow!("Invalid memory size {}", s))
+    }
+}
+
+/// Splits a memory budget between the parts of a run whose size can be bounded.
+///
+/// Accounts and open disputes are not bounded, so the shares leave a quarter of the budget for
+/// them and for the rest of the process.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryBudget {
+    pub bytes: u64,
+}
+
+impl MemoryBudget {
+    /// Resolves a requested limit, capping it to the container memory limit if there is one.
+    pub fn resolve(limit: MemoryLimit) -> anyhow::Result<Self> {
+        Self::resolve_with(limit, cgroup_memory_limit())
+    }
+
+    fn resolve_with(limit: MemoryLimit, cgroup_limit: Option<u64>) -> anyhow::Result<Self> {
+        let bytes = match (limit, cgroup_limit) {
+            (MemoryLimit::Auto, None) => {
+                return Err(anyhow!(
+                    "No container memory limit found for --max-memory auto"
+                ))
+            }
+            (MemoryLimit::Auto, Some(cgroup_limit)) => cgroup_limit,
+            (MemoryLimit::Bytes(bytes), cgroup_limit) => {
+                bytes.min(cgroup_limit.unwrap_or(u64::MAX))
+            }
+        };
+        Ok(Self { bytes })
+    }
+
+    /// Transactions the transaction index can keep in memory, using 5/8 of the budget.
+    pub fn max_stored_txs(&self) -> usize {
+        self.share(5, 8, STORED_TX_BYTES)
+    }
+
+    /// Rows the duplicate detector can remember, using 1/16 of the budget.
+    pub fn duplicate_window(&self) -> usize {
+        self.share(1, 16, DUPLICATE_WINDOW_ROW_BYTES)
+    }
+
+    /// Transactions an [`crate::handle::EngineHandle`] can queue, using 1/16 of the budget.
+    pub fn queue_capacity(&self) -> usize {
+        self.share(1, 16, mem::size_of::<Transaction>())
+    }
+
+    fn share(&self, numerator: u64, denominator: u64, item_bytes: usize) -> usize {
+        let bytes = self.bytes / denominator * numerator;
+        usize::try_from(bytes / item_bytes as u64)
+            .unwrap_or(usize::MAX)
+            .max(1)
+    }
+}
+
+/// Reads the memory limit of the cgroup the process runs in, for cgroup v2 or v1 hierarchies.
+pub fn cgroup_memory_limit() -> Option<u64> {
+    let read = |path| fs::read_to_string(path).ok();
+
+    if let Some(max) = read("/sys/fs/cgroup/memory.max") {
+        // "max" means unlimited.
+        return max.trim().parse().ok();
+    }
+
+    // cgroup v1 reports a value close to i64::MAX when unlimited.
+    read("/sys/fs/cgroup/memory/memory.limit_in_bytes")
+        .and_then(|limit| limit.trim().parse().ok())
+        .filter(|limit| *limit < 1 << 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_memory_limits() {
+        let bytes = |s: &str| s.parse::<MemoryLimit>().unwrap();
+
+        assert_eq!(bytes("4GiB"), MemoryLimit::Bytes(4 << 30));
+        assert_eq!(bytes("512MB"), MemoryLimit::Bytes(512_000_000));
+        assert_eq!(bytes("1024"), MemoryLimit::Bytes(1024));
+        assert_eq!(bytes("auto"), MemoryLimit::Auto);
+        assert!("4 parsecs".parse::<MemoryLimit>().is_err());
+        assert!("GiB".parse::<MemoryLimit>().is_err());
+    }
+
+    #[test]
+    fn budget_is_capped_by_the_container_limit() {
+        let resolve = MemoryBudget::resolve_with;
+
+        assert_eq!(
+            resolve(MemoryLimit::Bytes(100), Some(50)).unwrap().bytes,
+            50
+        );
+        assert_eq!(resolve(MemoryLimit::Bytes(100), None).unwrap().bytes, 100);
+        assert_eq!(resolve(MemoryLimit::Auto, Some(50)).unwrap().bytes, 50);
+        assert!(resolve(MemoryLimit::Auto, None).is_err());
+    }
+
+    #[test]
+    fn shares_fit_within_the_budget() {
+        let budget = MemoryBudget { bytes: 1 << 30 };
+
+        let used = budget.max_stored_txs() * STORED_TX_BYTES
+            + budget.duplicate_window() * DUPLICATE_WINDOW_ROW_BYTES
+            + budget.queue_capacity() * mem::size_of::<Transaction>();
+        assert!(used as u64 <= budget.bytes * 3 / 4);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    account::{AccountError, Manager, SimpleManager},
+    amount::Amount,
+    types::{Account, ClientId},
+};
+
+/// Error returned by [`CachedManager`]. Both variants keep their error as the source so reject
+/// codes can still be found.
+#[derive(Debug)]
+pub enum CacheError<E> {
+    /// Rejected from the cached account, without asking the underlying manager.
+    Rejected(AccountError),
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for CacheError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Rejected(err) => err.fmt(f),
+            CacheError::Inner(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for CacheError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CacheError::Rejected(err) => Some(err),
+            CacheError::Inner(err) => Some(err),
+        }
+    }
+}
+
+/// A size-bounded, least recently used cache of accounts in front of a manager that keeps its
+/// accounts elsewhere, such as [`crate::redis_manager::RedisManager`], so hot accounts do not need
+/// a round trip for every check.
+///
+/// Writes always go through to the underlying manager before they succeed, and the cached account
+/// is replaced with the result. Existence and lock checks are answered from the cache, and changes
+/// the cached balances show would be rejected (with the rules of [`SimpleManager`]) are rejected
+/// without a round trip. Accounts changed by other instances are only seen once they are evicted
+/// or after [`CachedManager::clear`], so call it at points where other writers may have run.
+pub struct CachedManager<M: Manager> {
+    inner: M,
+    capacity: usize,
+    accounts: HashMap<ClientId, (Account, u64)>,
+    /// Cached client ids by when they were last used.
+    recency: BTreeMap<u64, ClientId>,
+    tick: u64,
+}
+
+impl<M: Manager> CachedManager<M> {
+    /// Caches up to `capacity` accounts of `inner`.
+    pub fn new(inner: M, capacity: usize) -> Self {
+        Self {
+            inner,
+            capacity: capacity.max(1),
+            accounts: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Forgets every cached account, so the next access to each reads it from the underlying
+    /// manager again.
+    pub fn clear(&mut self) {
+        self.accounts.clear();
+        self.recency.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+
+    /// Returns the cached account, marking it as most recently used.
+    fn cached(&mut self, client_id: ClientId) -> Option<&Account> {
+        let (_, last_used) = self.accounts.get(&client_id)?;
+        self.recency.remove(last_used);
+        self.tick += 1;
+        self.recency.insert(self.tick, client_id);
+
+        let (acc, last_used) = self.accounts.get_mut(&client_id)?;
+        *last_used = self.tick;
+        Some(acc)
+    }
+
+    /// Caches the underlying manager's copy of the account, evicting the least recently used
+    /// account if the cache is full.
+    fn store(&mut self, client_id: ClientId) {
+        self.forget(client_id);
+        let Some(acc) = self.inner.account(client_id).cloned() else {
+            return;
+        };
+
+        if self.accounts.len() >= self.capacity {
+            if let Some((_, evicted)) = self.recency.pop_first() {
+                self.accounts.remove(&evicted);
+            }
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, client_id);
+        self.accounts.insert(client_id, (acc, self.tick));
+    }
+
+    fn forget(&mut self, client_id: ClientId) {
+        if let Some((_, last_used)) = self.accounts.remove(&client_id) {
+            self.recency.remove(&last_used);
+        }
+    }
+
+    /// Checks `op` against the cached account, if there is one, then applies `write` to the
+    /// underlying manager and caches the result.
+    fn write(
+        &mut self,
+        client_id: ClientId,
+        op: impl Fn(&mut SimpleManager) -> Result<(), AccountError>,
+        write: impl Fn(&mut M) -> Result<(), M::Error>,
+    ) -> Result<(), CacheError<M::Error>> {
+        if let Some(acc) = self.cached(client_id) {
+            let mut scratch = SimpleManager::new();
+            scratch.insert(acc.clone());
+            op(&mut scratch).map_err(CacheError::Rejected)?;
+        }
+
+        match write(&mut self.inner) {
+            Ok(()) => {
+                self.store(client_id);
+                Ok(())
+            }
+            Err(err) => {
+                // The account may or may not have changed.
+                self.forget(client_id);
+                Err(CacheError::Inner(err))
+            }
+        }
+    }
+}
+
+impl<M: Manager> Manager for CachedManager<M> {
+    type Error = CacheError<M::Error>;
+
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        if self.cached(client_id).is_some() {
+            return Ok(());
+        }
+
+        self.inner
+            .ensure_account(client_id)
+            .map_err(CacheError::Inner)?;
+        self.store(client_id);
+        Ok(())
+    }
+
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.deposit(client_id, amount),
+            |inner| inner.deposit(client_id, amount),
+        )
+    }
+
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.withdraw(client_id, amount),
+            |inner| inner.withdraw(client_id, amount),
+        )
+    }
+
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.withdraw_held(client_id, amount),
+            |inner| inner.withdraw_held(client_id, amount),
+        )
+    }
+
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.hold(client_id, amount),
+            |inner| inner.hold(client_id, amount),
+        )
+    }
+
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.release(client_id, amount),
+            |inner| inner.release(client_id, amount),
+        )
+    }
+
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.lock(client_id),
+            |inner| inner.lock(client_id),
+        )
+    }
+
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.write(
+            client_id,
+            |accounts| accounts.unlock(client_id),
+            |inner| inner.unlock(client_id),
+        )
+    }
+
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
+        if let Some(acc) = self.cached(client_id) {
+            return Ok(acc.is_locked);
+        }
+
+        let locked = self.inner.is_locked(client_id).map_err(CacheError::Inner)?;
+        self.store(client_id);
+        Ok(locked)
+    }
+
+    fn all(&self) -> Vec<&Account> {
+        self.inner.all()
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.inner.account(client_id)
+    }
+
+    fn page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        self.inner.page(after, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    /// Counts the calls that reach the underlying manager.
+    struct Counting {
+        accounts: SimpleManager,
+        calls: usize,
+    }
+
+    impl Manager for Counting {
+        type Error = AccountError;
+
+        fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.ensure_account(client_id)
+        }
+
+        fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.deposit(client_id, amount)
+        }
+
+        fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.withdraw(client_id, amount)
+        }
+
+        fn withdraw_held(
+            &mut self,
+            client_id: ClientId,
+            amount: Amount,
+        ) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.withdraw_held(client_id, amount)
+        }
+
+        fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.hold(client_id, amount)
+        }
+
+        fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.release(client_id, amount)
+        }
+
+        fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.lock(client_id)
+        }
+
+        fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+            self.calls += 1;
+            self.accounts.unlock(client_id)
+        }
+
+        fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
+            self.calls += 1;
+            self.accounts.is_locked(client_id)
+        }
+
+        fn all(&self) -> Vec<&Account> {
+            self.accounts.all()
+        }
+
+        fn account(&self, client_id: ClientId) -> Option<&Account> {
+            self.accounts.account(client_id)
+        }
+    }
+
+    fn cached(capacity: usize) -> CachedManager<Counting> {
+        let inner = Counting {
+            accounts: SimpleManager::new(),
+            calls: 0,
+        };
+        CachedManager::new(inner, capacity)
+    }
+
+    #[test]
+    fn matches_the_underlying_manager() {
+        let mut cache = cached(2);
+        let mut expected = SimpleManager::new();
+
+        for step in 0..200u64 {
+            let client_id = (step * 7 % 5) as ClientId;
+            let amount = Amount::new((step % 13) as i64 + 1, 1);
+            let results = match step % 6 {
+                0 | 3 => (
+                    cache.ensure_account(client_id).is_ok(),
+                    expected.ensure_account(client_id).is_ok(),
+                ),
+                1 => (
+                    cache.deposit(client_id, amount).is_ok(),
+                    expected.deposit(client_id, amount).is_ok(),
+                ),
+                2 => (
+                    cache.withdraw(client_id, amount).is_ok(),
+                    expected.withdraw(client_id, amount).is_ok(),
+                ),
+                4 => (
+                    cache.hold(client_id, amount).is_ok(),
+                    expected.hold(client_id, amount).is_ok(),
+                ),
+                _ => (
+                    cache.release(client_id, amount).is_ok(),
+                    expected.release(client_id, amount).is_ok(),
+                ),
+            };
+            assert_eq!(results.0, results.1, "step {}", step);
+            assert!(cache.len() <= 2);
+        }
+
+        assert_eq!(cache.all(), expected.all());
+    }
+
+    #[test]
+    fn answers_checks_and_rejections_from_the_cache() {
+        let mut cache = cached(10);
+        cache.ensure_account(1).unwrap();
+        cache.deposit(1, dec!(5)).unwrap();
+        let calls = cache.inner.calls;
+
+        cache.ensure_account(1).unwrap();
+        assert!(!cache.is_locked(1).unwrap());
+        let err = cache.withdraw(1, dec!(6)).unwrap_err();
+        assert!(matches!(
+            err,
+            CacheError::Rejected(AccountError::InsufficientAvailable)
+        ));
+        assert_eq!(cache.inner.calls, calls);
+
+        cache.clear();
+        assert!(!cache.is_locked(1).unwrap());
+        assert_eq!(cache.inner.calls, calls + 1);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_account() {
+        let mut cache = cached(2);
+        for client_id in 1..=2 {
+            cache.ensure_account(client_id).unwrap();
+        }
+        cache.is_locked(1).unwrap();
+        cache.ensure_account(3).unwrap();
+        let calls = cache.inner.calls;
+
+        cache.is_locked(1).unwrap();
+        cache.is_locked(3).unwrap();
+        assert_eq!(cache.inner.calls, calls);
+        cache.is_locked(2).unwrap();
+        assert_eq!(cache.inner.calls, calls + 1);
+    }
+}
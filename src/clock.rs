@@ -0,0 +1,91 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Source of the current time for all time-based behaviour, so it can be controlled in tests.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+
+    /// A clock reading the same time, e.g. for an engine simulating transactions.
+    fn boxed_clone(&self) -> Box<dyn Clock>;
+}
+
+/// Reads the time from the operating system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Clock> {
+        Box::new(*self)
+    }
+}
+
+/// Clock that only moves when told to. Clones share the same time, so a test can keep a handle
+/// while the engine owns another.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Clock> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::default();
+        let handle = clock.clone();
+
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        handle.advance(Duration::from_secs(60));
+        assert_eq!(
+            clock.now(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(60)
+        );
+
+        handle.set(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+
+        let boxed = clock.boxed_clone();
+        handle.advance(Duration::from_secs(5));
+        assert_eq!(boxed.now(), SystemTime::UNIX_EPOCH + Duration::from_secs(5));
+    }
+}
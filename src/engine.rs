@@ -1,202 +1,2015 @@
-use std::collections::HashMap;
-
-use log::{error, info};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    fmt, mem,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::anyhow;
+use log::{debug, error, info};
 
 use crate::{
-    account,
-    types::{Account, Transaction, TxType},
+    access::ClientAccess,
+    account::{self, AccountError},
+    amount::{Amount, Rounding},
+    anomaly::{DuplicateDetector, SuspectedDuplicate},
+    clock::{Clock, SystemClock},
+    metrics::LatencyMetrics,
+    query::TxQuery,
+    rejection::{reject, RejectCode, Rejection},
+    store::{EvictionPolicy, TxIndex, TxStore},
+    types::{Account, ClientId, StoredTx, Transaction, TxId, TxState, TxType},
 };
 
+/// Decimal places reward payouts are rounded to, so rewards are paid in whole cents.
+const REWARD_DECIMAL_PLACES: u32 = 2;
+
 pub struct Engine<A: account::Manager> {
     accounts: A,
-    transactions: HashMap<u32, Transaction>,
+    transactions: Box<dyn TxIndex>,
+    disputable_types: Vec<TxType>,
+    clock: Box<dyn Clock>,
+    /// Currently open disputes, keyed by index id.
+    open_disputes: HashMap<TxId, OpenDispute>,
+    stats_interval: Option<usize>,
+    tx_id_collisions: TxIdCollisionPolicy,
+    source: u32,
+    /// Index ids allocated to `(source, tx id)` pairs under [`TxIdCollisionPolicy::Namespaced`].
+    namespaced_ids: HashMap<(u32, TxId), TxId>,
+    /// Index id allocated to the next new `(source, tx id)` pair, above every restored index id.
+    next_index_id: TxId,
+    latencies: Option<LatencyMetrics>,
+    unlock_on_chargeback_reversal: bool,
+    reward_rate: Option<Amount>,
+    /// Rewards accrued since the last payout, by client.
+    rewards: BTreeMap<ClientId, Amount>,
+    rounding: Rounding,
+    unknown_types: UnknownTypePolicy,
+    unknown_type_count: usize,
+    /// Why the run was aborted under [`UnknownTypePolicy::Abort`].
+    abort_reason: Option<String>,
+    client_access: Option<ClientAccess>,
+    duplicate_detector: Option<DuplicateDetector>,
+    require_open_accounts: bool,
+    create_accounts_on_disputes: bool,
+    abort_on_missing_references: bool,
+    /// Metadata of the accounts opened with an `open_account` transaction.
+    opened_accounts: HashMap<ClientId, BTreeMap<String, String>>,
+    /// The client each merged client's transactions are applied to instead.
+    merged_into: HashMap<ClientId, ClientId>,
+    processed: u64,
+    balance_history_interval: Option<u64>,
+    /// Applied transactions per client, counted while sampling balances.
+    applied_counts: HashMap<ClientId, u64>,
+    balance_history: BTreeMap<ClientId, Vec<BalanceSample>>,
+    /// Clients with an applied transaction or restored account, see [`Engine::is_empty_account`].
+    active_clients: HashSet<ClientId>,
+}
+
+/// How a deposit or withdrawal reusing the tx id of an earlier one is handled, e.g. when several
+/// files are processed by the same engine.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TxIdCollisionPolicy {
+    /// Reject the transaction as a duplicate.
+    #[default]
+    Reject,
+    /// Ignore the transaction if it repeats the earlier one for the same client and amount, so
+    /// replaying a file is harmless. Otherwise reject it.
+    Idempotent,
+    /// Scope tx ids to the source they were read from, see [`Engine::start_source`]. Ids are only
+    /// rejected as duplicates within a source.
+    Namespaced,
+}
+
+impl FromStr for TxIdCollisionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(TxIdCollisionPolicy::Reject),
+            "idempotent" => Ok(TxIdCollisionPolicy::Idempotent),
+            "namespaced" => Ok(TxIdCollisionPolicy::Namespaced),
+            _ => Err(anyhow!("Unknown tx id collision policy {}", s)),
+        }
+    }
+}
+
+impl TxIdCollisionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxIdCollisionPolicy::Reject => "reject",
+            TxIdCollisionPolicy::Idempotent => "idempotent",
+            TxIdCollisionPolicy::Namespaced => "namespaced",
+        }
+    }
+}
+
+/// How records with a transaction type the engine does not know are handled, e.g. when an
+/// upstream system starts sending newer kinds of transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnknownTypePolicy {
+    /// Reject each record and log it as an error.
+    #[default]
+    Reject,
+    /// Ignore each record, only counting it in [`EngineStats::unknown_types`].
+    Skip,
+    /// Ignore each record like [`UnknownTypePolicy::Skip`], leaving the caller to set it aside
+    /// for later reprocessing.
+    Quarantine,
+    /// Reject the record and stop processing, see [`Engine::abort_reason`].
+    Abort,
+}
+
+impl FromStr for UnknownTypePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(UnknownTypePolicy::Reject),
+            "skip" => Ok(UnknownTypePolicy::Skip),
+            "quarantine" => Ok(UnknownTypePolicy::Quarantine),
+            "abort" => Ok(UnknownTypePolicy::Abort),
+            _ => Err(anyhow!("Unknown unknown type policy {}", s)),
+        }
+    }
+}
+
+/// An operator action on an account, applied outside the normal transaction flow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminAction {
+    Lock,
+    Unlock,
+    /// Resolves an open dispute, even if the account is locked.
+    ForceResolve {
+        tx_id: TxId,
+    },
+    /// Closes an open dispute by removing the held funds without a chargeback, so the account
+    /// stays unlocked.
+    WriteOff {
+        tx_id: TxId,
+    },
+    /// Credits a positive amount to, or debits a negative amount from, the available funds.
+    /// Adjustments are not stored and cannot be disputed.
+    Adjust {
+        amount: Amount,
+    },
+    /// Moves the balances, transaction history and open disputes of the account into the
+    /// account of `target`, e.g. when duplicate customer records are consolidated. The merged
+    /// account is left empty and later transactions for it are applied to `target`.
+    MergeInto {
+        target: ClientId,
+    },
+}
+
+/// How disputes left open at the end of a run are closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisputeResolution {
+    /// Release the held funds back to the client, as for a resolve.
+    Resolve,
+    /// Remove the held funds, see [`AdminAction::WriteOff`].
+    WriteOff,
+}
+
+impl FromStr for DisputeResolution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resolve" => Ok(DisputeResolution::Resolve),
+            "write-off" => Ok(DisputeResolution::WriteOff),
+            _ => Err(anyhow!("Unknown dispute resolution {}", s)),
+        }
+    }
+}
+
+/// A dispute that has not yet been resolved, charged back or written off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenDispute {
+    pub opened: SystemTime,
+    /// Evidence such as a case id, from the dispute's `metadata` column or attached later with
+    /// [`Engine::attach_dispute_metadata`].
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// What processing a transaction would do, as reported by [`Engine::simulate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub outcome: TxOutcome,
+    /// The account afterwards, or `None` if it would not exist.
+    pub account: Option<Account>,
+}
+
+/// What a run leaves behind for the next one, so later runs can resolve or charge back
+/// transactions processed by earlier ones. See [`Engine::state`] and [`Engine::restore`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineState {
+    pub accounts: Vec<Account>,
+    /// Stored transactions by index id.
+    pub transactions: Vec<(TxId, StoredTx)>,
+    /// Open disputes by the index id of the disputed transaction.
+    pub disputes: Vec<(TxId, OpenDispute)>,
+    /// `(source, tx id, index id)` of every tx id allocated an index id under
+    /// [`TxIdCollisionPolicy::Namespaced`].
+    pub namespaced_ids: Vec<(u32, TxId, TxId)>,
+    /// Rewards accrued but not paid out yet, by client.
+    pub rewards: Vec<(ClientId, Amount)>,
+    /// Metadata of the accounts opened with an `open_account` transaction, by client.
+    pub opened_accounts: Vec<(ClientId, BTreeMap<String, String>)>,
+    /// `(source, target)` of every merged client, see [`AdminAction::MergeInto`].
+    pub merged_into: Vec<(ClientId, ClientId)>,
+    /// The policy the tx ids were stored under, which a restoring engine must share.
+    pub tx_id_collisions: TxIdCollisionPolicy,
+}
+
+/// Rewards credited by [`Engine::pay_out_rewards`].
+#[derive(Debug, Default)]
+pub struct RewardPayout {
+    /// The generated deposits, which have tx id 0 and are not stored for disputes.
+    pub paid: Vec<Transaction>,
+    /// Clients whose reward could not be credited, and stays accrued, with why.
+    pub failed: Vec<(ClientId, anyhow::Error)>,
+}
+
+/// An open dispute as listed by [`Engine::open_disputes`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisputeSummary {
+    pub client_id: ClientId,
+    pub tx_id: TxId,
+    pub amount: Amount,
+    pub age: Duration,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Balances of an account after the transaction at position `seq` in the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BalanceSample {
+    pub seq: u64,
+    pub available: Amount,
+    pub held: Amount,
+}
+
+/// Why a transaction was accepted without changing any balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IgnoreReason {
+    /// The dispute, resolve or chargeback references a transaction that is unknown or no longer
+    /// stored.
+    TxNotFound,
+    /// The deposit or withdrawal repeats an earlier one under
+    /// [`TxIdCollisionPolicy::Idempotent`].
+    DuplicateTx,
+    /// The record has an unknown transaction type and [`UnknownTypePolicy::Skip`] or
+    /// [`UnknownTypePolicy::Quarantine`] is set.
+    UnknownType,
+}
+
+impl IgnoreReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IgnoreReason::TxNotFound => "TX_NOT_FOUND",
+            IgnoreReason::DuplicateTx => "DUPLICATE_TX",
+            IgnoreReason::UnknownType => "UNKNOWN_TYPE",
+        }
+    }
+}
+
+impl fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why the engine rejected a transaction, for callers that react to specific failures.
+///
+/// Rejections by the account manager are kept as [`EngineError::Account`] when the manager's
+/// error is, or is caused by, an [`AccountError`], as with the managers of this crate.
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    #[error("Client id {0} is blocked")]
+    ClientBlocked(ClientId),
+    #[error("Deposit {0} is a suspected duplicate")]
+    SuspectedDuplicate(TxId),
+    #[error("Account for client id {0} has not been opened")]
+    AccountNotOpened(ClientId),
+    #[error("Account for client id {0} is already open")]
+    AccountAlreadyOpen(ClientId),
+    #[error("Account is locked so transaction will not be processed for client id {0}")]
+    AccountLocked(ClientId),
+    #[error("Transaction {0} has already been processed")]
+    DuplicateTx(TxId),
+    #[error("The transaction {tx_id} does not belong to client {client_id}")]
+    ClientMismatch { tx_id: TxId, client_id: ClientId },
+    /// An admin action referenced a transaction that is not stored. There is no reject code for
+    /// it, so it is reported as [`RejectCode::Unknown`].
+    #[error("Transaction {0} not found")]
+    TxNotFound(TxId),
+    #[error(transparent)]
+    Account(AccountError),
+    /// Rejected by other checks, such as dispute state transitions, with the given code.
+    #[error(transparent)]
+    Rejected(Rejection),
+    /// A failure with no reject code of its own, such as a transaction store error.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl EngineError {
+    pub fn code(&self) -> RejectCode {
+        match self {
+            EngineError::ClientBlocked(_) => RejectCode::ClientBlocked,
+            EngineError::SuspectedDuplicate(_) => RejectCode::SuspectedDuplicate,
+            EngineError::AccountNotOpened(_) => RejectCode::AccountNotFound,
+            EngineError::AccountAlreadyOpen(_) => RejectCode::InvalidTxState,
+            EngineError::AccountLocked(_) => RejectCode::AccountLocked,
+            EngineError::DuplicateTx(_) => RejectCode::DuplicateTx,
+            EngineError::ClientMismatch { .. } => RejectCode::ClientMismatch,
+            EngineError::TxNotFound(_) => RejectCode::Unknown,
+            EngineError::Account(err) => err.code(),
+            EngineError::Rejected(err) => err.code,
+            EngineError::Other(err) => RejectCode::of(err),
+        }
+    }
+}
+
+impl From<anyhow::Error> for EngineError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<EngineError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<Rejection>() {
+            Ok(err) => return EngineError::Rejected(err),
+            Err(err) => err,
+        };
+        match err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<AccountError>())
+        {
+            Some(account_err) => EngineError::Account(account_err.clone()),
+            None => EngineError::Other(err),
+        }
+    }
+}
+
+/// What happened to a processed transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxOutcome {
+    Applied,
+    Ignored { reason: IgnoreReason },
+    Rejected { code: RejectCode, message: String },
+}
+
+impl TxOutcome {
+    /// `APPLIED`, or the ignore reason or reject code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxOutcome::Applied => "APPLIED",
+            TxOutcome::Ignored { reason } => reason.as_str(),
+            TxOutcome::Rejected { code, .. } => code.as_str(),
+        }
+    }
+}
+
+/// Outcome of the record at 1-based position `row` in the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxResult {
+    pub row: usize,
+    pub tx_id: Option<TxId>,
+    pub outcome: TxOutcome,
+}
+
+/// Tally of the outcomes of processed records, as returned by [`Engine::process_all`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessingSummary {
+    pub applied: usize,
+    pub ignored: usize,
+    pub rejected: usize,
+    pub ignored_by_reason: HashMap<IgnoreReason, usize>,
+    pub rejected_by_code: HashMap<RejectCode, usize>,
+    /// 1-based rows of the records that could not be read.
+    pub corrupt_rows: Vec<usize>,
+}
+
+impl ProcessingSummary {
+    pub fn record(&mut self, result: &TxResult) {
+        match &result.outcome {
+            TxOutcome::Applied => self.applied += 1,
+            TxOutcome::Ignored { reason } => {
+                self.ignored += 1;
+                *self.ignored_by_reason.entry(*reason).or_default() += 1;
+            }
+            TxOutcome::Rejected { code, .. } => {
+                self.rejected += 1;
+                *self.rejected_by_code.entry(*code).or_default() += 1;
+                if result.tx_id.is_none() {
+                    self.corrupt_rows.push(result.row);
+                }
+            }
+        }
+    }
+}
+
+/// Acknowledgement of a processed record, with the balances of its account afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    pub result: TxResult,
+    /// `None` if the record could not be read or its account does not exist.
+    pub available: Option<Amount>,
+    pub held: Option<Amount>,
+}
+
+/// Snapshot of the engine's size, for sizing machines to a workload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineStats {
+    pub accounts: usize,
+    pub stored_transactions: usize,
+    pub open_disputes: usize,
+    /// Records with an unknown transaction type, whatever the [`UnknownTypePolicy`].
+    pub unknown_types: usize,
+    /// Approximate memory used by accounts and the transaction index.
+    pub memory_bytes: usize,
+}
+
+impl fmt::Display for EngineStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} accounts, {} stored transactions, {} open disputes, {} unknown types, ~{} KiB",
+            self.accounts,
+            self.stored_transactions,
+            self.open_disputes,
+            self.unknown_types,
+            self.memory_bytes / 1024
+        )
+    }
+}
+
+pub struct EngineBuilder<A: account::Manager> {
+    accounts: A,
+    max_stored_transactions: Option<usize>,
+    eviction_policy: EvictionPolicy,
+    tx_index: Option<Box<dyn TxIndex>>,
+    disputable_types: Vec<TxType>,
+    clock: Box<dyn Clock>,
+    stats_interval: Option<usize>,
+    tx_id_collisions: TxIdCollisionPolicy,
+    record_latencies: bool,
+    unlock_on_chargeback_reversal: bool,
+    reward_rate: Option<Amount>,
+    rounding: Rounding,
+    unknown_types: UnknownTypePolicy,
+    client_access: Option<ClientAccess>,
+    duplicate_detector: Option<DuplicateDetector>,
+    require_open_accounts: bool,
+    create_accounts_on_disputes: bool,
+    abort_on_missing_references: bool,
+    balance_history_interval: Option<u64>,
+}
+
+impl<A: account::Manager> EngineBuilder<A> {
+    /// Caps the number of transactions kept in memory for dispute lookups.
+    pub fn max_stored_transactions(mut self, max: usize) -> Self {
+        self.max_stored_transactions = Some(max);
+        self
+    }
+
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.eviction_policy = policy;
+        self
+    }
+
+    /// Replaces the in-memory transaction index, e.g. with an on-disk index for inputs that do
+    /// not fit in memory. The size limit and eviction policy only apply to the in-memory index.
+    pub fn tx_index(mut self, tx_index: impl TxIndex + 'static) -> Self {
+        self.tx_index = Some(Box::new(tx_index));
+        self
+    }
+
+    /// Sets which transaction types are kept in the transaction index and can therefore be
+    /// disputed. Defaults to deposits and withdrawals; restricting it to deposits roughly halves
+    /// the index size for typical workloads.
+    ///
+    /// An empty list disables dispute tracking, so nothing is inserted into the index. Every
+    /// dispute is then ignored and reused tx ids are no longer detected.
+    pub fn disputable_types(mut self, tx_types: Vec<TxType>) -> Self {
+        self.disputable_types = tx_types;
+        self
+    }
+
+    /// Sets the clock used for all timestamps. Defaults to the [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets how reused tx ids are handled. Defaults to [`TxIdCollisionPolicy::Reject`].
+    pub fn tx_id_collisions(mut self, policy: TxIdCollisionPolicy) -> Self {
+        self.tx_id_collisions = policy;
+        self
+    }
+
+    /// Sets how records with an unknown transaction type are handled. Defaults to
+    /// [`UnknownTypePolicy::Reject`].
+    pub fn unknown_types(mut self, policy: UnknownTypePolicy) -> Self {
+        self.unknown_types = policy;
+        self
+    }
+
+    /// Rejects transactions of clients that are not permitted with
+    /// [`RejectCode::ClientBlocked`], without creating their accounts.
+    pub fn client_access(mut self, access: ClientAccess) -> Self {
+        self.client_access = Some(access);
+        self
+    }
+
+    /// Checks every transaction for suspected duplicate deposits, see [`Engine::suspects`]. Under
+    /// a strict detector, suspected duplicates are rejected with [`RejectCode::SuspectedDuplicate`].
+    pub fn duplicate_detector(mut self, detector: DuplicateDetector) -> Self {
+        self.duplicate_detector = Some(detector);
+        self
+    }
+
+    /// Rejects transactions for clients whose account was not opened with an `open_account`
+    /// transaction, instead of creating the account on first use.
+    pub fn require_open_accounts(mut self, require: bool) -> Self {
+        self.require_open_accounts = require;
+        self
+    }
+
+    /// Whether a dispute, resolve, chargeback or chargeback reversal for a client without an
+    /// account creates an empty one. Defaults to `true`; when disabled such transactions are
+    /// ignored as [`IgnoreReason::TxNotFound`] and the client does not appear in the output.
+    pub fn create_accounts_on_disputes(mut self, create: bool) -> Self {
+        self.create_accounts_on_disputes = create;
+        self
+    }
+
+    /// Stops the run when a dispute, resolve, chargeback or chargeback reversal references a
+    /// transaction that is not stored, such as one missing from the state passed to
+    /// [`Engine::restore`]. See [`Engine::abort_reason`].
+    pub fn abort_on_missing_references(mut self, abort: bool) -> Self {
+        self.abort_on_missing_references = abort;
+        self
+    }
+
+    /// Samples the balances of an account after every `interval` transactions applied to it,
+    /// see [`Engine::balance_history`].
+    pub fn balance_history_interval(mut self, interval: u64) -> Self {
+        self.balance_history_interval = Some(interval).filter(|interval| *interval > 0);
+        self
+    }
+
+    /// Logs [`EngineStats`] every `interval` records during [`Engine::process_all`].
+    pub fn stats_interval(mut self, interval: usize) -> Self {
+        self.stats_interval = Some(interval).filter(|interval| *interval > 0);
+        self
+    }
+
+    /// Records how long each transaction takes to process, by type. See [`Engine::latencies`].
+    pub fn record_latencies(mut self, record: bool) -> Self {
+        self.record_latencies = record;
+        self
+    }
+
+    /// Unlocks the account when a chargeback is reversed. By default the account stays locked
+    /// until an operator unlocks it.
+    pub fn unlock_on_chargeback_reversal(mut self, unlock: bool) -> Self {
+        self.unlock_on_chargeback_reversal = unlock;
+        self
+    }
+
+    /// Accrues this fraction of every applied deposit as a reward, paid out with
+    /// [`Engine::pay_out_rewards`].
+    pub fn reward_rate(mut self, rate: Amount) -> Self {
+        self.reward_rate = Some(rate);
+        self
+    }
+
+    /// Rounds reward payouts with `rounding` rather than half to even.
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    pub fn build(self) -> Engine<A> {
+        Engine {
+            accounts: self.accounts,
+            transactions: self.tx_index.unwrap_or_else(|| {
+                Box::new(TxStore::new(
+                    self.max_stored_transactions,
+                    self.eviction_policy,
+                ))
+            }),
+            disputable_types: self.disputable_types,
+            clock: self.clock,
+            open_disputes: HashMap::new(),
+            stats_interval: self.stats_interval,
+            tx_id_collisions: self.tx_id_collisions,
+            source: 0,
+            namespaced_ids: HashMap::new(),
+            next_index_id: 0,
+            latencies: self.record_latencies.then(LatencyMetrics::new),
+            unlock_on_chargeback_reversal: self.unlock_on_chargeback_reversal,
+            reward_rate: self.reward_rate,
+            rewards: BTreeMap::new(),
+            rounding: self.rounding,
+            unknown_types: self.unknown_types,
+            unknown_type_count: 0,
+            abort_reason: None,
+            client_access: self.client_access,
+            duplicate_detector: self.duplicate_detector,
+            require_open_accounts: self.require_open_accounts,
+            create_accounts_on_disputes: self.create_accounts_on_disputes,
+            abort_on_missing_references: self.abort_on_missing_references,
+            opened_accounts: HashMap::new(),
+            merged_into: HashMap::new(),
+            processed: 0,
+            balance_history_interval: self.balance_history_interval,
+            applied_counts: HashMap::new(),
+            balance_history: BTreeMap::new(),
+            active_clients: HashSet::new(),
+        }
+    }
 }
 
 impl<A: account::Manager> Engine<A> {
     pub fn new(accounts: A) -> Self {
-        Self {
+        Self::builder(accounts).build()
+    }
+
+    pub fn builder(accounts: A) -> EngineBuilder<A> {
+        EngineBuilder {
             accounts,
-            transactions: HashMap::new(),
+            max_stored_transactions: None,
+            eviction_policy: EvictionPolicy::OldestFirst,
+            tx_index: None,
+            disputable_types: vec![TxType::Deposit, TxType::Withdrawal],
+            clock: Box::new(SystemClock),
+            stats_interval: None,
+            tx_id_collisions: TxIdCollisionPolicy::default(),
+            record_latencies: false,
+            unlock_on_chargeback_reversal: false,
+            reward_rate: None,
+            rounding: Rounding::default(),
+            unknown_types: UnknownTypePolicy::default(),
+            client_access: None,
+            duplicate_detector: None,
+            require_open_accounts: false,
+            create_accounts_on_disputes: true,
+            abort_on_missing_references: false,
+            balance_history_interval: None,
+        }
+    }
+
+    /// The id under which the transaction index stores `tx_id` from the current source, if any.
+    fn index_id(&self, tx_id: TxId) -> Option<TxId> {
+        match self.tx_id_collisions {
+            TxIdCollisionPolicy::Namespaced => {
+                self.namespaced_ids.get(&(self.source, tx_id)).copied()
+            }
+            _ => Some(tx_id),
         }
     }
 
-    fn get_client_tx(&self, client_id: u16, tx_id: u32) -> anyhow::Result<Option<Transaction>> {
-        match self.transactions.get(&tx_id) {
+    /// The client whose account holds the transactions of `client_id`, following merges.
+    fn resolve_client(&self, client_id: ClientId) -> ClientId {
+        self.merged_into
+            .get(&client_id)
+            .copied()
+            .unwrap_or(client_id)
+    }
+
+    /// Reads a stored transaction, attributing it to the account it was merged into, if any.
+    fn stored(&self, index_id: TxId) -> anyhow::Result<Option<StoredTx>> {
+        Ok(self.transactions.get(index_id)?.map(|stored| StoredTx {
+            client_id: self.resolve_client(stored.client_id),
+            ..stored
+        }))
+    }
+
+    fn get_client_tx(
+        &self,
+        client_id: ClientId,
+        tx_id: TxId,
+    ) -> anyhow::Result<Option<(TxId, StoredTx)>> {
+        let index_id = match self.index_id(tx_id) {
+            Some(index_id) => index_id,
+            None => return Ok(None),
+        };
+
+        match self.stored(index_id)? {
             Some(tx) => {
                 if tx.client_id == client_id {
-                    Ok(Some(tx.clone()))
+                    Ok(Some((index_id, tx)))
                 } else {
-                    Err(anyhow!(
-                        "The transaction {} does not belong to client {}",
-                        tx_id,
-                        client_id
-                    ))
+                    Err(EngineError::ClientMismatch { tx_id, client_id }.into())
                 }
             }
             None => Ok(None),
         }
     }
 
-    fn process(&mut self, tx: &Transaction) -> anyhow::Result<()> {
-        info!("Ensuring account exists for client id {}", tx.client_id);
-        self.accounts.ensure_account(tx.client_id)?;
+    /// Checks a deposit or withdrawal against earlier transactions with the same id, returning
+    /// an outcome if it should not be applied.
+    fn check_collision(&self, tx: &Transaction) -> anyhow::Result<Option<TxOutcome>> {
+        let index_id = match self.index_id(tx.tx_id) {
+            Some(index_id) if self.transactions.contains(index_id) => index_id,
+            _ => return Ok(None),
+        };
+
+        if self.tx_id_collisions == TxIdCollisionPolicy::Idempotent {
+            if let Some(stored) = self.stored(index_id)? {
+                if stored.client_id == tx.client_id && stored.amount == tx.amount {
+                    info!(
+                        "[{}] Transaction {} has already been processed so will ignore",
+                        tx.trace_id(),
+                        tx.tx_id
+                    );
+                    return Ok(Some(TxOutcome::Ignored {
+                        reason: IgnoreReason::DuplicateTx,
+                    }));
+                }
+            }
+        }
+
+        Err(EngineError::DuplicateTx(tx.tx_id).into())
+    }
+
+    fn index(&mut self, tx: &Transaction) -> anyhow::Result<()> {
+        if !self.disputable_types.contains(&tx.tx_type) {
+            return Ok(());
+        }
+
+        let index_id = match self.tx_id_collisions {
+            TxIdCollisionPolicy::Namespaced => {
+                match self.namespaced_ids.entry((self.source, tx.tx_id)) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        let index_id = self.next_index_id;
+                        self.next_index_id += 1;
+                        *entry.insert(index_id)
+                    }
+                }
+            }
+            _ => tx.tx_id,
+        };
+
+        self.transactions
+            .insert(index_id, StoredTx::new(tx.tx_type, tx.client_id, tx.amount))
+    }
+
+    /// Checks the transaction against the client's account and applies it.
+    fn check_and_apply(&mut self, tx: &Transaction) -> anyhow::Result<TxOutcome> {
+        if let Some(detector) = &mut self.duplicate_detector {
+            if !detector.check(tx) {
+                return Err(EngineError::SuspectedDuplicate(tx.tx_id).into());
+            }
+        }
+
+        let merged;
+        let tx = match self.merged_into.get(&tx.client_id) {
+            Some(&target) => {
+                merged = Transaction {
+                    client_id: target,
+                    ..tx.clone()
+                };
+                &merged
+            }
+            None => tx,
+        };
 
-        if self.accounts.is_locked(tx.client_id)? {
+        if let Some(access) = &self.client_access {
+            if !access.permits(tx.client_id) {
+                return Err(EngineError::ClientBlocked(tx.client_id).into());
+            }
+        }
+
+        if self.require_open_accounts
+            && tx.tx_type != TxType::OpenAccount
+            && !self.opened_accounts.contains_key(&tx.client_id)
+        {
+            return Err(EngineError::AccountNotOpened(tx.client_id).into());
+        }
+
+        if !self.create_accounts_on_disputes
+            && !matches!(
+                tx.tx_type,
+                TxType::Deposit | TxType::Withdrawal | TxType::OpenAccount
+            )
+            && self.accounts.account(tx.client_id).is_none()
+        {
             info!(
-                "Account is locked so transaction will not be processed for client id {}",
-                tx.client_id
+                "[{}] No account for client id {} so will ignore {}",
+                tx.trace_id(),
+                tx.client_id,
+                tx.tx_type.as_str()
             );
-            return Ok(());
+            return Ok(TxOutcome::Ignored {
+                reason: IgnoreReason::TxNotFound,
+            });
+        }
+
+        info!(
+            "[{}] Ensuring account exists for client id {}",
+            tx.trace_id(),
+            tx.client_id
+        );
+        self.accounts.ensure_account(tx.client_id)?;
+
+        // A chargeback reversal usually applies to the account its chargeback locked.
+        if tx.tx_type != TxType::ChargebackReversal && self.accounts.is_locked(tx.client_id)? {
+            return Err(EngineError::AccountLocked(tx.client_id).into());
+        }
+
+        if matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal) {
+            if let Some(outcome) = self.check_collision(tx)? {
+                return Ok(outcome);
+            }
         }
 
+        self.apply(tx)
+    }
+
+    /// Applies a transaction to an existing account without checking whether it is locked.
+    fn apply(&mut self, tx: &Transaction) -> anyhow::Result<TxOutcome> {
         match tx.tx_type {
             TxType::Deposit => {
-                info!("Depositing amount for client id {}", tx.client_id);
-                self.transactions.insert(tx.tx_id, tx.clone());
-                self.accounts.deposit(tx.client_id, tx.amount)
+                info!(
+                    "[{}] Depositing amount for client id {}",
+                    tx.trace_id(),
+                    tx.client_id
+                );
+                self.accounts.deposit(tx.client_id, tx.amount)?;
+                self.index(tx)?;
+                self.accrue_reward(tx.client_id, tx.amount);
+                Ok(TxOutcome::Applied)
             }
             TxType::Withdrawal => {
-                info!("Withdrawing amount for client id {}", tx.client_id);
-                self.transactions.insert(tx.tx_id, tx.clone());
-                self.accounts.withdraw(tx.client_id, tx.amount)
+                info!(
+                    "[{}] Withdrawing amount for client id {}",
+                    tx.trace_id(),
+                    tx.client_id
+                );
+                self.accounts.withdraw(tx.client_id, tx.amount)?;
+                self.index(tx)?;
+                Ok(TxOutcome::Applied)
             }
             TxType::Dispute => {
                 info!(
-                    "Disputing transaction {} for client id {}",
-                    tx.tx_id, tx.client_id
+                    "[{}] Disputing transaction {} for client id {}",
+                    tx.trace_id(),
+                    tx.tx_id,
+                    tx.client_id
                 );
 
                 match self.get_client_tx(tx.client_id, tx.tx_id)? {
-                    Some(tx) => self.accounts.hold(tx.client_id, tx.amount),
+                    Some((index_id, stored)) => {
+                        let next = stored.state.transition(&tx.tx_type, tx.tx_id)?;
+                        let metadata = tx.metadata_entries()?;
+                        self.accounts.hold(stored.client_id, stored.amount)?;
+                        self.transactions
+                            .insert(index_id, stored.with_state(next))?;
+                        self.open_disputes.insert(
+                            index_id,
+                            OpenDispute {
+                                opened: self.clock.now(),
+                                metadata,
+                            },
+                        );
+                        Ok(TxOutcome::Applied)
+                    }
                     None => {
                         info!(
-                            "Disputed transaction {} not found so will ignore for client id {}",
-                            tx.tx_id, tx.client_id
+                            "[{}] Disputed transaction {} not found so will ignore for client id {}",
+                            tx.trace_id(),
+                            tx.tx_id,
+                            tx.client_id
                         );
-                        Ok(())
+                        Ok(TxOutcome::Ignored {
+                            reason: IgnoreReason::TxNotFound,
+                        })
                     }
                 }
             }
             TxType::Resolve => {
                 info!(
-                    "Resolving transaction {} for client id {}",
-                    tx.tx_id, tx.client_id
+                    "[{}] Resolving transaction {} for client id {}",
+                    tx.trace_id(),
+                    tx.tx_id,
+                    tx.client_id
                 );
 
                 match self.get_client_tx(tx.client_id, tx.tx_id)? {
-                    Some(held_tx) => self.accounts.release(held_tx.client_id, held_tx.amount),
+                    Some((index_id, stored)) => {
+                        let next = stored.state.transition(&tx.tx_type, tx.tx_id)?;
+                        self.accounts.release(stored.client_id, stored.amount)?;
+                        self.transactions
+                            .insert(index_id, stored.with_state(next))?;
+                        self.open_disputes.remove(&index_id);
+                        Ok(TxOutcome::Applied)
+                    }
                     None => {
                         info!(
-                            "Resolved transaction {} not found so will ignore for client id {}",
-                            tx.tx_id, tx.client_id
+                            "[{}] Resolved transaction {} not found so will ignore for client id {}",
+                            tx.trace_id(),
+                            tx.tx_id,
+                            tx.client_id
                         );
-                        Ok(())
+                        Ok(TxOutcome::Ignored {
+                            reason: IgnoreReason::TxNotFound,
+                        })
                     }
                 }
             }
             TxType::Chargeback => {
                 info!(
-                    "Chargeback transaction {} for client id {}",
-                    tx.tx_id, tx.client_id
+                    "[{}] Chargeback transaction {} for client id {}",
+                    tx.trace_id(),
+                    tx.tx_id,
+                    tx.client_id
                 );
 
                 match self.get_client_tx(tx.client_id, tx.tx_id)? {
-                    Some(tx) => {
-                        self.accounts.withdraw_held(tx.client_id, tx.amount)?;
-                        self.accounts.lock(tx.client_id)?;
-                        Ok(())
+                    Some((index_id, stored)) => {
+                        let next = stored.state.transition(&tx.tx_type, tx.tx_id)?;
+                        self.accounts
+                            .withdraw_held(stored.client_id, stored.amount)?;
+                        self.accounts.lock(stored.client_id)?;
+                        if stored.tx_type == TxType::Deposit {
+                            self.accrue_reward(stored.client_id, -stored.amount);
+                        }
+                        self.transactions
+                            .insert(index_id, stored.with_state(next))?;
+                        self.open_disputes.remove(&index_id);
+                        Ok(TxOutcome::Applied)
                     }
                     None => {
                         info!(
-                            "Chargeback transaction {} not found so will ignore for client id {}",
-                            tx.tx_id, tx.client_id
+                            "[{}] Chargeback transaction {} not found so will ignore for client id {}",
+                            tx.trace_id(),
+                            tx.tx_id,
+                            tx.client_id
                         );
-                        Ok(())
+                        Ok(TxOutcome::Ignored {
+                            reason: IgnoreReason::TxNotFound,
+                        })
                     }
                 }
             }
-        }
-    }
+            TxType::ChargebackReversal => {
+                info!(
+                    "[{}] Reversing chargeback of transaction {} for client id {}",
+                    tx.trace_id(),
+                    tx.tx_id,
+                    tx.client_id
+                );
 
-    pub fn process_all(
-        &mut self,
-        transactions: impl IntoIterator<Item = anyhow::Result<Transaction>>,
-    ) {
-        for result in transactions {
-            match result {
-                Ok(tx) => {
-                    info!("Processing transaction: {:?}", tx);
-                    match self.process(&tx) {
-                        Ok(()) => info!("Transaction complete"),
-                        Err(err) => error!("Transaction failed: {}", err),
+                match self.get_client_tx(tx.client_id, tx.tx_id)? {
+                    Some((index_id, stored)) => {
+                        let next = stored.state.transition(&tx.tx_type, tx.tx_id)?;
+                        self.accounts.deposit(stored.client_id, stored.amount)?;
+                        if self.unlock_on_chargeback_reversal {
+                            self.accounts.unlock(stored.client_id)?;
+                        }
+                        if stored.tx_type == TxType::Deposit {
+                            self.accrue_reward(stored.client_id, stored.amount);
+                        }
+                        self.transactions
+                            .insert(index_id, stored.with_state(next))?;
+                        Ok(TxOutcome::Applied)
+                    }
+                    None => {
+                        info!(
+                            "[{}] Charged back transaction {} not found so will ignore for client id {}",
+                            tx.trace_id(),
+                            tx.tx_id,
+                            tx.client_id
+                        );
+                        Ok(TxOutcome::Ignored {
+                            reason: IgnoreReason::TxNotFound,
+                        })
                     }
                 }
-                Err(err) => error!("Encountered corrupt transaction: {}", err),
             }
+            TxType::OpenAccount => self.open_account(tx),
         }
     }
 
-    pub fn get_accounts(&self) -> Vec<&Account> {
-        self.accounts.all()
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use rust_decimal_macros::dec;
+    fn open_account(&mut self, tx: &Transaction) -> anyhow::Result<TxOutcome> {
+        info!(
+            "[{}] Opening account for client id {}",
+            tx.trace_id(),
+            tx.client_id
+        );
 
-    use super::*;
+        if self.opened_accounts.contains_key(&tx.client_id) {
+            return Err(EngineError::AccountAlreadyOpen(tx.client_id).into());
+        }
 
-    #[test]
-    fn deposit_and_withdrawal_integration_test() {
-        let accounts = account::SimpleManager::new();
-        let mut engine = Engine::new(accounts);
+        self.opened_accounts
+            .insert(tx.client_id, tx.metadata_entries()?);
+        Ok(TxOutcome::Applied)
+    }
 
-        let txs = vec![
-            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
-            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(3.0))),
-        ];
+    /// Metadata the account of `client_id` was opened with, or `None` if it was not opened with
+    /// an `open_account` transaction.
+    pub fn account_metadata(&self, client_id: ClientId) -> Option<&BTreeMap<String, String>> {
+        self.opened_accounts.get(&client_id)
+    }
 
-        engine.process_all(txs);
+    /// Applies an operator action to an existing account, reporting the outcome in the same way
+    /// as for transactions.
+    pub fn apply_admin(&mut self, client_id: ClientId, action: &AdminAction) -> TxOutcome {
+        info!("[admin] Applying {:?} for client id {}", action, client_id);
+        let client_id = self.resolve_client(client_id);
 
-        let accounts = engine.get_accounts();
+        let result: anyhow::Result<()> = match action {
+            AdminAction::Lock => self.accounts.lock(client_id).map_err(Into::into),
+            AdminAction::Unlock => self.accounts.unlock(client_id).map_err(Into::into),
+            AdminAction::ForceResolve { tx_id } => {
+                return self
+                    .apply(
+                        &Transaction::new(TxType::Resolve, client_id, *tx_id, Amount::ZERO)
+                            .with_trace_id("admin"),
+                    )
+                    .unwrap_or_else(|err| admin_rejection(&err));
+            }
+            AdminAction::WriteOff { tx_id } => match self.get_client_tx(client_id, *tx_id) {
+                Ok(Some((index_id, stored))) => {
+                    self.close_dispute(index_id, *tx_id, stored, DisputeResolution::WriteOff)
+                }
+                Ok(None) => Err(EngineError::TxNotFound(*tx_id).into()),
+                Err(err) => Err(err),
+            },
+            AdminAction::Adjust { amount } if amount.is_sign_positive() => self
+                .accounts
+                .deposit(client_id, *amount)
+                .map_err(Into::into),
+            AdminAction::Adjust { amount } => self
+                .accounts
+                .withdraw(client_id, -*amount)
+                .map_err(Into::into),
+            AdminAction::MergeInto { target } => self.merge_accounts(client_id, *target),
+        };
 
-        assert_eq!(accounts.len(), 1);
-        assert_eq!(accounts[0].client_id, 1);
-        assert_eq!(accounts[0].available_amount, dec!(7.0));
+        match result {
+            Ok(()) => TxOutcome::Applied,
+            Err(err) => admin_rejection(&err),
+        }
     }
 
-    #[test]
-    fn dispute_integration_test() {
-        let accounts = account::SimpleManager::new();
-        let mut engine = Engine::new(accounts);
+    fn merge_accounts(&mut self, source: ClientId, target: ClientId) -> anyhow::Result<()> {
+        let target = self.resolve_client(target);
+        if source == target {
+            return Err(reject(
+                RejectCode::InvalidTxState,
+                format!("Client id {} is already merged into {}", source, target),
+            ));
+        }
 
-        let txs = vec![
-            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
-            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
-            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
-        ];
+        let (available, held) = match (self.accounts.account(source), self.accounts.account(target))
+        {
+            (Some(source_acc), Some(target_acc)) => {
+                if source_acc.is_locked || target_acc.is_locked {
+                    return Err(reject(
+                        RejectCode::AccountLocked,
+                        format!(
+                            "Cannot merge client id {} into {} as an account is locked",
+                            source, target
+                        ),
+                    ));
+                }
+                if target_acc
+                    .available_amount
+                    .checked_add(source_acc.available_amount + source_acc.held_amount)
+                    .is_none()
+                    || target_acc
+                        .held_amount
+                        .checked_add(source_acc.held_amount)
+                        .is_none()
+                {
+                    return Err(reject(
+                        RejectCode::Overflow,
+                        format!("Merged balances of client id {} are too large", target),
+                    ));
+                }
+                (source_acc.available_amount, source_acc.held_amount)
+            }
+            _ => {
+                return Err(reject(
+                    RejectCode::AccountNotFound,
+                    format!("Cannot merge client id {} into {}", source, target),
+                ))
+            }
+        };
 
-        engine.process_all(txs);
+        let total = available + held;
+        if held > Amount::ZERO {
+            self.accounts.release(source, held)?;
+        }
+        if total > Amount::ZERO {
+            self.accounts.withdraw(source, total)?;
+            self.accounts.deposit(target, total)?;
+        }
+        if held > Amount::ZERO {
+            self.accounts.hold(target, held)?;
+        }
 
-        let accounts = engine.get_accounts();
+        for merged in self.merged_into.values_mut() {
+            if *merged == source {
+                *merged = target;
+            }
+        }
+        self.merged_into.insert(source, target);
 
-        assert_eq!(accounts.len(), 1);
-        assert_eq!(accounts[0].client_id, 1);
-        assert_eq!(accounts[0].available_amount, dec!(5.0));
-        assert_eq!(accounts[0].held_amount, dec!(10.0));
+        if let Some(reward) = self.rewards.remove(&source) {
+            *self.rewards.entry(target).or_default() += reward;
+        }
+        if let Some(metadata) = self.opened_accounts.remove(&source) {
+            self.opened_accounts.entry(target).or_insert(metadata);
+        }
+
+        info!("[admin] Merged client id {} into {}", source, target);
+        Ok(())
     }
 
-    #[test]
-    fn chargeback_integration_test() {
-        let accounts = account::SimpleManager::new();
-        let mut engine = Engine::new(accounts);
+    /// Closes every dispute that is still open, for when the closing records will never arrive.
+    /// Returns the number of disputes closed.
+    pub fn close_open_disputes(&mut self, resolution: DisputeResolution) -> anyhow::Result<usize> {
+        let index_ids: Vec<TxId> = self.open_disputes.keys().copied().collect();
 
-        let txs = vec![
-            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
-            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+        for &index_id in &index_ids {
+            if let Some(stored) = self.stored(index_id)? {
+                self.close_dispute(index_id, index_id, stored, resolution)?;
+            }
+        }
+
+        Ok(index_ids.len())
+    }
+
+    fn close_dispute(
+        &mut self,
+        index_id: TxId,
+        tx_id: TxId,
+        stored: StoredTx,
+        resolution: DisputeResolution,
+    ) -> anyhow::Result<()> {
+        if stored.state != TxState::Disputed {
+            return Err(reject(
+                RejectCode::InvalidTxState,
+                format!(
+                    "Cannot close dispute of transaction {} as it is {}",
+                    tx_id,
+                    stored.state.as_str()
+                ),
+            ));
+        }
+
+        info!(
+            "[admin] Closing dispute of transaction {} for client id {} with {:?}",
+            tx_id, stored.client_id, resolution
+        );
+
+        let next = match resolution {
+            DisputeResolution::Resolve => {
+                self.accounts.release(stored.client_id, stored.amount)?;
+                TxState::Resolved
+            }
+            DisputeResolution::WriteOff => {
+                self.accounts
+                    .withdraw_held(stored.client_id, stored.amount)?;
+                TxState::WrittenOff
+            }
+        };
+        self.transactions
+            .insert(index_id, stored.with_state(next))?;
+        self.open_disputes.remove(&index_id);
+        Ok(())
+    }
+
+    /// Rewards accrued but not paid out yet, by client. A negative reward is owed back for a
+    /// deposit charged back or compensated after its reward was paid out.
+    pub fn rewards(&self) -> &BTreeMap<ClientId, Amount> {
+        &self.rewards
+    }
+
+    /// Accrues the reward on a deposit of `amount`, or reverses it for a negative `amount`.
+    fn accrue_reward(&mut self, client_id: ClientId, amount: Amount) {
+        if let Some(rate) = self.reward_rate {
+            *self.rewards.entry(client_id).or_default() += amount * rate;
+        }
+    }
+
+    /// Credits every accrued reward to its account, at the end of a reward period. Rewards accrue
+    /// unrounded and are paid out rounded to cents with the engine's [`Rounding`].
+    ///
+    /// Rewards of locked accounts and negative rewards stay accrued for a later payout, as do
+    /// rewards that could not be credited.
+    pub fn pay_out_rewards(&mut self) -> RewardPayout {
+        let mut payout = RewardPayout::default();
+
+        for (client_id, amount) in mem::take(&mut self.rewards) {
+            let locked = self
+                .accounts
+                .account(client_id)
+                .is_some_and(|acc| acc.is_locked);
+            if locked || amount < Amount::ZERO {
+                self.rewards.insert(client_id, amount);
+                continue;
+            }
+
+            let rounded = self.rounding.round(amount, REWARD_DECIMAL_PLACES);
+            if rounded == Amount::ZERO {
+                continue;
+            }
+
+            let tx = Transaction::new(TxType::Deposit, client_id, 0, rounded)
+                .with_trace_id(format!("reward-{}", client_id));
+            info!(
+                "[{}] Paying out reward for client id {}",
+                tx.trace_id(),
+                client_id
+            );
+            match self.accounts.deposit(client_id, rounded) {
+                Ok(()) => payout.paid.push(tx),
+                Err(err) => {
+                    self.rewards.insert(client_id, amount);
+                    payout.failed.push((client_id, err.into()));
+                }
+            }
+        }
+
+        payout
+    }
+
+    /// Reverses a stored deposit or withdrawal by applying an inverse transaction, which is
+    /// returned so it can be recorded alongside the original. The original is marked
+    /// [`TxState::Compensated`] rather than removed, and can no longer be disputed.
+    ///
+    /// Disputed transactions must be resolved first. Like admin actions, compensation applies to
+    /// locked accounts.
+    pub fn compensate(&mut self, tx_id: TxId, reason: &str) -> anyhow::Result<Transaction> {
+        let (index_id, stored) = match self.index_id(tx_id) {
+            Some(index_id) => match self.stored(index_id)? {
+                Some(stored) => (index_id, stored),
+                None => return Err(anyhow!("Transaction {} is not stored", tx_id)),
+            },
+            None => return Err(anyhow!("Transaction {} is not stored", tx_id)),
+        };
+
+        if !matches!(stored.state, TxState::Processed | TxState::Resolved) {
+            return Err(reject(
+                RejectCode::InvalidTxState,
+                format!(
+                    "Cannot compensate transaction {} as it is {}",
+                    tx_id,
+                    stored.state.as_str()
+                ),
+            ));
+        }
+
+        let inverse_type = match stored.tx_type {
+            TxType::Deposit => TxType::Withdrawal,
+            TxType::Withdrawal => TxType::Deposit,
+            tx_type => {
+                return Err(anyhow!(
+                    "Cannot compensate {} transaction {}",
+                    tx_type.as_str(),
+                    tx_id
+                ))
+            }
+        };
+        let inverse = Transaction::new(inverse_type, stored.client_id, tx_id, stored.amount)
+            .with_trace_id(format!("compensate-{}", tx_id));
+
+        info!(
+            "[{}] Compensating {} transaction {} for client id {}: {}",
+            inverse.trace_id(),
+            stored.tx_type.as_str(),
+            tx_id,
+            stored.client_id,
+            reason
+        );
+
+        match inverse_type {
+            TxType::Withdrawal => {
+                self.accounts.withdraw(stored.client_id, stored.amount)?;
+                self.accrue_reward(stored.client_id, -stored.amount);
+            }
+            _ => self.accounts.deposit(stored.client_id, stored.amount)?,
+        }
+        self.transactions
+            .insert(index_id, stored.with_state(TxState::Compensated))?;
+
+        Ok(inverse)
+    }
+
+    /// Processes a single transaction, reporting whether it was applied, ignored (for example as
+    /// [`IgnoreReason::TxNotFound`] for a dispute of an unknown transaction) or rejected.
+    pub fn process(&mut self, tx: &Transaction) -> TxOutcome {
+        self.try_process(tx)
+            .unwrap_or_else(|err| TxOutcome::Rejected {
+                code: err.code(),
+                message: err.to_string(),
+            })
+    }
+
+    /// Processes a single transaction like [`Engine::process`], returning why it was rejected as
+    /// an [`EngineError`]. The returned outcome is never [`TxOutcome::Rejected`].
+    pub fn try_process(&mut self, tx: &Transaction) -> Result<TxOutcome, EngineError> {
+        let start = self.latencies.is_some().then(Instant::now);
+
+        let outcome = self.check_and_apply(tx).map_err(EngineError::from);
+
+        if let (Some(latencies), Some(start)) = (self.latencies.as_mut(), start) {
+            latencies.record(&tx.tx_type, start.elapsed());
+        }
+
+        self.processed += 1;
+        if let Ok(TxOutcome::Applied) = outcome {
+            self.active_clients
+                .insert(self.resolve_client(tx.client_id));
+            if let Some(interval) = self.balance_history_interval {
+                self.sample_balance(self.resolve_client(tx.client_id), interval);
+            }
+        }
+
+        outcome
+    }
+
+    fn sample_balance(&mut self, client_id: ClientId, interval: u64) {
+        let count = self.applied_counts.entry(client_id).or_default();
+        *count += 1;
+        if !count.is_multiple_of(interval) {
+            return;
+        }
+
+        if let Some(acc) = self.accounts.account(client_id) {
+            self.balance_history
+                .entry(client_id)
+                .or_default()
+                .push(BalanceSample {
+                    seq: self.processed,
+                    available: acc.available_amount,
+                    held: acc.held_amount,
+                });
+        }
+    }
+
+    /// Balance samples of each account, oldest first, if sampling is enabled with
+    /// [`EngineBuilder::balance_history_interval`].
+    pub fn balance_history(&self) -> &BTreeMap<ClientId, Vec<BalanceSample>> {
+        &self.balance_history
+    }
+
+    /// Processes every record, returning a tally of their outcomes. Stops early if the run is
+    /// aborted.
+    pub fn process_all(
+        &mut self,
+        transactions: impl IntoIterator<Item = anyhow::Result<Transaction>>,
+    ) -> ProcessingSummary {
+        let mut summary = ProcessingSummary::default();
+        for result in self.process_all_iter(transactions) {
+            summary.record(&result);
+        }
+        summary
+    }
+
+    /// Lazily processes each record as the returned iterator is advanced, yielding its outcome.
+    ///
+    /// Records that could not be read are yielded as rejected with no tx id. The iterator ends
+    /// early if the run is aborted.
+    pub fn process_all_iter<'a, I>(
+        &'a mut self,
+        transactions: I,
+    ) -> impl Iterator<Item = TxResult> + 'a
+    where
+        I: IntoIterator<Item = anyhow::Result<Transaction>>,
+        I::IntoIter: 'a,
+    {
+        transactions
+            .into_iter()
+            .enumerate()
+            .map_while(move |(index, result)| {
+                (self.abort_reason.is_none()).then(|| self.process_record(index + 1, result))
+            })
+    }
+
+    /// Deposits flagged by the [`EngineBuilder::duplicate_detector`], if any.
+    pub fn suspects(&self) -> &[SuspectedDuplicate] {
+        self.duplicate_detector
+            .as_ref()
+            .map_or(&[], DuplicateDetector::suspects)
+    }
+
+    /// Why processing was stopped under [`UnknownTypePolicy::Abort`] or
+    /// [`EngineBuilder::abort_on_missing_references`], if it was. Later records are still
+    /// processed if passed to [`Engine::process_with_receipt`] directly.
+    pub fn abort_reason(&self) -> Option<&str> {
+        self.abort_reason.as_deref()
+    }
+
+    /// Processes the record at 1-based position `row` in its input, returning its outcome and the
+    /// balances of its account afterwards.
+    pub fn process_with_receipt(
+        &mut self,
+        row: usize,
+        result: anyhow::Result<Transaction>,
+    ) -> Receipt {
+        let client_id = result.as_ref().ok().map(|tx| tx.client_id);
+        let result = self.process_record(row, result);
+        let account = client_id.and_then(|client_id| self.accounts.account(client_id));
+
+        Receipt {
+            result,
+            available: account.map(|acc| acc.available_amount),
+            held: account.map(|acc| acc.held_amount),
+        }
+    }
+
+    fn process_record(&mut self, row: usize, result: anyhow::Result<Transaction>) -> TxResult {
+        let result = match result {
+            Ok(tx) => {
+                info!("[{}] Processing transaction: {:?}", tx.trace_id(), tx);
+                let outcome = self.process(&tx);
+                if self.abort_on_missing_references
+                    && matches!(
+                        outcome,
+                        TxOutcome::Ignored {
+                            reason: IgnoreReason::TxNotFound
+                        }
+                    )
+                {
+                    self.abort_reason = Some(format!(
+                        "Record {} references transaction {} which is not stored",
+                        row, tx.tx_id
+                    ));
+                }
+                match &outcome {
+                    TxOutcome::Applied => info!("[{}] Transaction complete", tx.trace_id()),
+                    TxOutcome::Ignored { reason } => {
+                        info!("[{}] Transaction ignored: {}", tx.trace_id(), reason)
+                    }
+                    TxOutcome::Rejected { code, message } => error!(
+                        "[{}] Transaction failed [{}]: {}",
+                        tx.trace_id(),
+                        code,
+                        message
+                    ),
+                }
+                TxResult {
+                    row,
+                    tx_id: Some(tx.tx_id),
+                    outcome,
+                }
+            }
+            Err(err) if RejectCode::of(&err) == RejectCode::UnknownType => {
+                self.unknown_type_count += 1;
+                self.unknown_type(row, err)
+            }
+            Err(err) => {
+                let code = RejectCode::of(&err);
+                error!("Encountered corrupt transaction [{}]: {}", code, err);
+                TxResult {
+                    row,
+                    tx_id: None,
+                    outcome: TxOutcome::Rejected {
+                        code,
+                        message: err.to_string(),
+                    },
+                }
+            }
+        };
+
+        if let Some(interval) = self.stats_interval {
+            if row.is_multiple_of(interval) {
+                info!("Processed {} records: {}", row, self.stats());
+            }
+        }
+
+        result
+    }
+
+    fn unknown_type(&mut self, row: usize, err: anyhow::Error) -> TxResult {
+        let outcome = match self.unknown_types {
+            UnknownTypePolicy::Skip | UnknownTypePolicy::Quarantine => {
+                debug!("Skipping record {} with unknown type: {}", row, err);
+                TxOutcome::Ignored {
+                    reason: IgnoreReason::UnknownType,
+                }
+            }
+            UnknownTypePolicy::Reject | UnknownTypePolicy::Abort => {
+                error!("Encountered unknown transaction type: {}", err);
+                if self.unknown_types == UnknownTypePolicy::Abort {
+                    self.abort_reason = Some(format!("Record {} has an unknown type", row));
+                }
+                TxOutcome::Rejected {
+                    code: RejectCode::UnknownType,
+                    message: err.to_string(),
+                }
+            }
+        };
+
+        TxResult {
+            row,
+            tx_id: None,
+            outcome,
+        }
+    }
+
+    pub fn stats(&self) -> EngineStats {
+        let accounts = self.accounts.all().len();
+
+        EngineStats {
+            accounts,
+            stored_transactions: self.transactions.len(),
+            open_disputes: self.open_disputes.len(),
+            unknown_types: self.unknown_type_count,
+            memory_bytes: accounts * (mem::size_of::<ClientId>() + mem::size_of::<Account>())
+                + self.open_disputes.capacity()
+                    * (mem::size_of::<TxId>() + mem::size_of::<OpenDispute>())
+                + self.namespaced_ids.capacity() * mem::size_of::<((u32, TxId), TxId)>()
+                + self.transactions.memory_bytes(),
+        }
+    }
+
+    /// Captures the accounts, stored transactions and open disputes. Fails if the transaction
+    /// index cannot list its transactions.
+    pub fn state(&self) -> anyhow::Result<EngineState> {
+        let mut namespaced_ids: Vec<_> = self
+            .namespaced_ids
+            .iter()
+            .map(|(&(source, tx_id), &index_id)| (source, tx_id, index_id))
+            .collect();
+        namespaced_ids.sort_by_key(|&(_, _, index_id)| index_id);
+        let mut opened_accounts: Vec<_> = self
+            .opened_accounts
+            .iter()
+            .map(|(client_id, metadata)| (*client_id, metadata.clone()))
+            .collect();
+        opened_accounts.sort_by_key(|(client_id, _)| *client_id);
+        let mut merged_into: Vec<_> = self
+            .merged_into
+            .iter()
+            .map(|(source, target)| (*source, *target))
+            .collect();
+        merged_into.sort();
+
+        Ok(EngineState {
+            accounts: self.accounts.all().into_iter().cloned().collect(),
+            transactions: self.transactions.entries()?,
+            disputes: self
+                .open_disputes
+                .iter()
+                .map(|(index_id, dispute)| (*index_id, dispute.clone()))
+                .collect(),
+            namespaced_ids,
+            rewards: self
+                .rewards
+                .iter()
+                .map(|(client_id, amount)| (*client_id, *amount))
+                .collect(),
+            opened_accounts,
+            merged_into,
+            tx_id_collisions: self.tx_id_collisions,
+        })
+    }
+
+    /// Restores the state saved by an earlier run. Restored accounts must not exist yet, and the
+    /// state must have been saved under the same [`TxIdCollisionPolicy`].
+    ///
+    /// Accounts are rebuilt with a deposit and a hold, so an account with a negative balance is
+    /// an error. On error nothing is restored.
+    pub fn restore(&mut self, state: EngineState) -> anyhow::Result<()> {
+        if state.tx_id_collisions != self.tx_id_collisions {
+            return Err(anyhow!(
+                "State was saved under the {} tx id collision policy, not {}",
+                state.tx_id_collisions.as_str(),
+                self.tx_id_collisions.as_str()
+            ));
+        }
+
+        if let Some(acc) = state
+            .accounts
+            .iter()
+            .find(|acc| acc.available_amount < Amount::ZERO || acc.held_amount < Amount::ZERO)
+        {
+            return Err(anyhow!(
+                "Account {} cannot be restored with a negative balance ({} available, {} held)",
+                acc.client_id,
+                acc.available_amount,
+                acc.held_amount
+            ));
+        }
+
+        if let Some(acc) = state
+            .accounts
+            .iter()
+            .find(|acc| self.accounts.account(acc.client_id).is_some())
+        {
+            return Err(anyhow!("Account {} already exists", acc.client_id));
+        }
+
+        for acc in state.accounts {
+            self.accounts.ensure_account(acc.client_id)?;
+            let total = acc.available_amount + acc.held_amount;
+            if total > Amount::ZERO {
+                self.accounts.deposit(acc.client_id, total)?;
+            }
+            if acc.held_amount > Amount::ZERO {
+                self.accounts.hold(acc.client_id, acc.held_amount)?;
+            }
+            if acc.is_locked {
+                self.accounts.lock(acc.client_id)?;
+            }
+            self.active_clients.insert(acc.client_id);
+        }
+
+        for (index_id, tx) in state.transactions {
+            self.transactions.insert(index_id, tx)?;
+            self.next_index_id = self.next_index_id.max(index_id + 1);
+        }
+        self.open_disputes.extend(state.disputes);
+        // Sources are numbered by their position in each run, so the n-th input of a later run
+        // continues the namespace of the n-th input of earlier runs.
+        for (source, tx_id, index_id) in state.namespaced_ids {
+            self.namespaced_ids.insert((source, tx_id), index_id);
+            self.next_index_id = self.next_index_id.max(index_id + 1);
+        }
+        for (client_id, amount) in state.rewards {
+            *self.rewards.entry(client_id).or_default() += amount;
+        }
+        self.opened_accounts.extend(state.opened_accounts);
+        self.merged_into.extend(state.merged_into);
+
+        Ok(())
+    }
+
+    /// Reports what processing `tx` would do and the resulting account, without changing the
+    /// engine, e.g. to answer whether a withdrawal would succeed.
+    ///
+    /// Balance changes are made to a [`account::CowManager`] view over the accounts, with the
+    /// rules of [`account::SimpleManager`], and the simulated engine only sees the stored
+    /// transaction and open dispute `tx` refers to. Suspected duplicates are checked without
+    /// being recorded.
+    pub fn simulate(&self, tx: &Transaction) -> Simulation {
+        let client_id = self.resolve_client(tx.client_id);
+        let index_id = self.index_id(tx.tx_id);
+
+        let mut index = SeededIndex::default();
+        if let Some(index_id) = index_id.filter(|index_id| self.transactions.contains(*index_id)) {
+            match self.transactions.get(index_id) {
+                Ok(stored) => index.seed(index_id, stored),
+                Err(err) => {
+                    return Simulation {
+                        outcome: TxOutcome::Rejected {
+                            code: RejectCode::of(&err),
+                            message: err.to_string(),
+                        },
+                        account: self.accounts.account(client_id).cloned(),
+                    }
+                }
+            }
+        }
+
+        // The detector only records transactions, so it is consulted up front instead.
+        if self
+            .duplicate_detector
+            .as_ref()
+            .is_some_and(|detector| detector.would_block(tx))
+        {
+            let err = EngineError::SuspectedDuplicate(tx.tx_id);
+            return Simulation {
+                outcome: TxOutcome::Rejected {
+                    code: err.code(),
+                    message: err.to_string(),
+                },
+                account: self.accounts.account(client_id).cloned(),
+            };
+        }
+
+        let mut view = EngineBuilder {
+            tx_index: Some(Box::new(index)),
+            disputable_types: self.disputable_types.clone(),
+            clock: self.clock.boxed_clone(),
+            tx_id_collisions: self.tx_id_collisions,
+            unlock_on_chargeback_reversal: self.unlock_on_chargeback_reversal,
+            reward_rate: self.reward_rate,
+            rounding: self.rounding,
+            unknown_types: self.unknown_types,
+            // Only whether this client is permitted matters.
+            client_access: self.client_access.as_ref().map(|access| {
+                ClientAccess::Allow(
+                    access
+                        .permits(client_id)
+                        .then_some(client_id)
+                        .into_iter()
+                        .collect(),
+                )
+            }),
+            require_open_accounts: self.require_open_accounts,
+            create_accounts_on_disputes: self.create_accounts_on_disputes,
+            ..Engine::builder(account::CowManager::new(&self.accounts))
+        }
+        .build();
+        view.open_disputes = index_id
+            .and_then(|index_id| Some((index_id, self.open_disputes.get(&index_id)?.clone())))
+            .into_iter()
+            .collect();
+        view.source = self.source;
+        view.namespaced_ids = self
+            .namespaced_ids
+            .get_key_value(&(self.source, tx.tx_id))
+            .map(|(key, index_id)| (*key, *index_id))
+            .into_iter()
+            .collect();
+        view.next_index_id = self.next_index_id;
+        view.opened_accounts = self
+            .opened_accounts
+            .get(&client_id)
+            .map(|metadata| (client_id, metadata.clone()))
+            .into_iter()
+            .collect();
+        view.merged_into = self.merged_into.clone();
+        view.processed = self.processed;
+
+        let outcome = view.process(tx);
+        Simulation {
+            outcome,
+            account: account::Manager::account(&view.accounts, client_id).cloned(),
+        }
+    }
+
+    /// Applies corrected records that were set aside by an earlier run, such as quarantined rows,
+    /// returning a receipt for each.
+    ///
+    /// Nothing is applied if a deposit or withdrawal reuses a tx id that has been stored since, or
+    /// that appears more than once in `records`.
+    pub fn reprocess(
+        &mut self,
+        records: Vec<anyhow::Result<Transaction>>,
+    ) -> anyhow::Result<Vec<Receipt>> {
+        let mut seen = HashSet::new();
+        let reused: Vec<String> = records
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .filter(|tx| matches!(tx.tx_type, TxType::Deposit | TxType::Withdrawal))
+            .filter(|tx| {
+                self.index_id(tx.tx_id)
+                    .is_some_and(|index_id| self.transactions.contains(index_id))
+                    || !seen.insert(tx.tx_id)
+            })
+            .map(|tx| tx.tx_id.to_string())
+            .collect();
+        if !reused.is_empty() {
+            return Err(anyhow!(
+                "Tx ids have been used since the records were set aside: {}",
+                reused.join(", ")
+            ));
+        }
+
+        Ok(records
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| self.process_with_receipt(index + 1, result))
+            .collect())
+    }
+
+    /// Marks the start of a new input source, such as the next file. Under
+    /// [`TxIdCollisionPolicy::Namespaced`], later transactions can only reference transactions from
+    /// the same source.
+    pub fn start_source(&mut self) {
+        self.source += 1;
+    }
+
+    /// How long the dispute of `tx_id` has been open, or `None` if it is not currently disputed.
+    pub fn dispute_age(&self, tx_id: TxId) -> Option<Duration> {
+        let index_id = self.index_id(tx_id)?;
+        self.open_disputes.get(&index_id).map(|dispute| {
+            self.clock
+                .now()
+                .duration_since(dispute.opened)
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    /// Lists every dispute that is still open, ordered by client and tx id.
+    pub fn open_disputes(&self) -> anyhow::Result<Vec<DisputeSummary>> {
+        // Namespaced index ids are mapped back to the tx id they were read with.
+        let tx_ids: HashMap<TxId, TxId> = self
+            .namespaced_ids
+            .iter()
+            .map(|(&(_, tx_id), &index_id)| (index_id, tx_id))
+            .collect();
+        let now = self.clock.now();
+
+        let mut disputes = Vec::with_capacity(self.open_disputes.len());
+        for (index_id, dispute) in &self.open_disputes {
+            let stored = match self.stored(*index_id)? {
+                Some(stored) => stored,
+                None => continue,
+            };
+
+            disputes.push(DisputeSummary {
+                client_id: stored.client_id,
+                tx_id: tx_ids.get(index_id).copied().unwrap_or(*index_id),
+                amount: stored.amount,
+                age: now.duration_since(dispute.opened).unwrap_or(Duration::ZERO),
+                metadata: dispute.metadata.clone(),
+            });
+        }
+
+        disputes.sort_by_key(|dispute| (dispute.client_id, dispute.tx_id));
+        Ok(disputes)
+    }
+
+    /// Stored transactions matching `query`, in tx id order, for ad-hoc analysis of the retained
+    /// history.
+    pub fn query(&self, query: &TxQuery) -> anyhow::Result<Vec<(TxId, StoredTx)>> {
+        // Namespaced index ids are mapped back to the tx id they were read with.
+        let tx_ids: HashMap<TxId, TxId> = self
+            .namespaced_ids
+            .iter()
+            .map(|(&(_, tx_id), &index_id)| (index_id, tx_id))
+            .collect();
+
+        let mut matches: Vec<_> = self
+            .transactions
+            .entries()?
+            .into_iter()
+            .map(|(index_id, stored)| {
+                let tx_id = tx_ids.get(&index_id).copied().unwrap_or(index_id);
+                let stored = StoredTx {
+                    client_id: self.resolve_client(stored.client_id),
+                    ..stored
+                };
+                (tx_id, stored)
+            })
+            .filter(|(tx_id, stored)| query.matches(*tx_id, stored))
+            .collect();
+        matches.sort_by_key(|(tx_id, _)| *tx_id);
+        Ok(matches)
+    }
+
+    /// Evidence attached to the open dispute of `tx_id`, or `None` if it is not currently
+    /// disputed.
+    pub fn dispute_metadata(&self, tx_id: TxId) -> Option<&BTreeMap<String, String>> {
+        let index_id = self.index_id(tx_id)?;
+        self.open_disputes
+            .get(&index_id)
+            .map(|dispute| &dispute.metadata)
+    }
+
+    /// Attaches evidence to the open dispute of `tx_id`, replacing any value with the same key.
+    pub fn attach_dispute_metadata(
+        &mut self,
+        tx_id: TxId,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> anyhow::Result<()> {
+        let dispute = self
+            .index_id(tx_id)
+            .and_then(|index_id| self.open_disputes.get_mut(&index_id))
+            .ok_or_else(|| anyhow!("Transaction {} is not disputed", tx_id))?;
+
+        dispute.metadata.insert(key.into(), value.into());
+        Ok(())
+    }
+
+    /// Processing latencies by transaction type, if enabled with
+    /// [`EngineBuilder::record_latencies`].
+    pub fn latencies(&self) -> Option<&LatencyMetrics> {
+        self.latencies.as_ref()
+    }
+
+    /// Number of transactions evicted from the in-memory transaction index.
+    pub fn evicted_transactions(&self) -> usize {
+        self.transactions.evictions()
+    }
+
+    pub fn get_accounts(&self) -> Vec<&Account> {
+        self.accounts.all()
+    }
+
+    /// Whether `acc` was only created implicitly, e.g. by an ignored dispute: it has zero
+    /// balances, is not locked and no transaction was ever applied to it.
+    pub fn is_empty_account(&self, acc: &Account) -> bool {
+        acc.available_amount == Amount::ZERO
+            && acc.held_amount == Amount::ZERO
+            && !acc.is_locked
+            && !self.active_clients.contains(&acc.client_id)
+    }
+
+    /// Returns up to `limit` accounts after the `after` client id; pass the last client id of a
+    /// page to get the next one.
+    pub fn accounts_page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        self.accounts.page(after, limit)
+    }
+
+    pub fn find_accounts(&self, filter: &account::AccountFilter) -> Vec<&Account> {
+        self.accounts.filter(filter)
+    }
+}
+
+fn admin_rejection(err: &anyhow::Error) -> TxOutcome {
+    let code = RejectCode::of(err);
+    error!("[admin] Action failed [{}]: {}", code, err);
+    TxOutcome::Rejected {
+        code,
+        message: err.to_string(),
+    }
+}
+
+/// Transaction index of a simulated engine, holding only the entries copied from the real index.
+#[derive(Default)]
+struct SeededIndex {
+    seen: HashSet<TxId>,
+    transactions: HashMap<TxId, StoredTx>,
+}
+
+impl SeededIndex {
+    /// Copies an entry of the real index, which may have evicted the transaction itself.
+    fn seed(&mut self, index_id: TxId, stored: Option<StoredTx>) {
+        self.seen.insert(index_id);
+        if let Some(stored) = stored {
+            self.transactions.insert(index_id, stored);
+        }
+    }
+}
+
+impl TxIndex for SeededIndex {
+    fn get(&self, tx_id: TxId) -> anyhow::Result<Option<StoredTx>> {
+        Ok(self.transactions.get(&tx_id).copied())
+    }
+
+    fn contains(&self, tx_id: TxId) -> bool {
+        self.seen.contains(&tx_id)
+    }
+
+    fn insert(&mut self, tx_id: TxId, tx: StoredTx) -> anyhow::Result<()> {
+        self.seen.insert(tx_id);
+        self.transactions.insert(tx_id, tx);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.transactions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    #[test]
+    fn deposit_and_withdrawal_integration_test() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        let txs = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(3.0))),
+        ];
+
+        engine.process_all(txs);
+
+        let accounts = engine.get_accounts();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].client_id, 1);
+        assert_eq!(accounts[0].available_amount, dec!(7.0));
+    }
+
+    #[test]
+    fn dispute_integration_test() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        let txs = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+        ];
+
+        engine.process_all(txs);
+
+        let accounts = engine.get_accounts();
+
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].client_id, 1);
+        assert_eq!(accounts[0].available_amount, dec!(5.0));
+        assert_eq!(accounts[0].held_amount, dec!(10.0));
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn chargeback_integration_test() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        let txs = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
             Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
             Ok(Transaction::new(TxType::Chargeback, 1, 1, dec!(0.0))),
             Ok(Transaction::new(TxType::Withdrawal, 1, 3, dec!(1.0))),
@@ -213,6 +2026,1373 @@ mod tests {
         assert_eq!(accounts[0].is_locked, true);
     }
 
+    #[test]
+    fn duplicate_tx_id_is_rejected() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)))
+            .unwrap_err();
+
+        assert_eq!(err.code(), RejectCode::DuplicateTx);
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(10.0));
+    }
+
+    #[test]
+    fn process_all_summarizes_outcomes() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+
+        let summary = engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Err(anyhow!("Malformed record")),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(20.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 9, dec!(0.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 3, dec!(5.0))),
+        ]);
+
+        assert_eq!(summary.applied, 2);
+        assert_eq!(summary.ignored, 1);
+        assert_eq!(summary.rejected, 2);
+        assert_eq!(
+            summary.ignored_by_reason,
+            HashMap::from([(IgnoreReason::TxNotFound, 1)])
+        );
+        assert_eq!(
+            summary.rejected_by_code,
+            HashMap::from([(RejectCode::Unknown, 1), (RejectCode::InsufficientFunds, 1)])
+        );
+        assert_eq!(summary.corrupt_rows, vec![2]);
+    }
+
+    #[test]
+    fn locked_account_rejects_transactions() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+            Ok(Transaction::new(TxType::Chargeback, 1, 1, dec!(0.0))),
+        ]);
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 1, 2, dec!(5.0)))
+            .unwrap_err();
+
+        assert_eq!(err.code(), RejectCode::AccountLocked);
+    }
+
+    #[test]
+    fn process_returns_typed_errors() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+
+        let err = engine
+            .try_process(&Transaction::new(TxType::Withdrawal, 1, 2, dec!(11.0)))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EngineError::Account(AccountError::InsufficientAvailable)
+        ));
+        assert_eq!(err.code(), RejectCode::InsufficientFunds);
+        assert_eq!(err.to_string(), "Available amount is too low");
+
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 1, 1, dec!(1.0)))
+            .unwrap_err();
+        assert!(matches!(err, EngineError::DuplicateTx(1)));
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Deposit, 2, 1, dec!(1.0))),
+            TxOutcome::Rejected {
+                code: RejectCode::DuplicateTx,
+                message: "Transaction 1 has already been processed".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn query_returns_matching_stored_transactions() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(2000.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(1500.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 3, dec!(1200.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 3, dec!(0.0))),
+        ]);
+
+        let query = "amount > 1000 AND state != processed".parse().unwrap();
+        let matches = engine.query(&query).unwrap();
+
+        assert_eq!(
+            matches,
+            vec![(
+                3,
+                StoredTx::new(TxType::Deposit, 2, dec!(1200.0)).with_state(TxState::Disputed)
+            )]
+        );
+        assert_eq!(engine.query(&TxQuery::new()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn dispute_for_other_client_is_rejected() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+        let err = engine
+            .try_process(&Transaction::new(TxType::Dispute, 2, 1, dec!(0.0)))
+            .unwrap_err();
+
+        assert_eq!(err.code(), RejectCode::ClientMismatch);
+    }
+
+    #[test]
+    fn evicted_transactions_cannot_be_disputed() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::builder(accounts).max_stored_transactions(1).build();
+
+        let txs = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 2, dec!(0.0))),
+        ];
+
+        engine.process_all(txs);
+
+        let accounts = engine.get_accounts();
+
+        assert_eq!(engine.evicted_transactions(), 1);
+        assert_eq!(accounts[0].available_amount, dec!(10.0));
+        assert_eq!(accounts[0].held_amount, dec!(5.0));
+    }
+
+    #[test]
+    fn only_disputable_types_are_indexed() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::builder(accounts)
+            .disputable_types(vec![TxType::Deposit])
+            .build();
+
+        let txs = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(4.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 2, dec!(0.0))),
+        ];
+
+        engine.process_all(txs);
+
+        let accounts = engine.get_accounts();
+
+        assert_eq!(engine.transactions.len(), 1);
+        assert_eq!(accounts[0].available_amount, dec!(6.0));
+        assert_eq!(accounts[0].held_amount, dec!(0.0));
+    }
+
+    #[test]
+    fn dispute_lifecycle_is_enforced() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+
+        let resolve = Transaction::new(TxType::Resolve, 1, 1, dec!(0.0));
+        let dispute = Transaction::new(TxType::Dispute, 1, 1, dec!(0.0));
+
+        let err = engine.try_process(&resolve).unwrap_err();
+        assert_eq!(err.code(), RejectCode::InvalidTxState);
+
+        assert!(engine.try_process(&dispute).is_ok());
+        let err = engine.try_process(&dispute).unwrap_err();
+        assert_eq!(err.code(), RejectCode::InvalidTxState);
+
+        assert!(engine.try_process(&resolve).is_ok());
+        let err = engine.try_process(&resolve).unwrap_err();
+        assert_eq!(err.code(), RejectCode::InvalidTxState);
+
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts[0].available_amount, dec!(10.0));
+        assert_eq!(accounts[0].held_amount, dec!(0.0));
+    }
+
+    #[test]
+    fn failed_deposit_is_not_indexed() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(-10.0),
+        ))]);
+
+        assert!(engine.transactions.is_empty());
+    }
+
+    #[test]
+    fn process_distinguishes_applied_ignored_and_rejected() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            TxOutcome::Applied
+        );
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Dispute, 1, 99, dec!(0.0))),
+            TxOutcome::Ignored {
+                reason: IgnoreReason::TxNotFound
+            }
+        );
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Withdrawal, 1, 2, dec!(20.0))),
+            TxOutcome::Rejected {
+                code: RejectCode::InsufficientFunds,
+                message: "Available amount is too low".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn process_with_receipt_returns_balances_after_each_record() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+
+        let receipts: Vec<Receipt> = vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(5.0))),
+            Err(reject(RejectCode::MalformedRecord, "bad record")),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| engine.process_with_receipt(index + 1, result))
+        .collect();
+
+        assert_eq!(receipts.len(), 4);
+        assert_eq!(receipts[0].result.outcome.code(), "APPLIED");
+        assert_eq!(receipts[1].available, Some(dec!(0.0)));
+        assert_eq!(receipts[1].held, Some(dec!(10.0)));
+        assert_eq!(receipts[2].result.outcome.code(), "INSUFFICIENT_FUNDS");
+        assert_eq!(receipts[3].result.row, 4);
+        assert_eq!(receipts[3].result.outcome.code(), "MALFORMED_RECORD");
+        assert_eq!(receipts[3].available, None);
+    }
+
+    #[test]
+    fn process_all_iter_yields_a_result_per_record() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        let results: Vec<TxResult> = engine
+            .process_all_iter(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+                Err(reject(RejectCode::MalformedRecord, "bad record")),
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            ])
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                TxResult {
+                    row: 1,
+                    tx_id: Some(1),
+                    outcome: TxOutcome::Applied,
+                },
+                TxResult {
+                    row: 2,
+                    tx_id: None,
+                    outcome: TxOutcome::Rejected {
+                        code: RejectCode::MalformedRecord,
+                        message: "bad record".to_string(),
+                    },
+                },
+                TxResult {
+                    row: 3,
+                    tx_id: Some(1),
+                    outcome: TxOutcome::Rejected {
+                        code: RejectCode::DuplicateTx,
+                        message: "Transaction 1 has already been processed".to_string(),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn blocked_clients_are_rejected_without_creating_accounts() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .client_access(ClientAccess::Block([2].into()))
+            .build();
+
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            TxOutcome::Applied
+        );
+        assert_eq!(
+            engine
+                .process(&Transaction::new(TxType::Deposit, 2, 2, dec!(10.0)))
+                .code(),
+            "CLIENT_BLOCKED"
+        );
+        assert_eq!(engine.get_accounts().len(), 1);
+    }
+
+    #[test]
+    fn strict_duplicate_detector_rejects_suspected_duplicates() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .duplicate_detector(DuplicateDetector::new(2, 10, true))
+            .build();
+
+        let summary = engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(5.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+        ]);
+
+        assert_eq!(summary.applied, 1);
+        assert_eq!(summary.rejected_by_code[&RejectCode::SuspectedDuplicate], 1);
+        assert!(summary.corrupt_rows.is_empty());
+        assert_eq!(engine.suspects().len(), 1);
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(5.0));
+    }
+
+    #[test]
+    fn open_account_records_metadata() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        let open = Transaction::new(TxType::OpenAccount, 1, 1, dec!(0))
+            .with_metadata("currency=EUR;tier=gold");
+
+        assert_eq!(engine.process(&open), TxOutcome::Applied);
+        assert_eq!(engine.account_metadata(1).unwrap()["tier"], "gold");
+        assert_eq!(engine.get_accounts().len(), 1);
+        assert_eq!(engine.process(&open).code(), "INVALID_TX_STATE");
+    }
+
+    #[test]
+    fn require_open_accounts_rejects_unopened_clients() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .require_open_accounts(true)
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::OpenAccount, 1, 1, dec!(0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(10.0))),
+        ]);
+
+        assert_eq!(
+            engine
+                .process(&Transaction::new(TxType::Deposit, 2, 3, dec!(10.0)))
+                .code(),
+            "ACCOUNT_NOT_FOUND"
+        );
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available_amount, dec!(10.0));
+    }
+
+    #[test]
+    fn disputes_do_not_create_accounts_when_disabled() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .create_accounts_on_disputes(false)
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 1, dec!(0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+        ]);
+
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].held_amount, dec!(10.0));
+    }
+
+    #[test]
+    fn merge_moves_balances_history_and_disputes() {
+        use crate::account::Manager;
+
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 3, dec!(3.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 2, dec!(0))),
+        ]);
+
+        assert_eq!(
+            engine.apply_admin(2, &AdminAction::MergeInto { target: 1 }),
+            TxOutcome::Applied
+        );
+        let target = engine.accounts.account(1).unwrap();
+        assert_eq!(target.available_amount, dec!(13.0));
+        assert_eq!(target.held_amount, dec!(5.0));
+        assert_eq!(
+            engine.accounts.account(2).unwrap().available_amount,
+            dec!(0)
+        );
+
+        // The open dispute and the history of client 2 now belong to client 1.
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Resolve, 1, 2, dec!(0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 3, dec!(0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 4, dec!(1.0))),
+        ]);
+        let target = engine.accounts.account(1).unwrap();
+        assert_eq!(target.available_amount, dec!(16.0));
+        assert_eq!(target.held_amount, dec!(3.0));
+        assert_eq!(engine.open_disputes().unwrap()[0].client_id, 1);
+
+        assert_eq!(
+            engine
+                .apply_admin(2, &AdminAction::MergeInto { target: 1 })
+                .code(),
+            "INVALID_TX_STATE"
+        );
+    }
+
+    #[test]
+    fn balance_history_samples_every_interval() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .balance_history_interval(2)
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 2, dec!(1.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 3, dec!(5.0))),
+            Ok(Transaction::new(TxType::Resolve, 1, 1, dec!(0))),
+        ]);
+
+        assert_eq!(
+            engine.balance_history()[&1],
+            vec![BalanceSample {
+                seq: 3,
+                available: dec!(0),
+                held: dec!(10.0),
+            }]
+        );
+        assert!(!engine.balance_history().contains_key(&2));
+    }
+
+    fn unknown_type_records() -> Vec<anyhow::Result<Transaction>> {
+        vec![
+            Err(reject(
+                RejectCode::UnknownType,
+                "Unknown transaction type refund",
+            )),
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Err(reject(
+                RejectCode::UnknownType,
+                "Unknown transaction type refund",
+            )),
+        ]
+    }
+
+    #[test]
+    fn skipped_unknown_types_are_ignored_and_counted() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .unknown_types(UnknownTypePolicy::Skip)
+            .build();
+
+        let codes: Vec<&str> = engine
+            .process_all_iter(unknown_type_records())
+            .map(|result| result.outcome.code())
+            .collect();
+
+        assert_eq!(codes, vec!["UNKNOWN_TYPE", "APPLIED", "UNKNOWN_TYPE"]);
+        assert!(matches!(
+            engine.process_all_iter(unknown_type_records()).next(),
+            Some(TxResult {
+                outcome: TxOutcome::Ignored {
+                    reason: IgnoreReason::UnknownType
+                },
+                ..
+            })
+        ));
+        assert_eq!(engine.stats().unknown_types, 3);
+    }
+
+    #[test]
+    fn abort_stops_processing_at_the_first_unknown_type() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .unknown_types(UnknownTypePolicy::Abort)
+            .build();
+
+        let results: Vec<TxResult> = engine
+            .process_all_iter(unknown_type_records().into_iter().rev())
+            .collect();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].outcome.code(), "UNKNOWN_TYPE");
+        assert_eq!(engine.abort_reason(), Some("Record 1 has an unknown type"));
+        assert!(engine.get_accounts().is_empty());
+    }
+
+    #[test]
+    fn restored_state_lets_later_runs_settle_earlier_disputes() {
+        let mut first = Engine::new(account::SimpleManager::new());
+        first.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 2, dec!(0))),
+        ]);
+
+        let mut second = Engine::new(account::SimpleManager::new());
+        second.restore(first.state().unwrap()).unwrap();
+        second.process_all(vec![
+            Ok(Transaction::new(TxType::Resolve, 1, 2, dec!(0))),
+            Ok(Transaction::new(TxType::Chargeback, 1, 1, dec!(0))),
+        ]);
+
+        let acc = second.get_accounts()[0];
+        assert_eq!(acc.available_amount, dec!(5));
+        assert_eq!(acc.held_amount, dec!(0));
+        assert!(acc.is_locked);
+        assert_eq!(second.stats().open_disputes, 0);
+    }
+
+    #[test]
+    fn restored_state_keeps_opened_accounts_and_merges() {
+        let strict_engine = || {
+            Engine::builder(account::SimpleManager::new())
+                .require_open_accounts(true)
+                .build()
+        };
+        let mut first = strict_engine();
+        first.process_all(vec![
+            Ok(Transaction::new(TxType::OpenAccount, 1, 1, dec!(0)).with_metadata("tier=gold")),
+            Ok(Transaction::new(TxType::OpenAccount, 2, 2, dec!(0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 3, dec!(5))),
+        ]);
+        assert_eq!(
+            first.apply_admin(2, &AdminAction::MergeInto { target: 1 }),
+            TxOutcome::Applied
+        );
+
+        let mut second = strict_engine();
+        second.restore(first.state().unwrap()).unwrap();
+        second.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 4, dec!(10))),
+            Ok(Transaction::new(TxType::Deposit, 2, 5, dec!(1))),
+        ]);
+
+        assert_eq!(second.account_metadata(1).unwrap()["tier"], "gold");
+        let mut accounts = second.get_accounts();
+        accounts.sort_by_key(|acc| acc.client_id);
+        assert_eq!(accounts[0].available_amount, dec!(16));
+        assert_eq!(accounts[1].available_amount, dec!(0));
+    }
+
+    #[test]
+    fn reprocess_applies_records_with_unused_tx_ids() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10)))]);
+
+        let receipts = engine
+            .reprocess(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5))),
+                Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+            ])
+            .unwrap();
+
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[1].available, Some(dec!(5)));
+        assert_eq!(receipts[1].held, Some(dec!(10)));
+    }
+
+    #[test]
+    fn reprocess_rejects_reused_tx_ids_without_applying_anything() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10)))]);
+
+        let err = engine
+            .reprocess(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5))),
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(5))),
+                Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(1))),
+            ])
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Tx ids have been used since the records were set aside: 1, 2"
+        );
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(10));
+    }
+
+    #[test]
+    fn reprocess_checks_tx_ids_of_the_current_source_under_namespaced_policy() {
+        let mut engine = collision_engine(TxIdCollisionPolicy::Namespaced);
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 10, dec!(10))),
+            Ok(Transaction::new(TxType::Deposit, 1, 11, dec!(10))),
+        ]);
+        engine.start_source();
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 7, dec!(1)))]);
+
+        let err = engine
+            .reprocess(vec![Ok(Transaction::new(TxType::Deposit, 1, 7, dec!(1)))])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tx ids have been used since the records were set aside: 7"
+        );
+
+        let receipts = engine
+            .reprocess(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1)))])
+            .unwrap();
+        assert_eq!(receipts[0].result.outcome, TxOutcome::Applied);
+    }
+
+    #[test]
+    fn restore_rejects_negative_balances() {
+        let mut overdrawn = Account::new(2);
+        overdrawn.available_amount = dec!(-1.5);
+        overdrawn.held_amount = dec!(3);
+        overdrawn.is_locked = true;
+        let mut held = Account::new(1);
+        held.available_amount = dec!(2);
+        held.held_amount = dec!(3);
+        let mut engine = Engine::new(account::SimpleManager::new());
+
+        let err = engine
+            .restore(EngineState {
+                accounts: vec![held.clone(), overdrawn],
+                ..Default::default()
+            })
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Account 2 cannot be restored with a negative balance (-1.5 available, 3 held)"
+        );
+        assert!(engine.get_accounts().is_empty());
+
+        engine
+            .restore(EngineState {
+                accounts: vec![held.clone()],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(engine.get_accounts(), vec![&held]);
+    }
+
+    #[test]
+    fn restore_rejects_existing_accounts() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1)))]);
+        let mut state = engine.state().unwrap();
+        state.accounts.insert(0, Account::new(2));
+
+        let err = engine.restore(state).unwrap_err();
+
+        assert_eq!(err.to_string(), "Account 1 already exists");
+        assert_eq!(engine.get_accounts().len(), 1);
+    }
+
+    #[test]
+    fn abort_on_missing_references_stops_at_unknown_transactions() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .abort_on_missing_references(true)
+            .build();
+
+        let results: Vec<TxResult> = engine
+            .process_all_iter(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1))),
+                Ok(Transaction::new(TxType::Resolve, 1, 7, dec!(0))),
+                Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(1))),
+            ])
+            .collect();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            engine.abort_reason(),
+            Some("Record 2 references transaction 7 which is not stored")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn namespaced_references_to_earlier_runs_do_not_abort() {
+        let namespaced_engine = || {
+            Engine::builder(account::SimpleManager::new())
+                .tx_id_collisions(TxIdCollisionPolicy::Namespaced)
+                .abort_on_missing_references(true)
+                .build()
+        };
+        let mut first = namespaced_engine();
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10)))]);
+        first.start_source();
+        first.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 2, 1, dec!(5))),
+            Ok(Transaction::new(TxType::Dispute, 2, 1, dec!(0))),
+        ]);
+        let mut file = Vec::new();
+        crate::state::write_state(&mut file, &first.state().unwrap()).unwrap();
+
+        let mut second = namespaced_engine();
+        second
+            .restore(crate::state::read_state(file.as_slice()).unwrap())
+            .unwrap();
+        second.process_all(vec![Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0)))]);
+        second.start_source();
+        second.process_all(vec![Ok(Transaction::new(TxType::Resolve, 2, 1, dec!(0)))]);
+
+        assert_eq!(second.abort_reason(), None);
+        let mut accounts = second.get_accounts();
+        accounts.sort_by_key(|acc| acc.client_id);
+        assert_eq!(accounts[0].held_amount, dec!(10));
+        assert_eq!(accounts[1].available_amount, dec!(5));
+        assert_eq!(accounts[1].held_amount, dec!(0));
+    }
+
+    #[test]
+    fn parses_unknown_type_policies() {
+        assert_eq!(
+            "quarantine".parse::<UnknownTypePolicy>().unwrap(),
+            UnknownTypePolicy::Quarantine
+        );
+        assert!("drop".parse::<UnknownTypePolicy>().is_err());
+    }
+
+    #[test]
+    fn dispute_age_uses_engine_clock() {
+        let clock = crate::clock::ManualClock::default();
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .clock(clock.clone())
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+        ]);
+        clock.advance(Duration::from_secs(90));
+
+        assert_eq!(engine.dispute_age(1), Some(Duration::from_secs(90)));
+
+        engine.process_all(vec![Ok(Transaction::new(TxType::Resolve, 1, 1, dec!(0.0)))]);
+
+        assert_eq!(engine.dispute_age(1), None);
+    }
+
+    #[test]
+    fn open_disputes_lists_disputes_with_age() {
+        let clock = crate::clock::ManualClock::default();
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .clock(clock.clone())
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 2, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 3, dec!(1.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 1, dec!(0.0))),
+        ]);
+        clock.advance(Duration::from_secs(60));
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Dispute, 1, 2, dec!(0.0)).with_metadata("case_id=C-7")),
+            Ok(Transaction::new(TxType::Dispute, 1, 3, dec!(0.0))),
+            Ok(Transaction::new(TxType::Resolve, 1, 3, dec!(0.0))),
+        ]);
+
+        let disputes = engine.open_disputes().unwrap();
+
+        assert_eq!(disputes.len(), 2);
+        assert_eq!(disputes[0].client_id, 1);
+        assert_eq!(disputes[0].tx_id, 2);
+        assert_eq!(disputes[0].amount, dec!(5.0));
+        assert_eq!(disputes[0].age, Duration::ZERO);
+        assert_eq!(disputes[0].metadata["case_id"], "C-7");
+        assert_eq!(disputes[1].tx_id, 1);
+        assert_eq!(disputes[1].age, Duration::from_secs(60));
+    }
+
+    fn collision_engine(policy: TxIdCollisionPolicy) -> Engine<account::SimpleManager> {
+        Engine::builder(account::SimpleManager::new())
+            .tx_id_collisions(policy)
+            .build()
+    }
+
+    #[test]
+    fn idempotent_policy_ignores_repeated_transactions() {
+        let mut engine = collision_engine(TxIdCollisionPolicy::Idempotent);
+        let deposit = Transaction::new(TxType::Deposit, 1, 1, dec!(10.0));
+
+        assert_eq!(engine.process(&deposit), TxOutcome::Applied);
+        engine.start_source();
+        assert_eq!(
+            engine.process(&deposit),
+            TxOutcome::Ignored {
+                reason: IgnoreReason::DuplicateTx
+            }
+        );
+
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)))
+            .unwrap_err();
+        assert_eq!(err.code(), RejectCode::DuplicateTx);
+
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(10.0));
+    }
+
+    #[test]
+    fn namespaced_policy_scopes_tx_ids_to_source() {
+        let mut engine = collision_engine(TxIdCollisionPolicy::Namespaced);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 1, 1, dec!(10.0)))
+            .unwrap_err();
+        assert_eq!(err.code(), RejectCode::DuplicateTx);
+
+        engine.start_source();
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 2, 1, dec!(3.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 1, dec!(0.0))),
+        ]);
+
+        let mut accounts = engine.get_accounts();
+        accounts.sort_by_key(|acc| acc.client_id);
+        assert_eq!(accounts[0].available_amount, dec!(10.0));
+        assert_eq!(accounts[0].held_amount, dec!(0.0));
+        assert_eq!(accounts[1].available_amount, dec!(0.0));
+        assert_eq!(accounts[1].held_amount, dec!(3.0));
+    }
+
+    #[test]
+    fn namespaced_ids_survive_restored_state() {
+        let mut first = collision_engine(TxIdCollisionPolicy::Namespaced);
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10)))]);
+        first.start_source();
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 2, 1, dec!(3)))]);
+
+        let mut second = collision_engine(TxIdCollisionPolicy::Namespaced);
+        second.restore(first.state().unwrap()).unwrap();
+        second.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(4))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+        ]);
+
+        assert_eq!(second.state().unwrap().transactions.len(), 3);
+        let mut accounts = second.get_accounts();
+        accounts.sort_by_key(|acc| acc.client_id);
+        assert_eq!(accounts[0].available_amount, dec!(4));
+        assert_eq!(accounts[0].held_amount, dec!(10));
+        assert_eq!(accounts[1].available_amount, dec!(3));
+    }
+
+    #[test]
+    fn restore_rejects_state_saved_under_another_policy() {
+        let mut first = collision_engine(TxIdCollisionPolicy::Namespaced);
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10)))]);
+
+        let mut second = Engine::new(account::SimpleManager::new());
+        let err = second.restore(first.state().unwrap()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "State was saved under the namespaced tx id collision policy, not reject"
+        );
+        assert!(second.get_accounts().is_empty());
+    }
+
+    #[test]
+    fn reprocess_after_a_namespaced_run_uses_the_restored_ids() {
+        let mut first = collision_engine(TxIdCollisionPolicy::Namespaced);
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 5, dec!(10)))]);
+        first.start_source();
+        first.process_all(vec![Ok(Transaction::new(TxType::Deposit, 2, 1, dec!(3)))]);
+
+        let mut second = collision_engine(TxIdCollisionPolicy::Namespaced);
+        second.restore(first.state().unwrap()).unwrap();
+        // Tx 1 is free in the first source, although index id 1 is taken by its tx 5.
+        let receipts = second
+            .reprocess(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(4))),
+                Ok(Transaction::new(TxType::Dispute, 1, 5, dec!(0))),
+            ])
+            .unwrap();
+
+        assert_eq!(receipts[0].result.outcome, TxOutcome::Applied);
+        assert_eq!(receipts[1].available, Some(dec!(4)));
+        assert_eq!(receipts[1].held, Some(dec!(10)));
+        assert_eq!(second.state().unwrap().transactions.len(), 3);
+    }
+
+    #[test]
+    fn reject_policy_rejects_ids_from_earlier_sources() {
+        let mut engine = collision_engine(TxIdCollisionPolicy::Reject);
+
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            1,
+            dec!(10.0),
+        ))]);
+        engine.start_source();
+
+        let err = engine
+            .try_process(&Transaction::new(TxType::Deposit, 2, 1, dec!(10.0)))
+            .unwrap_err();
+        assert_eq!(err.code(), RejectCode::DuplicateTx);
+    }
+
+    #[test]
+    fn stats_track_accounts_transactions_and_open_disputes() {
+        let accounts = account::SimpleManager::new();
+        let mut engine = Engine::new(accounts);
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 3, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 2, dec!(0.0))),
+            Ok(Transaction::new(TxType::Resolve, 2, 2, dec!(0.0))),
+        ]);
+
+        let stats = engine.stats();
+        assert_eq!(stats.accounts, 2);
+        assert_eq!(stats.stored_transactions, 3);
+        assert_eq!(stats.open_disputes, 1);
+        assert!(stats.memory_bytes > 0);
+    }
+
+    #[test]
+    fn records_latencies_by_type_when_enabled() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .record_latencies(true)
+            .build();
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+        ]);
+
+        let latencies = engine.latencies().unwrap();
+        assert_eq!(latencies.histogram(&TxType::Deposit).count(), 2);
+        assert_eq!(latencies.histogram(&TxType::Dispute).count(), 1);
+        assert_eq!(latencies.histogram(&TxType::Resolve).count(), 0);
+        assert!(Engine::new(account::SimpleManager::new())
+            .latencies()
+            .is_none());
+    }
+
+    #[test]
+    fn admin_actions_bypass_account_lock() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+        ]);
+
+        assert_eq!(
+            engine.apply_admin(1, &AdminAction::Lock),
+            TxOutcome::Applied
+        );
+        assert_eq!(
+            engine.apply_admin(1, &AdminAction::ForceResolve { tx_id: 1 }),
+            TxOutcome::Applied
+        );
+        assert_eq!(
+            engine.apply_admin(1, &AdminAction::Adjust { amount: dec!(-2.5) }),
+            TxOutcome::Applied
+        );
+        assert_eq!(
+            engine.apply_admin(1, &AdminAction::Unlock),
+            TxOutcome::Applied
+        );
+
+        let acc = engine.get_accounts()[0];
+        assert_eq!(acc.available_amount, dec!(12.5));
+        assert_eq!(acc.held_amount, dec!(0));
+        assert!(!acc.is_locked);
+        assert_eq!(engine.dispute_age(1), None);
+    }
+
+    #[test]
+    fn admin_adjustment_cannot_overdraw() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1.0)))]);
+
+        let outcome = engine.apply_admin(1, &AdminAction::Adjust { amount: dec!(-2.0) });
+
+        assert!(matches!(
+            outcome,
+            TxOutcome::Rejected {
+                code: RejectCode::InsufficientFunds,
+                ..
+            }
+        ));
+        assert_eq!(
+            engine.apply_admin(2, &AdminAction::Lock),
+            TxOutcome::Rejected {
+                code: RejectCode::AccountNotFound,
+                message: "Account for client 2 not found".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn chargeback_reversal_recredits_amount() {
+        let txs = || {
+            vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+                Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+                Ok(Transaction::new(TxType::Chargeback, 1, 1, dec!(0.0))),
+                Ok(Transaction::new(
+                    TxType::ChargebackReversal,
+                    1,
+                    1,
+                    dec!(0.0),
+                )),
+            ]
+        };
+
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(txs());
+        let acc = engine.get_accounts()[0];
+        assert_eq!(acc.available_amount, dec!(10.0));
+        assert_eq!(acc.held_amount, dec!(0.0));
+        assert!(acc.is_locked);
+        assert!(matches!(
+            engine.process(&Transaction::new(
+                TxType::ChargebackReversal,
+                1,
+                1,
+                dec!(0.0)
+            )),
+            TxOutcome::Rejected {
+                code: RejectCode::InvalidTxState,
+                ..
+            }
+        ));
+
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .unlock_on_chargeback_reversal(true)
+            .build();
+        engine.process_all(txs());
+        assert!(!engine.get_accounts()[0].is_locked);
+    }
+
+    #[test]
+    fn write_off_removes_held_funds_without_locking() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+        ]);
+
+        assert_eq!(
+            engine.apply_admin(1, &AdminAction::WriteOff { tx_id: 1 }),
+            TxOutcome::Applied
+        );
+        assert!(matches!(
+            engine.apply_admin(1, &AdminAction::WriteOff { tx_id: 1 }),
+            TxOutcome::Rejected {
+                code: RejectCode::InvalidTxState,
+                ..
+            }
+        ));
+
+        let acc = engine.get_accounts()[0];
+        assert_eq!(acc.available_amount, dec!(5.0));
+        assert_eq!(acc.held_amount, dec!(0.0));
+        assert!(!acc.is_locked);
+        assert_eq!(engine.stats().open_disputes, 0);
+    }
+
+    #[test]
+    fn close_open_disputes_resolves_all_open_disputes() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 2, dec!(0.0))),
+        ]);
+
+        assert_eq!(
+            engine
+                .close_open_disputes(DisputeResolution::Resolve)
+                .unwrap(),
+            2
+        );
+
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts[0].available_amount, dec!(10.0));
+        assert_eq!(accounts[1].available_amount, dec!(5.0));
+        assert_eq!(accounts[1].held_amount, dec!(0.0));
+        assert_eq!(engine.stats().open_disputes, 0);
+    }
+
+    #[test]
+    fn disputes_carry_metadata_until_closed() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 1, dec!(0.0)).with_metadata("case_id=C-1")),
+        ]);
+
+        engine
+            .attach_dispute_metadata(1, "evidence", "https://example.com/e/1")
+            .unwrap();
+        let metadata = engine.dispute_metadata(1).unwrap();
+        assert_eq!(metadata["case_id"], "C-1");
+        assert_eq!(metadata["evidence"], "https://example.com/e/1");
+
+        engine.process_all(vec![Ok(Transaction::new(TxType::Resolve, 1, 1, dec!(0.0)))]);
+        assert_eq!(engine.dispute_metadata(1), None);
+        assert!(engine.attach_dispute_metadata(1, "case_id", "C-2").is_err());
+    }
+
+    #[test]
+    fn rewards_accrue_on_deposits_and_are_paid_out() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .reward_rate(dec!(0.01))
+            .build();
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(100.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(50.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 3, dec!(20.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 4, dec!(10.0))),
+        ]);
+
+        assert_eq!(engine.rewards()[&1], dec!(1.5));
+        assert_eq!(engine.rewards()[&2], dec!(0.1));
+
+        let paid = engine.pay_out_rewards().paid;
+
+        assert_eq!(paid.len(), 2);
+        assert_eq!(paid[0].client_id, 1);
+        assert_eq!(paid[0].amount, dec!(1.5));
+        assert_eq!(paid[0].trace_id(), "reward-1");
+        assert!(engine.rewards().is_empty());
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts[0].available_amount, dec!(131.5));
+        assert_eq!(accounts[1].available_amount, dec!(10.1));
+    }
+
+    #[test]
+    fn rewards_of_locked_accounts_stay_accrued_and_charged_back_deposits_earn_none() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .reward_rate(dec!(0.01))
+            .build();
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(100.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(50.0))),
+            Ok(Transaction::new(TxType::Dispute, 1, 2, dec!(0))),
+            Ok(Transaction::new(TxType::Chargeback, 1, 2, dec!(0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 3, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 2, 4, dec!(20.0))),
+        ]);
+        engine.compensate(4, "sent in error").unwrap();
+
+        assert_eq!(engine.rewards()[&1], dec!(1.0));
+        assert_eq!(engine.rewards()[&2], dec!(0.1));
+
+        let payout = engine.pay_out_rewards();
+
+        assert_eq!(payout.paid.len(), 1);
+        assert_eq!(payout.paid[0].client_id, 2);
+        assert_eq!(payout.paid[0].amount, dec!(0.1));
+        assert_eq!(engine.rewards().len(), 1);
+        assert_eq!(engine.rewards()[&1], dec!(1.0));
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(100.0));
+    }
+
+    #[test]
+    fn rewards_that_cannot_be_credited_stay_accrued() {
+        let mut full = Account::new(1);
+        full.available_amount = Amount::MAX;
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .reward_rate(dec!(0.01))
+            .build();
+        engine
+            .restore(EngineState {
+                accounts: vec![full],
+                rewards: vec![(1, dec!(1))],
+                ..Default::default()
+            })
+            .unwrap();
+        engine.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            2,
+            1,
+            dec!(100.0),
+        ))]);
+
+        let payout = engine.pay_out_rewards();
+
+        assert_eq!(payout.paid.len(), 1);
+        assert_eq!(payout.paid[0].client_id, 2);
+        assert_eq!(payout.failed.len(), 1);
+        assert_eq!(payout.failed[0].0, 1);
+        assert_eq!(RejectCode::of(&payout.failed[0].1), RejectCode::Overflow);
+        assert_eq!(engine.rewards()[&1], dec!(1));
+        assert_eq!(engine.state().unwrap().rewards, vec![(1, dec!(1))]);
+    }
+
+    #[test]
+    fn rewards_are_paid_out_with_the_rounding_strategy() {
+        for (rounding, expected) in [
+            (Rounding::HalfEven, [dec!(0.02), dec!(0.02)]),
+            (Rounding::HalfUp, [dec!(0.02), dec!(0.03)]),
+            (Rounding::Truncate, [dec!(0.01), dec!(0.02)]),
+        ] {
+            let mut engine = Engine::builder(account::SimpleManager::new())
+                .reward_rate(dec!(0.01))
+                .rounding(rounding)
+                .build();
+            engine.process_all(vec![
+                Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1.5))),
+                Ok(Transaction::new(TxType::Deposit, 2, 2, dec!(2.5))),
+            ]);
+
+            let paid: Vec<_> = engine
+                .pay_out_rewards()
+                .paid
+                .iter()
+                .map(|tx| tx.amount)
+                .collect();
+
+            assert_eq!(paid, expected, "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn empty_accounts_have_no_applied_transactions() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(5.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(5.0))),
+            Ok(Transaction::new(TxType::Dispute, 2, 99, dec!(0))),
+            Ok(Transaction::new(TxType::Deposit, 3, 3, dec!(1.0))),
+        ]);
+
+        let empty: Vec<_> = engine
+            .get_accounts()
+            .into_iter()
+            .filter(|acc| engine.is_empty_account(acc))
+            .map(|acc| acc.client_id)
+            .collect();
+
+        assert_eq!(empty, vec![2]);
+    }
+
+    #[test]
+    fn compensate_applies_inverse_transaction() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Withdrawal, 1, 2, dec!(4.0))),
+        ]);
+
+        let inverse = engine.compensate(2, "duplicate payout").unwrap();
+        assert_eq!(inverse.tx_type, TxType::Deposit);
+        assert_eq!(inverse.tx_id, 2);
+        assert_eq!(inverse.trace_id(), "compensate-2");
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(10.0));
+
+        engine.compensate(1, "test deposit").unwrap();
+        assert_eq!(engine.get_accounts()[0].available_amount, dec!(0.0));
+
+        let err = engine.compensate(1, "again").unwrap_err();
+        assert_eq!(RejectCode::of(&err), RejectCode::InvalidTxState);
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Dispute, 1, 1, dec!(0))),
+            TxOutcome::Rejected {
+                code: RejectCode::InvalidTxState,
+                message: "Cannot dispute transaction 1 as it is compensated but must be processed"
+                    .to_string(),
+            }
+        );
+        assert!(engine.compensate(3, "unknown").is_err());
+    }
+
+    #[test]
+    fn simulate_rejects_suspected_duplicates_without_recording_them() {
+        let mut engine = Engine::builder(account::SimpleManager::new())
+            .duplicate_detector(DuplicateDetector::new(2, 10, true))
+            .build();
+        engine.process_all(vec![Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(5.0)))]);
+        let repeat = Transaction::new(TxType::Deposit, 1, 2, dec!(5.0));
+
+        let simulation = engine.simulate(&repeat);
+
+        assert_eq!(simulation.outcome.code(), "SUSPECTED_DUPLICATE");
+        assert_eq!(simulation.account.unwrap().available_amount, dec!(5.0));
+        assert!(engine.suspects().is_empty());
+        assert_eq!(engine.process(&repeat).code(), "SUSPECTED_DUPLICATE");
+    }
+
+    #[test]
+    fn simulate_reports_outcome_without_changing_state() {
+        let mut engine = Engine::new(account::SimpleManager::new());
+        engine.process_all(vec![
+            Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(10.0))),
+            Ok(Transaction::new(TxType::Deposit, 1, 2, dec!(5.0))),
+        ]);
+
+        let simulation = engine.simulate(&Transaction::new(TxType::Withdrawal, 1, 3, dec!(4.0)));
+        assert_eq!(simulation.outcome, TxOutcome::Applied);
+        assert_eq!(simulation.account.unwrap().available_amount, dec!(11.0));
+
+        let simulation = engine.simulate(&Transaction::new(TxType::Withdrawal, 1, 3, dec!(20.0)));
+        assert_eq!(simulation.outcome.code(), "INSUFFICIENT_FUNDS");
+        assert_eq!(simulation.account.unwrap().available_amount, dec!(15.0));
+
+        let simulation = engine.simulate(&Transaction::new(TxType::Dispute, 1, 2, dec!(0.0)));
+        assert_eq!(simulation.outcome, TxOutcome::Applied);
+        assert_eq!(simulation.account.unwrap().held_amount, dec!(5.0));
+
+        let simulation = engine.simulate(&Transaction::new(TxType::Deposit, 2, 1, dec!(1.0)));
+        assert!(matches!(simulation.outcome, TxOutcome::Rejected { .. }));
+
+        let accounts = engine.get_accounts();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available_amount, dec!(15.0));
+        assert_eq!(accounts[0].held_amount, dec!(0.0));
+        assert_eq!(engine.stats().stored_transactions, 2);
+        assert_eq!(
+            engine.process(&Transaction::new(TxType::Withdrawal, 1, 3, dec!(4.0))),
+            TxOutcome::Applied
+        );
+    }
+
     #[test]
     fn complex_integration_test() {
         let accounts = account::SimpleManager::new();
@@ -0,0 +1,262 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+
+use crate::types::{ClientId, Transaction};
+
+/// A pipeline stage run on each transaction between the reader and the engine, to handle quirks of
+/// the input such as inconsistent codes or external ids outside of the engine.
+pub trait Enricher {
+    /// Updates the transaction in place. An error rejects the record.
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()>;
+}
+
+impl<F: FnMut(&mut Transaction) -> anyhow::Result<()>> Enricher for F {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        self(tx)
+    }
+}
+
+impl Enricher for Box<dyn Enricher> {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        self.as_mut().enrich(tx)
+    }
+}
+
+/// Runs enrichers in the order they were added, stopping at the first error.
+#[derive(Default)]
+pub struct EnricherChain {
+    stages: Vec<Box<dyn Enricher>>,
+}
+
+impl EnricherChain {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with(mut self, stage: impl Enricher + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Enriches a record read from the input, passing read errors through unchanged.
+    pub fn apply(&mut self, result: anyhow::Result<Transaction>) -> anyhow::Result<Transaction> {
+        let mut tx = result?;
+        self.enrich(&mut tx)?;
+        Ok(tx)
+    }
+}
+
+impl Enricher for EnricherChain {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        self.stages
+            .iter_mut()
+            .try_for_each(|stage| stage.enrich(tx))
+    }
+}
+
+/// Sets a metadata entry on every transaction that does not already have it.
+pub struct AttachMetadata {
+    pub key: String,
+    pub value: String,
+}
+
+impl Enricher for AttachMetadata {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        let mut entries = tx.metadata_entries()?;
+        entries
+            .entry(self.key.clone())
+            .or_insert_with(|| self.value.clone());
+        set_metadata(tx, &entries);
+        Ok(())
+    }
+}
+
+/// Upper-cases the value of a metadata entry, such as a currency code.
+pub struct UppercaseMetadata {
+    pub key: String,
+}
+
+impl Enricher for UppercaseMetadata {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        let mut entries = tx.metadata_entries()?;
+        if let Some(value) = entries.get_mut(&self.key) {
+            *value = value.to_uppercase();
+            set_metadata(tx, &entries);
+        }
+        Ok(())
+    }
+}
+
+/// Replaces client ids found in the map, leaving other clients unchanged.
+pub struct MapClients {
+    pub clients: HashMap<ClientId, ClientId>,
+}
+
+impl MapClients {
+    /// Loads a map from a file with one `from,to` pair of client ids per line, skipping blank
+    /// lines and `#` comments.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::read(File::open(path)?)
+    }
+
+    pub fn read(r: impl io::Read) -> anyhow::Result<Self> {
+        let mut clients = HashMap::new();
+
+        for (index, line) in BufReader::new(r).lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || anyhow!("Invalid client mapping {} on line {}", line, index + 1);
+            let (from, to) = line.split_once(',').ok_or_else(invalid)?;
+            let from = from.trim().parse().map_err(|_| invalid())?;
+            let to = to.trim().parse().map_err(|_| invalid())?;
+            clients.insert(from, to);
+        }
+
+        Ok(Self { clients })
+    }
+}
+
+impl Enricher for MapClients {
+    fn enrich(&mut self, tx: &mut Transaction) -> anyhow::Result<()> {
+        if let Some(client_id) = self.clients.get(&tx.client_id) {
+            tx.client_id = *client_id;
+        }
+        Ok(())
+    }
+}
+
+fn set_metadata(tx: &mut Transaction, entries: &BTreeMap<String, String>) {
+    let metadata: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    tx.metadata = Some(metadata.join(";"));
+}
+
+/// A built-in enricher as given on the command line: `metadata:KEY=VALUE`, `uppercase:KEY` or
+/// `client-map:FILE`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnricherSpec {
+    Metadata { key: String, value: String },
+    Uppercase { key: String },
+    ClientMap { path: String },
+}
+
+impl EnricherSpec {
+    pub fn build(&self) -> anyhow::Result<Box<dyn Enricher>> {
+        Ok(match self {
+            EnricherSpec::Metadata { key, value } => Box::new(AttachMetadata {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            EnricherSpec::Uppercase { key } => Box::new(UppercaseMetadata { key: key.clone() }),
+            EnricherSpec::ClientMap { path } => Box::new(MapClients::load(path)?),
+        })
+    }
+}
+
+impl FromStr for EnricherSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("Invalid enricher {}", s);
+        let (name, arg) = s.split_once(':').ok_or_else(invalid)?;
+        match name {
+            "metadata" => {
+                let (key, value) = arg.split_once('=').ok_or_else(invalid)?;
+                Ok(EnricherSpec::Metadata {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                })
+            }
+            "uppercase" => Ok(EnricherSpec::Uppercase {
+                key: arg.to_string(),
+            }),
+            "client-map" => Ok(EnricherSpec::ClientMap {
+                path: arg.to_string(),
+            }),
+            _ => Err(anyhow!("Unknown enricher {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::dec, types::TxType};
+
+    #[test]
+    fn runs_stages_in_order() {
+        let mut chain = EnricherChain::new()
+            .with(UppercaseMetadata {
+                key: "currency".to_string(),
+            })
+            .with(AttachMetadata {
+                key: "currency".to_string(),
+                value: "gbp".to_string(),
+            })
+            .with(MapClients {
+                clients: HashMap::from([(1, 9)]),
+            });
+
+        let tx = chain
+            .apply(Ok(Transaction::new(TxType::OpenAccount, 1, 1, dec!(0))))
+            .unwrap();
+
+        // The default was attached after upper-casing, so it keeps its case.
+        assert_eq!(tx.metadata.as_deref(), Some("currency=gbp"));
+        assert_eq!(tx.client_id, 9);
+    }
+
+    #[test]
+    fn errors_reject_the_record() {
+        let mut chain =
+            EnricherChain::new().with(|_: &mut Transaction| Err(anyhow!("unsupported currency")));
+
+        let result = chain.apply(Ok(Transaction::new(TxType::Deposit, 1, 1, dec!(1))));
+
+        assert_eq!(result.unwrap_err().to_string(), "unsupported currency");
+    }
+
+    #[test]
+    fn parses_enricher_specs() {
+        assert_eq!(
+            "metadata:source=bank-a".parse::<EnricherSpec>().unwrap(),
+            EnricherSpec::Metadata {
+                key: "source".to_string(),
+                value: "bank-a".to_string()
+            }
+        );
+        assert_eq!(
+            "uppercase:currency".parse::<EnricherSpec>().unwrap(),
+            EnricherSpec::Uppercase {
+                key: "currency".to_string()
+            }
+        );
+        assert!("metadata:source".parse::<EnricherSpec>().is_err());
+        assert!("rename:x".parse::<EnricherSpec>().is_err());
+    }
+
+    #[test]
+    fn reads_client_mappings() {
+        let map = MapClients::read("# merged clients\n1, 9\n\n2,8\n".as_bytes()).unwrap();
+
+        assert_eq!(map.clients, HashMap::from([(1, 9), (2, 8)]));
+        assert!(MapClients::read("1\n".as_bytes()).is_err());
+    }
+}
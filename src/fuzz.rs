@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    account::SimpleManager,
+    amount::Amount,
+    engine::Engine,
+    types::{Account, ClientId, Transaction, TxType},
+};
+
+const CLIENTS: ClientId = 10;
+
+#[derive(Debug, PartialEq)]
+pub struct FuzzReport {
+    pub seed: u64,
+    pub rows: usize,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+}
+
+/// Generates a reproducible mix of valid and invalid transactions for `seed`.
+///
+/// Invalid rows include corrupt records, negative amounts, overdrawing withdrawals and disputes
+/// referencing unknown transactions or the wrong client.
+pub fn generate(seed: u64, rows: usize) -> Vec<anyhow::Result<Transaction>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut next_tx_id = 1;
+
+    (0..rows)
+        .map(|_| {
+            let client_id = rng.gen_range(1..=CLIENTS);
+            let amount = Amount::new(rng.gen_range(-1_000..1_000_000), 4);
+
+            let tx_type = match rng.gen_range(0..100) {
+                0..=39 => TxType::Deposit,
+                40..=64 => TxType::Withdrawal,
+                65..=79 => TxType::Dispute,
+                80..=89 => TxType::Resolve,
+                90..=96 => TxType::Chargeback,
+                _ => return Err(anyhow!("Corrupt record")),
+            };
+
+            let tx_id = match tx_type {
+                TxType::Deposit | TxType::Withdrawal => {
+                    next_tx_id += 1;
+                    next_tx_id - 1
+                }
+                _ => rng.gen_range(1..next_tx_id + 5),
+            };
+
+            Ok(Transaction::new(tx_type, client_id, tx_id, amount))
+        })
+        .collect()
+}
+
+/// Processes generated transactions one at a time, checking the core account invariants after
+/// each row. Errors include the seed so a failing run can be reproduced.
+pub fn fuzz_run(seed: u64, rows: usize) -> anyhow::Result<FuzzReport> {
+    let mut engine = Engine::new(SimpleManager::new());
+    let mut locked = HashSet::new();
+
+    for (row, tx) in generate(seed, rows).into_iter().enumerate() {
+        engine.process_all(std::iter::once(tx));
+
+        check_invariants(engine.get_accounts(), &mut locked).map_err(|err| {
+            anyhow!(
+                "Invariant violated at row {} with seed {}: {}",
+                row + 1,
+                seed,
+                err
+            )
+        })?;
+    }
+
+    Ok(FuzzReport {
+        seed,
+        rows,
+        accounts: engine.get_accounts().len(),
+        locked_accounts: locked.len(),
+    })
+}
+
+fn check_invariants(accounts: Vec<&Account>, locked: &mut HashSet<ClientId>) -> anyhow::Result<()> {
+    for acc in accounts {
+        if acc.held_amount < Amount::ZERO {
+            return Err(anyhow!("client {} has negative held amount", acc.client_id));
+        }
+
+        if acc.available_amount < Amount::ZERO {
+            return Err(anyhow!(
+                "client {} has negative available amount",
+                acc.client_id
+            ));
+        }
+
+        if acc.is_locked {
+            locked.insert(acc.client_id);
+        } else if locked.contains(&acc.client_id) {
+            return Err(anyhow!("client {} was unlocked", acc.client_id));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_is_reproducible_for_a_seed() {
+        let describe = |seed| {
+            generate(seed, 100)
+                .into_iter()
+                .map(|result| format!("{:?}", result.ok()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(describe(3), describe(3));
+        assert_ne!(describe(3), describe(4));
+    }
+
+    #[test]
+    fn fuzz_run_holds_invariants() {
+        for seed in 0..20 {
+            let report = fuzz_run(seed, 500).unwrap();
+            assert_eq!(report.seed, seed);
+            assert_eq!(report.rows, 500);
+        }
+    }
+
+    #[test]
+    fn check_invariants_detects_unlocked_account() {
+        let acc = Account::new(1);
+        let mut locked = HashSet::from([1]);
+
+        let err = check_invariants(vec![&acc], &mut locked).err().unwrap();
+
+        assert_eq!(err.to_string(), "client 1 was unlocked");
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    amount::Amount,
+    engine::TxOutcome,
+    types::{ClientId, Transaction, TxId, TxType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    GraphMl,
+}
+
+impl FromStr for GraphFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::GraphMl),
+            _ => Err(anyhow!("Unknown graph format {}", s)),
+        }
+    }
+}
+
+struct TxNode {
+    tx_type: TxType,
+    client_id: ClientId,
+    amount: Amount,
+}
+
+struct DisputeEdge {
+    tx_type: TxType,
+    client_id: ClientId,
+    tx_id: TxId,
+}
+
+/// Collects the relationships between clients, transactions and disputes seen during a run so
+/// they can be exported for visualisation.
+///
+/// Deposits and withdrawals become transaction nodes linked to their client's account node.
+/// Disputes, resolves and chargebacks become edges from the client to the originating transaction.
+/// Only transactions the engine applied are recorded, so rejected and ignored records never
+/// appear.
+#[derive(Default)]
+pub struct TxGraph {
+    clients: BTreeSet<ClientId>,
+    transactions: BTreeMap<TxId, TxNode>,
+    disputes: Vec<DisputeEdge>,
+}
+
+impl TxGraph {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records `tx` if its `outcome` is [`TxOutcome::Applied`].
+    pub fn record(&mut self, tx: &Transaction, outcome: &TxOutcome) {
+        if *outcome != TxOutcome::Applied {
+            return;
+        }
+
+        self.clients.insert(tx.client_id);
+
+        match tx.tx_type {
+            TxType::Deposit | TxType::Withdrawal => {
+                self.transactions.insert(
+                    tx.tx_id,
+                    TxNode {
+                        tx_type: tx.tx_type,
+                        client_id: tx.client_id,
+                        amount: tx.amount,
+                    },
+                );
+            }
+            TxType::Dispute | TxType::Resolve | TxType::Chargeback | TxType::ChargebackReversal => {
+                if self.transactions.contains_key(&tx.tx_id) {
+                    self.disputes.push(DisputeEdge {
+                        tx_type: tx.tx_type,
+                        client_id: tx.client_id,
+                        tx_id: tx.tx_id,
+                    });
+                }
+            }
+            TxType::OpenAccount => {}
+        }
+    }
+
+    pub fn write(&self, format: GraphFormat, w: &mut impl Write) -> io::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(w),
+            GraphFormat::GraphMl => self.write_graphml(w),
+        }
+    }
+
+    fn write_dot(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "digraph transactions {{")?;
+
+        for client_id in &self.clients {
+            writeln!(
+                w,
+                "  client_{} [shape=box, label=\"client {}\"];",
+                client_id, client_id
+            )?;
+        }
+
+        for (tx_id, node) in &self.transactions {
+            writeln!(
+                w,
+                "  tx_{} [label=\"{} {} {}\"];",
+                tx_id,
+                node.tx_type.as_str(),
+                tx_id,
+                node.amount
+            )?;
+            writeln!(w, "  tx_{} -> client_{};", tx_id, node.client_id)?;
+        }
+
+        for edge in &self.disputes {
+            writeln!(
+                w,
+                "  client_{} -> tx_{} [style=dashed, label=\"{}\"];",
+                edge.client_id,
+                edge.tx_id,
+                edge.tx_type.as_str()
+            )?;
+        }
+
+        writeln!(w, "}}")
+    }
+
+    fn write_graphml(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            w,
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+        )?;
+        writeln!(
+            w,
+            "  <key id=\"type\" for=\"all\" attr.name=\"type\" attr.type=\"string\"/>"
+        )?;
+        writeln!(
+            w,
+            "  <key id=\"amount\" for=\"node\" attr.name=\"amount\" attr.type=\"string\"/>"
+        )?;
+        writeln!(w, "  <graph id=\"transactions\" edgedefault=\"directed\">")?;
+
+        for client_id in &self.clients {
+            writeln!(
+                w,
+                "    <node id=\"client_{}\"><data key=\"type\">client</data></node>",
+                client_id
+            )?;
+        }
+
+        for (tx_id, node) in &self.transactions {
+            writeln!(
+                w,
+                "    <node id=\"tx_{}\"><data key=\"type\">{}</data><data key=\"amount\">{}</data></node>",
+                tx_id,
+                node.tx_type.as_str(),
+                node.amount
+            )?;
+            writeln!(
+                w,
+                "    <edge source=\"tx_{}\" target=\"client_{}\"/>",
+                tx_id, node.client_id
+            )?;
+        }
+
+        for edge in &self.disputes {
+            writeln!(
+                w,
+                "    <edge source=\"client_{}\" target=\"tx_{}\"><data key=\"type\">{}</data></edge>",
+                edge.client_id,
+                edge.tx_id,
+                edge.tx_type.as_str()
+            )?;
+        }
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::dec, rejection::RejectCode};
+
+    fn sample_graph() -> TxGraph {
+        let rejected = TxOutcome::Rejected {
+            code: RejectCode::ClientMismatch,
+            message: "The transaction 1 does not belong to client 2".to_string(),
+        };
+        let mut graph = TxGraph::new();
+        graph.record(
+            &Transaction::new(TxType::Deposit, 1, 1, dec!(10.0)),
+            &TxOutcome::Applied,
+        );
+        graph.record(
+            &Transaction::new(TxType::Withdrawal, 1, 2, dec!(3.0)),
+            &TxOutcome::Applied,
+        );
+        graph.record(
+            &Transaction::new(TxType::Dispute, 1, 1, dec!(0.0)),
+            &TxOutcome::Applied,
+        );
+        graph.record(
+            &Transaction::new(TxType::Deposit, 2, 3, dec!(5.0)),
+            &TxOutcome::Applied,
+        );
+        graph.record(
+            &Transaction::new(TxType::Dispute, 2, 1, dec!(0.0)),
+            &rejected,
+        );
+        graph.record(
+            &Transaction::new(TxType::Withdrawal, 3, 4, dec!(1.0)),
+            &TxOutcome::Rejected {
+                code: RejectCode::InsufficientFunds,
+                message: "Available amount is too low".to_string(),
+            },
+        );
+        graph
+    }
+
+    #[test]
+    fn parse_graph_format() {
+        assert_eq!("dot".parse::<GraphFormat>().unwrap(), GraphFormat::Dot);
+        assert_eq!(
+            "graphml".parse::<GraphFormat>().unwrap(),
+            GraphFormat::GraphMl
+        );
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+
+    #[test]
+    fn record_ignores_transactions_that_were_not_applied() {
+        let graph = sample_graph();
+
+        assert_eq!(graph.clients.len(), 2);
+        assert_eq!(graph.transactions.len(), 3);
+        assert_eq!(graph.disputes.len(), 1);
+        assert_eq!(graph.disputes[0].client_id, 1);
+    }
+
+    #[test]
+    fn write_dot_links_transactions_and_disputes() {
+        let mut out = Vec::new();
+        sample_graph().write(GraphFormat::Dot, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("digraph transactions {"));
+        assert!(out.contains("  tx_1 -> client_1;"));
+        assert!(out.contains("  tx_2 -> client_1;"));
+        assert!(out.contains("  client_1 -> tx_1 [style=dashed, label=\"dispute\"];"));
+        assert!(!out.contains("tx_4"));
+        assert!(!out.contains("client_2 -> tx_1"));
+    }
+
+    #[test]
+    fn write_graphml_links_transactions_and_disputes() {
+        let mut out = Vec::new();
+        sample_graph()
+            .write(GraphFormat::GraphMl, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("<node id=\"client_2\">"));
+        assert!(out.contains("<edge source=\"tx_1\" target=\"client_1\"/>"));
+        assert!(out.contains(
+            "<edge source=\"client_1\" target=\"tx_1\"><data key=\"type\">dispute</data></edge>"
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    sync::{
+        mpsc::{self, SyncSender, TryRecvError},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    account,
+    engine::Engine,
+    types::{Account, ClientId, Transaction},
+};
+
+/// Processed transactions after which a snapshot is published even if the queue never empties.
+const PUBLISH_INTERVAL: u64 = 1000;
+
+/// Submits transactions to an [`EngineHandle`]. Cheap to clone, so each producer thread can
+/// have its own.
+#[derive(Clone)]
+pub struct Submitter {
+    sender: SyncSender<Transaction>,
+}
+
+impl Submitter {
+    /// Queues a transaction, blocking while the queue is full. Fails if the engine has stopped.
+    pub fn submit(&self, tx: Transaction) -> anyhow::Result<()> {
+        self.sender
+            .send(tx)
+            .map_err(|_| anyhow!("The engine is no longer running"))
+    }
+}
+
+/// The accounts as they were between two transactions.
+#[derive(Debug, Default)]
+pub struct AccountSnapshot {
+    /// Accounts in client id order.
+    pub accounts: Vec<Account>,
+    /// Transactions processed before the snapshot was taken.
+    pub processed: u64,
+}
+
+impl AccountSnapshot {
+    pub fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts
+            .binary_search_by_key(&client_id, |acc| acc.client_id)
+            .ok()
+            .map(|index| &self.accounts[index])
+    }
+}
+
+/// Read-only access to the accounts of an [`EngineHandle`] while it keeps processing, e.g. for
+/// API queries or dashboards. Cheap to clone, so each reader thread can have its own.
+///
+/// The engine thread publishes a new snapshot whenever its queue runs dry and at least every
+/// 1000 transactions. Readers never wait for processing, and a snapshot they hold never changes.
+#[derive(Clone, Default)]
+pub struct AccountView {
+    published: Arc<RwLock<Arc<AccountSnapshot>>>,
+}
+
+impl AccountView {
+    /// Returns the latest published snapshot.
+    pub fn snapshot(&self) -> Arc<AccountSnapshot> {
+        match self.published.read() {
+            Ok(published) => published.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn publish<A: account::Manager>(&self, engine: &Engine<A>, processed: u64) {
+        let mut accounts: Vec<Account> = engine.get_accounts().into_iter().cloned().collect();
+        accounts.sort_by_key(|acc| acc.client_id);
+        let snapshot = Arc::new(AccountSnapshot {
+            accounts,
+            processed,
+        });
+
+        match self.published.write() {
+            Ok(mut published) => *published = snapshot,
+            Err(poisoned) => *poisoned.into_inner() = snapshot,
+        }
+    }
+}
+
+/// Runs an engine on its own thread, fed through a bounded queue by any number of producer
+/// threads.
+///
+/// Transactions are processed one at a time in the order they were queued, so transactions for a
+/// client keep their order as long as each client is fed by a single producer.
+pub struct EngineHandle {
+    submitter: Submitter,
+    view: AccountView,
+    worker: JoinHandle<Vec<Account>>,
+}
+
+impl EngineHandle {
+    /// Starts the processing loop with the engine returned by `build`, which is called on the
+    /// engine thread. At most `capacity` transactions are queued before producers block.
+    pub fn spawn<A, F>(capacity: usize, build: F) -> Self
+    where
+        A: account::Manager,
+        F: FnOnce() -> Engine<A> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<Transaction>(capacity);
+        let view = AccountView::default();
+
+        let publisher = view.clone();
+        let worker = thread::spawn(move || {
+            let mut engine = build();
+            let (mut processed, mut published) = (0, 0);
+            publisher.publish(&engine, processed);
+            loop {
+                let tx = match receiver.try_recv() {
+                    Ok(tx) => tx,
+                    Err(TryRecvError::Empty) => {
+                        if processed > published {
+                            publisher.publish(&engine, processed);
+                            published = processed;
+                        }
+                        match receiver.recv() {
+                            Ok(tx) => tx,
+                            Err(_) => break,
+                        }
+                    }
+                    Err(TryRecvError::Disconnected) => break,
+                };
+
+                engine.process(&tx);
+                processed += 1;
+                if processed - published >= PUBLISH_INTERVAL {
+                    publisher.publish(&engine, processed);
+                    published = processed;
+                }
+            }
+            publisher.publish(&engine, processed);
+            engine.get_accounts().into_iter().cloned().collect()
+        });
+
+        Self {
+            submitter: Submitter { sender },
+            view,
+            worker,
+        }
+    }
+
+    /// Returns a view of the accounts that can be read from other threads during processing.
+    pub fn view(&self) -> AccountView {
+        self.view.clone()
+    }
+
+    pub fn submitter(&self) -> Submitter {
+        self.submitter.clone()
+    }
+
+    pub fn submit(&self, tx: Transaction) -> anyhow::Result<()> {
+        self.submitter.submit(tx)
+    }
+
+    /// Processes the queued transactions and returns the final accounts once every
+    /// [`Submitter`] has been dropped.
+    pub fn finish(self) -> anyhow::Result<Vec<Account>> {
+        drop(self.submitter);
+        self.worker
+            .join()
+            .map_err(|_| anyhow!("The engine thread panicked"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account::SimpleManager, amount::dec, types::TxType};
+
+    #[test]
+    fn processes_transactions_from_several_producers() {
+        let handle = EngineHandle::spawn(16, || Engine::new(SimpleManager::new()));
+
+        let producers: Vec<_> = (1u64..=4)
+            .zip(1..)
+            .map(|(producer, client_id)| {
+                let submitter = handle.submitter();
+                thread::spawn(move || {
+                    for n in 0..100 {
+                        let tx_id = producer * 1000 + n;
+                        submitter
+                            .submit(Transaction::new(TxType::Deposit, client_id, tx_id, dec!(1)))
+                            .unwrap();
+                    }
+                    // A withdrawal only succeeds if it is processed after this producer's deposits.
+                    submitter
+                        .submit(Transaction::new(
+                            TxType::Withdrawal,
+                            client_id,
+                            producer * 1000 + 999,
+                            dec!(100),
+                        ))
+                        .unwrap();
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let accounts = handle.finish().unwrap();
+        assert_eq!(accounts.len(), 4);
+        assert!(accounts.iter().all(|acc| acc.available_amount == dec!(0)));
+    }
+
+    #[test]
+    fn view_publishes_snapshots_during_processing() {
+        let handle = EngineHandle::spawn(16, || Engine::new(SimpleManager::new()));
+        let view = handle.view();
+        assert!(view.snapshot().accounts.is_empty());
+
+        for tx_id in 1..=10 {
+            handle
+                .submit(Transaction::new(TxType::Deposit, 1, tx_id, dec!(1)))
+                .unwrap();
+        }
+        let mut snapshot = view.snapshot();
+        while snapshot.processed < 10 {
+            thread::yield_now();
+            snapshot = view.snapshot();
+        }
+        assert_eq!(snapshot.account(1).unwrap().available_amount, dec!(10));
+        assert!(snapshot.account(2).is_none());
+
+        handle
+            .submit(Transaction::new(TxType::Deposit, 2, 11, dec!(5)))
+            .unwrap();
+        let accounts = handle.finish().unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(snapshot.accounts.len(), 1);
+        assert_eq!(view.snapshot().processed, 11);
+        assert_eq!(
+            view.snapshot().account(2).unwrap().available_amount,
+            dec!(5)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+/// A source of transaction CSV data: a file, stdin, or a memory-mapped file.
+pub enum Input {
+    Stream(Box<dyn Read>),
+    /// Reads directly from the page cache without copying through a read buffer.
+    #[cfg(feature = "mmap")]
+    Mapped(Mmap),
+}
+
+impl Input {
+    /// Opens a file for buffered reading, or stdin if `path` is `-`.
+    pub fn open(path: &str) -> io::Result<Self> {
+        match path {
+            "-" => Ok(Input::Stream(Box::new(io::stdin()))),
+            _ => Ok(Input::Stream(Box::new(File::open(path)?))),
+        }
+    }
+
+    /// Memory-maps a file, falling back to [`Input::open`] for stdin, pipes and other files that
+    /// cannot be mapped.
+    ///
+    /// The file must not be truncated while it is mapped.
+    #[cfg(feature = "mmap")]
+    pub fn map(path: &str) -> io::Result<Self> {
+        if path == "-" {
+            return Self::open(path);
+        }
+
+        let file = File::open(path)?;
+        if !file.metadata()?.is_file() {
+            return Ok(Input::Stream(Box::new(file)));
+        }
+
+        // Safety: the mapping is only read, and callers are told not to modify the file while
+        // it is being processed.
+        match unsafe { Mmap::map(&file) } {
+            Ok(mmap) => Ok(Input::Mapped(mmap)),
+            Err(err) => {
+                log::info!("Could not map {}, reading it instead: {}", path, err);
+                Ok(Input::Stream(Box::new(file)))
+            }
+        }
+    }
+
+    /// Opens a file for reading with io_uring, falling back to [`Input::open`] for stdin, other
+    /// platforms and kernels where io_uring is unavailable.
+    #[cfg(feature = "io-uring")]
+    pub fn uring(path: &str) -> io::Result<Self> {
+        #[cfg(target_os = "linux")]
+        if path != "-" {
+            match crate::uring::UringReader::open(File::open(path)?) {
+                Ok(reader) => return Ok(Input::Stream(Box::new(reader))),
+                Err(err) => log::info!(
+                    "Could not use io_uring for {}, reading it instead: {}",
+                    path,
+                    err
+                ),
+            }
+        }
+
+        Self::open(path)
+    }
+
+    /// Reads the first sheet of an `.xlsx` workbook, converted to CSV. Workbooks are zip archives
+    /// that cannot be read as a stream, so stdin is read fully first.
+    #[cfg(feature = "xlsx")]
+    pub fn xlsx(path: &str) -> anyhow::Result<Self> {
+        let csv = match path {
+            "-" => {
+                let mut contents = Vec::new();
+                io::stdin().read_to_end(&mut contents)?;
+                crate::xlsx::first_sheet_to_csv(io::Cursor::new(contents))?
+            }
+            _ => crate::xlsx::first_sheet_to_csv(File::open(path)?)?,
+        };
+        Ok(Input::Stream(Box::new(io::Cursor::new(csv))))
+    }
+
+    pub fn reader(&mut self) -> Box<dyn Read + '_> {
+        match self {
+            Input::Stream(stream) => Box::new(stream),
+            #[cfg(feature = "mmap")]
+            Input::Mapped(mmap) => Box::new(&mmap[..]),
+        }
+    }
+}
+
+/// How transaction files are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    /// A simplified ISO 8583 message dump, see [`crate::iso8583::Iso8583Reader`].
+    Iso8583,
+    /// The first sheet of an `.xlsx` workbook, with the same columns as CSV.
+    Xlsx,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(InputFormat::Csv),
+            "iso8583" => Ok(InputFormat::Iso8583),
+            "xlsx" => Ok(InputFormat::Xlsx),
+            _ => Err(anyhow!("Unknown input format {}", s)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn map_reads_file_contents() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"type,client,tx,amount\n").unwrap();
+
+        let mut input = Input::map(file.path().to_str().unwrap()).unwrap();
+        let mut contents = String::new();
+        input.reader().read_to_string(&mut contents).unwrap();
+
+        assert!(matches!(input, Input::Mapped(_)));
+        assert_eq!(contents, "type,client,tx,amount\n");
+    }
+
+    #[test]
+    fn map_falls_back_for_special_files() {
+        let mut input = Input::map("/dev/null").unwrap();
+        let mut contents = String::new();
+        input.reader().read_to_string(&mut contents).unwrap();
+
+        assert!(matches!(input, Input::Stream(_)));
+        assert_eq!(contents, "");
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+};
+
+#[cfg(feature = "anonymize")]
+use crate::anonymize::Anonymizer;
+use crate::{
+    amount::{Amount, AmountArithmetic},
+    mapping::AccountMap,
+    rejection::{reject, RejectCode},
+    types::{ClientId, Transaction, TxId, TxType},
+};
+
+/// Decimal places of the minor units amounts are given in.
+const AMOUNT_EXPONENT: u32 = 2;
+
+/// Reads transactions from a simplified ISO 8583 message dump, as delivered by card processors
+/// in settlement files.
+///
+/// Each line is a message type indicator followed by `|`-separated `field=value` pairs, e.g.
+/// `0200|2=1|3=000000|4=000000001050|11=000123`. Blank lines and lines starting with `#` are
+/// skipped. The fields used are:
+///
+/// - 2: the client id, or the account reference translated with
+///   [`Iso8583Reader::with_account_map`].
+/// - 3: the processing code, whose first two digits are `00` (purchase) or `01` (cash) for a
+///   withdrawal, or `20` (refund) or `21` (deposit) for a deposit.
+/// - 4: the amount in minor units with 2 decimal places.
+/// - 11: the system trace audit number, used as the tx id.
+///
+/// Financial messages (`0200`, `0220`) are applied as they are. Reversals (`0400`, `0420`) are
+/// applied as the opposite transaction under their own trace number, e.g. a purchase reversal is
+/// a deposit. Other message types are rejected as unknown types.
+pub struct Iso8583Reader<'a, R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+    account_map: Option<&'a mut AccountMap>,
+    #[cfg(feature = "anonymize")]
+    anonymizer: Option<&'a Anonymizer>,
+}
+
+impl<'a, R: BufRead> Iso8583Reader<'a, R> {
+    pub fn new(rdr: R) -> Self {
+        Self {
+            lines: rdr.lines(),
+            line: 0,
+            account_map: None,
+            #[cfg(feature = "anonymize")]
+            anonymizer: None,
+        }
+    }
+
+    /// Treats field 2 as an external account reference, such as a card number, and translates it
+    /// to a client id with `account_map`.
+    pub fn with_account_map(mut self, account_map: &'a mut AccountMap) -> Self {
+        self.account_map = Some(account_map);
+        self
+    }
+
+    /// Replaces every client id with its pseudonym, after any account reference translation.
+    #[cfg(feature = "anonymize")]
+    pub fn with_anonymizer(mut self, anonymizer: &'a Anonymizer) -> Self {
+        self.anonymizer = Some(anonymizer);
+        self
+    }
+
+    fn parse(&mut self, message: &str) -> anyhow::Result<Transaction> {
+        let mut parts = message.split('|');
+        let mti = parts.next().unwrap_or_default().trim();
+        let mut fields = HashMap::new();
+        for part in parts {
+            let (number, value) = part
+                .split_once('=')
+                .and_then(|(number, value)| Some((number.trim().parse::<u32>().ok()?, value)))
+                .ok_or_else(|| {
+                    reject(
+                        RejectCode::MalformedRecord,
+                        format!("Invalid field {}", part),
+                    )
+                })?;
+            fields.insert(number, value.trim());
+        }
+        let field = |number: u32| {
+            fields.get(&number).copied().ok_or_else(|| {
+                reject(
+                    RejectCode::MalformedRecord,
+                    format!("Message has no field {}", number),
+                )
+            })
+        };
+
+        let reversal = match mti {
+            "0200" | "0220" => false,
+            "0400" | "0420" => true,
+            _ => {
+                return Err(reject(
+                    RejectCode::UnknownType,
+                    format!("Unsupported message type {}", mti),
+                ))
+            }
+        };
+        let processing_code = field(3)?;
+        let debit = match processing_code.get(..2) {
+            Some("00" | "01") => true,
+            Some("20" | "21") => false,
+            _ => {
+                return Err(reject(
+                    RejectCode::UnknownType,
+                    format!("Unsupported processing code {}", processing_code),
+                ))
+            }
+        };
+        let tx_type = match debit != reversal {
+            true => TxType::Withdrawal,
+            false => TxType::Deposit,
+        };
+
+        let amount = field(4)?;
+        let amount = match amount.bytes().all(|b| b.is_ascii_digit()) {
+            true => amount.parse::<i64>().ok(),
+            false => None,
+        }
+        .map(|minor_units| Amount::new(minor_units, AMOUNT_EXPONENT).canonical())
+        .ok_or_else(|| reject(RejectCode::BadAmount, format!("Invalid amount {}", amount)))?;
+
+        let tx_id = field(11)?;
+        let tx_id: TxId = tx_id.parse().map_err(|_| {
+            reject(
+                RejectCode::MalformedRecord,
+                format!("Invalid trace number {}", tx_id),
+            )
+        })?;
+
+        let client = field(2)?;
+        let client_id: ClientId = match self.account_map.as_mut() {
+            Some(account_map) => account_map.resolve(client)?,
+            None => client.parse().map_err(|_| {
+                reject(
+                    RejectCode::MalformedRecord,
+                    format!("Invalid client id {}", client),
+                )
+            })?,
+        };
+        #[cfg(feature = "anonymize")]
+        let client_id = match self.anonymizer {
+            Some(anonymizer) => anonymizer.pseudonym(client_id),
+            None => client_id,
+        };
+
+        let mut tx = Transaction::new(tx_type, client_id, tx_id, amount);
+        tx.trace_id = Some(format!("line-{}", self.line));
+        Ok(tx)
+    }
+}
+
+impl<R: BufRead> Iterator for Iso8583Reader<'_, R> {
+    type Item = anyhow::Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line += 1;
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(reject(RejectCode::MalformedRecord, err.to_string()))),
+            };
+
+            let message = line.trim();
+            if !message.is_empty() && !message.starts_with('#') {
+                return Some(self.parse(message));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    #[test]
+    fn maps_messages_to_transactions() {
+        let src = "# settlement 2024-01-31\n\
+                   0200|2=1|3=200000|4=000000001050|11=000001\n\
+                   \n\
+                   0200|2=1|3=000000|4=000000000250|11=000002\n\
+                   0420|2=1|3=000000|4=000000000250|11=000003|90=0200000002\n";
+        let txs: Vec<_> = Iso8583Reader::new(src.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+
+        let summary: Vec<_> = txs
+            .iter()
+            .map(|tx| (tx.tx_type, tx.client_id, tx.tx_id, tx.amount))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (TxType::Deposit, 1, 1, dec!(10.5)),
+                (TxType::Withdrawal, 1, 2, dec!(2.5)),
+                (TxType::Deposit, 1, 3, dec!(2.5)),
+            ]
+        );
+        assert_eq!(txs[1].trace_id(), "line-4");
+    }
+
+    #[test]
+    fn rejects_unsupported_and_corrupt_messages() {
+        let src = "0800|70=301\n\
+                   0200|2=1|3=310000|4=0|11=1\n\
+                   0200|2=1|3=000000|4=-100|11=2\n\
+                   0200|2=1|3=000000|4=100\n\
+                   0200|2=1|3000000\n";
+        let codes: Vec<_> = Iso8583Reader::new(src.as_bytes())
+            .map(|result| RejectCode::of(&result.unwrap_err()))
+            .collect();
+
+        assert_eq!(
+            codes,
+            vec![
+                RejectCode::UnknownType,
+                RejectCode::UnknownType,
+                RejectCode::BadAmount,
+                RejectCode::MalformedRecord,
+                RejectCode::MalformedRecord,
+            ]
+        );
+    }
+
+    #[test]
+    fn translates_card_numbers() {
+        let src = "0200|2=4111111111111111|3=200000|4=100|11=1\n\
+                   0200|2=5500000000000004|3=200000|4=100|11=2\n\
+                   0200|2=4111111111111111|3=000000|4=100|11=3\n";
+        let mut account_map = AccountMap::new();
+
+        let client_ids: Vec<_> = Iso8583Reader::new(src.as_bytes())
+            .with_account_map(&mut account_map)
+            .map(|result| result.unwrap().client_id)
+            .collect();
+
+        assert_eq!(client_ids, vec![0, 1, 0]);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    amount::Amount,
+    engine::{Receipt, TxOutcome},
+    types::{Account, ClientId, Transaction},
+};
+
+const CASH: &str = "Assets:Cash";
+const OPENING_BALANCES: &str = "Equity:Opening-Balances";
+
+/// Plain-text accounting format written by [`LedgerWriter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LedgerFormat {
+    #[default]
+    Beancount,
+    /// ledger-cli (and hledger).
+    Ledger,
+}
+
+impl FromStr for LedgerFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beancount" => Ok(LedgerFormat::Beancount),
+            "ledger" => Ok(LedgerFormat::Ledger),
+            _ => Err(anyhow!("Unknown ledger format {}", s)),
+        }
+    }
+}
+
+/// Writes the balance changes of processed records as a double-entry journal.
+///
+/// Each client has `Liabilities:Clients:C<id>:Available` and `...:Held` accounts, balanced against
+/// `Assets:Cash` for money entering or leaving and against each other for holds. Postings are
+/// derived from the balances in each receipt, so every applied record is journaled with the
+/// amounts the engine actually moved. Transactions carry no date, so entries are dated with the
+/// day of the run.
+pub struct LedgerWriter<W: Write> {
+    w: W,
+    format: LedgerFormat,
+    currency: String,
+    date: String,
+    /// Last journaled available and held amounts of each client.
+    balances: HashMap<ClientId, (Amount, Amount)>,
+    opened: HashSet<String>,
+}
+
+impl<W: Write> LedgerWriter<W> {
+    pub fn new(w: W, format: LedgerFormat, currency: impl Into<String>, date: SystemTime) -> Self {
+        let days = date
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / 86_400;
+        let (year, month, day) = civil_date(days as i64);
+        let date = match format {
+            LedgerFormat::Beancount => format!("{:04}-{:02}-{:02}", year, month, day),
+            LedgerFormat::Ledger => format!("{:04}/{:02}/{:02}", year, month, day),
+        };
+
+        Self {
+            w,
+            format,
+            currency: currency.into(),
+            date,
+            balances: HashMap::new(),
+            opened: HashSet::new(),
+        }
+    }
+
+    /// Journals the balances of accounts that existed before the run, such as restored state,
+    /// against `Equity:Opening-Balances`.
+    pub fn open_balances(&mut self, accounts: &[&Account]) -> io::Result<()> {
+        self.sync(accounts, "Opening balance", OPENING_BALANCES)
+    }
+
+    /// Journals the change a processed record made, if it was applied.
+    pub fn write(&mut self, tx: &Transaction, receipt: &Receipt) -> io::Result<()> {
+        let (TxOutcome::Applied, Some(available), Some(held)) =
+            (&receipt.result.outcome, receipt.available, receipt.held)
+        else {
+            return Ok(());
+        };
+
+        let narration = format!(
+            "{} tx {} (client {})",
+            tx.tx_type.as_str(),
+            tx.tx_id,
+            tx.client_id
+        );
+        self.entry(tx.client_id, available, held, &narration, CASH)
+    }
+
+    /// Journals any difference between the journaled and final balances, such as rewards paid out
+    /// or disputes closed after the last record, so the journal matches the accounts output.
+    pub fn reconcile(&mut self, accounts: &[&Account]) -> io::Result<()> {
+        self.sync(accounts, "End of run adjustment", CASH)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    fn sync(&mut self, accounts: &[&Account], narration: &str, against: &str) -> io::Result<()> {
+        for acc in accounts {
+            let narration = format!("{} (client {})", narration, acc.client_id);
+            self.entry(
+                acc.client_id,
+                acc.available_amount,
+                acc.held_amount,
+                &narration,
+                against,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes an entry moving the client's balances to `available` and `held`, balanced against
+    /// `against`. Nothing is written if the balances did not change.
+    fn entry(
+        &mut self,
+        client_id: ClientId,
+        available: Amount,
+        held: Amount,
+        narration: &str,
+        against: &str,
+    ) -> io::Result<()> {
+        let (previous_available, previous_held) = self
+            .balances
+            .insert(client_id, (available, held))
+            .unwrap_or_default();
+        let (available_change, held_change) =
+            (available - previous_available, held - previous_held);
+
+        // Client balances are liabilities, so an increase is a credit (a negative posting).
+        let postings: Vec<_> = [
+            (
+                format!("Liabilities:Clients:C{}:Available", client_id),
+                Amount::ZERO - available_change,
+            ),
+            (
+                format!("Liabilities:Clients:C{}:Held", client_id),
+                Amount::ZERO - held_change,
+            ),
+            (against.to_string(), available_change + held_change),
+        ]
+        .into_iter()
+        .filter(|(_, amount)| *amount != Amount::ZERO)
+        .collect();
+        if postings.is_empty() {
+            return Ok(());
+        }
+
+        if self.format == LedgerFormat::Beancount {
+            for (account, _) in &postings {
+                if self.opened.insert(account.clone()) {
+                    writeln!(self.w, "{} open {}", self.date, account)?;
+                }
+            }
+        }
+
+        match self.format {
+            LedgerFormat::Beancount => writeln!(self.w, "{} * \"{}\"", self.date, narration)?,
+            LedgerFormat::Ledger => writeln!(self.w, "{} {}", self.date, narration)?,
+        }
+        for (account, amount) in &postings {
+            writeln!(self.w, "  {}  {} {}", account, amount, self.currency)?;
+        }
+        writeln!(self.w)
+    }
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian
+/// calendar.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{account::SimpleManager, amount::dec, engine::Engine, types::TxType};
+
+    fn journal(format: LedgerFormat, txs: Vec<Transaction>) -> String {
+        let date = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut engine = Engine::new(SimpleManager::new());
+        let mut ledger = LedgerWriter::new(Vec::new(), format, "EUR", date);
+
+        for (row, tx) in txs.into_iter().enumerate() {
+            let receipt = engine.process_with_receipt(row + 1, Ok(tx.clone()));
+            ledger.write(&tx, &receipt).unwrap();
+        }
+        ledger.reconcile(&engine.get_accounts()).unwrap();
+
+        String::from_utf8(ledger.w).unwrap()
+    }
+
+    #[test]
+    fn writes_beancount_entries_for_applied_records() {
+        let journal = journal(
+            LedgerFormat::Beancount,
+            vec![
+                Transaction::new(TxType::Deposit, 1, 1, dec!(10)),
+                Transaction::new(TxType::Withdrawal, 1, 2, dec!(20)),
+                Transaction::new(TxType::Dispute, 1, 1, dec!(0)),
+            ],
+        );
+
+        assert_eq!(
+            journal,
+            "2023-11-14 open Liabilities:Clients:C1:Available\n\
+             2023-11-14 open Assets:Cash\n\
+             2023-11-14 * \"deposit tx 1 (client 1)\"\n\
+             \x20 Liabilities:Clients:C1:Available  -10 EUR\n\
+             \x20 Assets:Cash  10 EUR\n\
+             \n\
+             2023-11-14 open Liabilities:Clients:C1:Held\n\
+             2023-11-14 * \"dispute tx 1 (client 1)\"\n\
+             \x20 Liabilities:Clients:C1:Available  10 EUR\n\
+             \x20 Liabilities:Clients:C1:Held  -10 EUR\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn writes_ledger_entries() {
+        let journal = journal(
+            LedgerFormat::Ledger,
+            vec![Transaction::new(TxType::Deposit, 2, 1, dec!(1.5))],
+        );
+
+        assert_eq!(
+            journal,
+            "2023/11/14 deposit tx 1 (client 2)\n\
+             \x20 Liabilities:Clients:C2:Available  -1.5 EUR\n\
+             \x20 Assets:Cash  1.5 EUR\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+    }
+}
@@ -0,0 +1,82 @@
+//! A payment transaction engine that applies deposits, withdrawals and disputes to client accounts.
+//!
+//! The engine is usable as a library as well as through the binary: build an [`Engine`] over an
+//! account [`Manager`] such as [`account::SimpleManager`], feed it [`Transaction`]s (for example
+//! the records of a [`CsvTxReader`]) and read the resulting [`Account`]s back.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod access;
+pub mod account;
+pub mod amount;
+#[cfg(feature = "std")]
+pub mod anomaly;
+#[cfg(feature = "anonymize")]
+pub mod anonymize;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod engine;
+#[cfg(feature = "std")]
+pub mod enrich;
+#[cfg(feature = "simulation")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod graph;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "csv")]
+pub mod input;
+#[cfg(feature = "csv")]
+pub mod iso8583;
+#[cfg(feature = "std")]
+pub mod ledger;
+#[cfg(feature = "lmdb")]
+pub mod lmdb;
+#[cfg(feature = "csv")]
+pub mod mapping;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "csv")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "csv")]
+pub mod reader;
+#[cfg(feature = "redis")]
+pub mod redis_manager;
+pub mod rejection;
+#[cfg(feature = "template")]
+pub mod report;
+#[cfg(feature = "csv")]
+pub mod scenario;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+#[cfg(feature = "std")]
+pub mod source;
+#[cfg(feature = "csv")]
+pub mod state;
+#[cfg(feature = "std")]
+pub mod store;
+pub mod types;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
+
+pub use account::Manager;
+#[cfg(feature = "std")]
+pub use engine::{Engine, EngineBuilder};
+#[cfg(feature = "csv")]
+pub use reader::CsvTxReader;
+pub use types::{Account, AccountStatus, ClientId, Transaction, TxId, TxState, TxType};
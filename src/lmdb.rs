@@ -0,0 +1,286 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    mem,
+    path::Path,
+};
+
+use anyhow::anyhow;
+
+use heed::{
+    types::U64, BoxedError, BytesDecode, BytesEncode, Database, Env, EnvFlags, EnvOpenOptions,
+};
+use roaring::RoaringTreemap;
+
+use crate::{
+    amount::{Amount, AmountArithmetic},
+    store::TxIndex,
+    types::{ClientId, StoredTx, TxId, TxState, TxType},
+};
+
+type Key = U64<heed::byteorder::BigEndian>;
+
+const CLIENT_LEN: usize = mem::size_of::<ClientId>();
+const RECORD_LEN: usize = CLIENT_LEN + 18;
+
+/// Marks a directory as holding a transaction index, and is locked while the index is open.
+const LOCK_FILE: &str = "tx-index.lock";
+
+/// Fixed-width encoding of a [`StoredTx`]: client id, encoded amount, state, type.
+struct StoredTxCodec;
+
+impl<'a> BytesEncode<'a> for StoredTxCodec {
+    type EItem = StoredTx;
+
+    fn bytes_encode(tx: &'a StoredTx) -> Result<Cow<'a, [u8]>, BoxedError> {
+        let mut bytes = Vec::with_capacity(RECORD_LEN);
+        bytes.extend_from_slice(&tx.client_id.to_be_bytes());
+        bytes.extend_from_slice(&tx.amount.to_bytes());
+        bytes.push(match tx.state {
+            TxState::Processed => 0,
+            TxState::Disputed => 1,
+            TxState::Resolved => 2,
+            TxState::ChargedBack => 3,
+            TxState::Compensated => 4,
+            TxState::WrittenOff => 5,
+            TxState::ChargebackReversed => 6,
+        });
+        bytes.push(match tx.tx_type {
+            TxType::Deposit => 0,
+            TxType::Withdrawal => 1,
+            TxType::Dispute => 2,
+            TxType::Resolve => 3,
+            TxType::Chargeback => 4,
+            TxType::ChargebackReversal => 5,
+            TxType::OpenAccount => 6,
+        });
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+impl<'a> BytesDecode<'a> for StoredTxCodec {
+    type DItem = StoredTx;
+
+    fn bytes_decode(bytes: &'a [u8]) -> Result<StoredTx, BoxedError> {
+        if bytes.len() != RECORD_LEN {
+            return Err(format!("Invalid stored transaction length {}", bytes.len()).into());
+        }
+
+        let mut client_id = [0; CLIENT_LEN];
+        client_id.copy_from_slice(&bytes[..CLIENT_LEN]);
+        let mut amount = [0; 16];
+        amount.copy_from_slice(&bytes[CLIENT_LEN..CLIENT_LEN + 16]);
+
+        let state = match bytes[CLIENT_LEN + 16] {
+            0 => TxState::Processed,
+            1 => TxState::Disputed,
+            2 => TxState::Resolved,
+            3 => TxState::ChargedBack,
+            4 => TxState::Compensated,
+            5 => TxState::WrittenOff,
+            6 => TxState::ChargebackReversed,
+            state => return Err(format!("Invalid stored transaction state {}", state).into()),
+        };
+
+        let tx_type = match bytes[CLIENT_LEN + 17] {
+            0 => TxType::Deposit,
+            1 => TxType::Withdrawal,
+            2 => TxType::Dispute,
+            3 => TxType::Resolve,
+            4 => TxType::Chargeback,
+            5 => TxType::ChargebackReversal,
+            6 => TxType::OpenAccount,
+            tx_type => return Err(format!("Invalid stored transaction type {}", tx_type).into()),
+        };
+
+        Ok(StoredTx {
+            tx_type,
+            client_id: ClientId::from_be_bytes(client_id),
+            amount: Amount::from_bytes(amount),
+            state,
+        })
+    }
+}
+
+/// Transaction index persisted in an LMDB environment, for inputs whose transactions do not fit
+/// in memory.
+///
+/// The index is rebuilt on every run, so writes skip syncing to disk and reads skip read-ahead
+/// to suit random dispute lookups. A bitmap of stored tx ids avoids opening read transactions
+/// for unknown ids.
+pub struct LmdbTxStore {
+    env: Env,
+    db: Database<Key, StoredTxCodec>,
+    seen: RoaringTreemap,
+    len: usize,
+    /// Held until the environment is closed, so no other store opens it meanwhile.
+    _lock: File,
+}
+
+impl LmdbTxStore {
+    /// Opens an empty index in `dir`, which is created if needed. `map_size` is the maximum
+    /// size of the database in bytes.
+    ///
+    /// The index of an earlier run in `dir` is cleared, but a directory holding any other LMDB
+    /// database, or an index that is still open, is an error.
+    pub fn open(dir: impl AsRef<Path>, map_size: usize) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let lock_path = dir.join(LOCK_FILE);
+        if dir.join("data.mdb").exists() && !lock_path.exists() {
+            return Err(anyhow!(
+                "{} holds a database that is not a transaction index",
+                dir.display()
+            ));
+        }
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        lock.try_lock()
+            .map_err(|_| anyhow!("The transaction index in {} is already open", dir.display()))?;
+
+        // SAFETY: the environment is only opened while holding the exclusive lock on its lock
+        // file, so no other store in this or another process maps it at the same time, and the
+        // store never resizes the map after opening it.
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(map_size)
+                .flags(EnvFlags::NO_SYNC | EnvFlags::NO_META_SYNC | EnvFlags::NO_READ_AHEAD)
+                .open(dir)?
+        };
+
+        let mut wtxn = env.write_txn()?;
+        let db: Database<Key, StoredTxCodec> = env.create_database(&mut wtxn, None)?;
+        db.clear(&mut wtxn)?;
+        wtxn.commit()?;
+
+        Ok(Self {
+            env,
+            db,
+            seen: RoaringTreemap::new(),
+            len: 0,
+            _lock: lock,
+        })
+    }
+}
+
+impl TxIndex for LmdbTxStore {
+    fn get(&self, tx_id: TxId) -> anyhow::Result<Option<StoredTx>> {
+        if !self.seen.contains(tx_id) {
+            return Ok(None);
+        }
+
+        let rtxn = self.env.read_txn()?;
+        Ok(self.db.get(&rtxn, &tx_id)?)
+    }
+
+    fn contains(&self, tx_id: TxId) -> bool {
+        self.seen.contains(tx_id)
+    }
+
+    fn insert(&mut self, tx_id: TxId, tx: StoredTx) -> anyhow::Result<()> {
+        let mut wtxn = self.env.write_txn()?;
+        self.db.put(&mut wtxn, &tx_id, &tx)?;
+        wtxn.commit()?;
+
+        if self.seen.insert(tx_id) {
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Only the bitmap is held in memory; pages cached by the OS are not counted.
+    fn memory_bytes(&self) -> usize {
+        self.seen.serialized_size()
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<(TxId, StoredTx)>> {
+        let rtxn = self.env.read_txn()?;
+        let mut entries = Vec::with_capacity(self.len);
+        for entry in self.db.iter(&rtxn)? {
+            entries.push(entry?);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    #[test]
+    fn stores_and_updates_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = LmdbTxStore::open(dir.path(), 10 * 1024 * 1024).unwrap();
+
+        let stored = StoredTx::new(TxType::Deposit, 3, dec!(12.3456));
+        store.insert(1, stored).unwrap();
+        store
+            .insert(2, StoredTx::new(TxType::Withdrawal, 4, dec!(1)))
+            .unwrap();
+        store
+            .insert(1, stored.with_state(TxState::Disputed))
+            .unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(1));
+        assert!(!store.contains(3));
+        assert_eq!(
+            store.get(1).unwrap(),
+            Some(stored.with_state(TxState::Disputed))
+        );
+        assert_eq!(store.get(3).unwrap(), None);
+        assert_eq!(store.entries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn open_clears_previous_run() {
+        let dir = tempfile::tempdir().unwrap();
+
+        {
+            let mut store = LmdbTxStore::open(dir.path(), 10 * 1024 * 1024).unwrap();
+            store
+                .insert(1, StoredTx::new(TxType::Deposit, 1, dec!(1)))
+                .unwrap();
+        }
+
+        let store = LmdbTxStore::open(dir.path(), 10 * 1024 * 1024).unwrap();
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn open_rejects_open_indexes_and_other_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let _store = LmdbTxStore::open(dir.path(), 10 * 1024 * 1024).unwrap();
+
+        let Err(err) = LmdbTxStore::open(dir.path(), 10 * 1024 * 1024) else {
+            panic!("opened an index that is already open");
+        };
+        assert!(err.to_string().ends_with("is already open"), "{}", err);
+
+        let other = tempfile::tempdir().unwrap();
+        fs::write(other.path().join("data.mdb"), b"other data").unwrap();
+        let Err(err) = LmdbTxStore::open(other.path(), 10 * 1024 * 1024) else {
+            panic!("opened another database");
+        };
+        assert!(
+            err.to_string()
+                .ends_with("holds a database that is not a transaction index"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            fs::read(other.path().join("data.mdb")).unwrap(),
+            b"other data"
+        );
+    }
+}
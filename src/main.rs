@@ -1,72 +1,1272 @@
-mod account;
-mod engine;
-mod reader;
-mod types;
-
-use std::env;
+use std::{
+    env,
+    io::{self, BufReader, BufWriter},
+    num::NonZeroU32,
+    str::FromStr,
+};
 
 use anyhow::anyhow;
-use log::info;
-use types::Account;
-
-use crate::{engine::Engine, reader::CsvTxReader};
+use log::{info, warn};
+use payment_transaction_engine::{
+    access::ClientAccess,
+    account,
+    amount::Amount,
+    anomaly::DuplicateDetector,
+    anonymize::Anonymizer,
+    bench,
+    budget::{MemoryBudget, MemoryLimit},
+    engine::{
+        DisputeResolution, Engine, EngineBuilder, EngineState, EngineStats, ProcessingSummary,
+        TxIdCollisionPolicy, UnknownTypePolicy,
+    },
+    enrich::{EnricherChain, EnricherSpec},
+    fuzz,
+    graph::{GraphFormat, TxGraph},
+    input::{Input, InputFormat},
+    iso8583::Iso8583Reader,
+    ledger::{LedgerFormat, LedgerWriter},
+    mapping::AccountMap,
+    metrics::{ClientCounts, RunSummary},
+    output::{self, AccountRecord, OutputFormat, OutputVersion, QuarantinedRow, ReceiptWriter},
+    query::TxQuery,
+    reader::{self, AmountLocale, CsvTxReader},
+    rejection::RejectCode,
+    simulation::FlakyManager,
+    source::TxSource,
+    state,
+    store::{EvictionPolicy, SpillFile},
+    types::{Account, Transaction, TxId, TxType},
+};
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
-    let args = parse_args(env::args().collect())?;
+    match parse_command(env::args().collect())? {
+        Command::Process(args) => {
+            let run = || match &args.profile_file {
+                Some(profile_file) => profile(profile_file, || process(&args)),
+                None => process(&args),
+            };
+            match args.notify_slack.is_some() || args.notify_webhook.is_some() {
+                true => notify(&args, run),
+                false => run(),
+            }
+            .map(drop)
+        }
+        Command::FuzzRun { seed, rows } => {
+            let report = fuzz::fuzz_run(seed, rows)?;
+            println!(
+                "Fuzz run with seed {} passed: {} rows, {} accounts, {} locked",
+                report.seed, report.rows, report.accounts, report.locked_accounts
+            );
+            Ok(())
+        }
+        Command::Reprocess(args) => reprocess(&args, io::stdout().lock()),
+        Command::Bench {
+            rows,
+            max_stored_txs,
+            track_disputes,
+        } => {
+            let report = bench::bench_run(rows, max_stored_txs, track_disputes);
+            println!(
+                "Processed {} rows in {:.3}s: {:.0} rows/sec",
+                report.rows,
+                report.elapsed.as_secs_f64(),
+                report.rows_per_sec()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Applies corrected quarantined records to the saved state, writing a receipt for each record.
+/// The engine and reader are configured by the same options as the run that saved the state.
+fn reprocess(args: &Args, w: impl io::Write) -> anyhow::Result<()> {
+    let (file, state_file) = match (args.transactions_files.as_slice(), &args.state_file) {
+        ([file], Some(state_file)) => (file, state_file),
+        _ => return Err(anyhow!("reprocess requires one file and --state")),
+    };
+    let saved = state::load_state(state_file)?
+        .ok_or_else(|| anyhow!("No state found in {}", state_file))?;
+    let anonymizer = load_anonymizer(args)?;
+    let client_access = load_client_access(args, anonymizer.as_ref())?;
+    let mut engine = engine_builder(
+        args,
+        account::SimpleManager::new(),
+        None,
+        client_access.as_ref(),
+    )?
+    .build();
+    engine.restore(saved)?;
+
+    let mut account_map = match &args.account_map_file {
+        Some(account_map_file) => {
+            Some(AccountMap::load(account_map_file)?.strict(args.strict_account_map))
+        }
+        None => None,
+    };
+    let mut input = Input::open(file)?;
+    let mut csv_reader = reader::new_csv_reader(input.reader());
+    let records = csv_tx_reader(
+        args,
+        &mut csv_reader,
+        account_map.as_mut(),
+        anonymizer.as_ref(),
+    )
+    .into_iter()
+    .collect();
+
+    let receipts = engine.reprocess(records)?;
+    info!("Reprocessed {} records from {}", receipts.len(), file);
+
+    let mut w = ReceiptWriter::new(w, args.output_format.clone())?;
+    for receipt in &receipts {
+        w.write(file, receipt)?;
+    }
+    w.flush()?;
+
+    if let (Some(account_map), Some(account_map_file)) = (account_map, &args.account_map_file) {
+        account_map.save(account_map_file)?;
+    }
+    state::save_state(state_file, &engine.state()?)
+}
+
+fn process(args: &Args) -> anyhow::Result<RunSummary> {
+    match &args.redis_url {
+        Some(redis_url) => process_with_redis(args, redis_url),
+        None => process_with(args, account::SimpleManager::new()),
+    }
+}
+
+#[cfg(feature = "redis")]
+fn process_with_redis(args: &Args, url: &str) -> anyhow::Result<RunSummary> {
+    info!("Storing accounts in Redis at {}", url);
+    let accounts =
+        payment_transaction_engine::redis_manager::RedisManager::connect(url, &args.redis_prefix)?;
+    match args.account_cache {
+        Some(capacity) => {
+            info!("Caching up to {} accounts in memory", capacity);
+            process_with(
+                args,
+                payment_transaction_engine::cache::CachedManager::new(accounts, capacity),
+            )
+        }
+        None => process_with(args, accounts),
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+fn process_with_redis(_args: &Args, _url: &str) -> anyhow::Result<RunSummary> {
+    Err(anyhow!(
+        "--redis-url requires building with the redis feature enabled"
+    ))
+}
+
+fn process_with<A: account::Manager>(args: &Args, accounts: A) -> anyhow::Result<RunSummary> {
+    match args.failure_rate {
+        Some(failure_rate) => {
+            info!(
+                "Simulating transient failures for {} of operations with seed {}",
+                failure_rate, args.seed
+            );
+            run(args, FlakyManager::new(accounts, failure_rate, args.seed))
+        }
+        None => run(args, accounts),
+    }
+}
+
+fn run<A: account::Manager>(args: &Args, accounts: A) -> anyhow::Result<RunSummary> {
+    let budget = match args.max_memory {
+        Some(limit) => {
+            let budget = MemoryBudget::resolve(limit)?;
+            info!(
+                "Sizing buffers for a memory budget of {} bytes",
+                budget.bytes
+            );
+            Some(budget)
+        }
+        None => None,
+    };
+
+    let anonymizer = load_anonymizer(args)?;
+    let client_access = load_client_access(args, anonymizer.as_ref())?;
+    let mut engine = engine_builder(args, accounts, budget, client_access.as_ref())?.build();
+
+    if let Some(state_file) = &args.state_file {
+        match state::load_state(state_file)? {
+            Some(saved) => {
+                info!(
+                    "Restoring {} accounts and {} transactions from {}",
+                    saved.accounts.len(),
+                    saved.transactions.len(),
+                    state_file
+                );
+                engine.restore(saved)?;
+            }
+            None => info!("No state in {} yet, starting empty", state_file),
+        }
+    }
+
+    if let Some(accounts_file) = &args.initial_accounts_file {
+        let records =
+            output::read_accounts(std::fs::File::open(accounts_file)?, &args.output_format)?;
+        info!("Loading {} accounts from {}", records.len(), accounts_file);
+        let initial = EngineState {
+            accounts: records.iter().map(AccountRecord::to_account).collect(),
+            tx_id_collisions: args.tx_id_collisions.unwrap_or_default(),
+            ..Default::default()
+        };
+        engine.restore(initial).map_err(|err| {
+            anyhow!(
+                "Could not load initial accounts from {}: {}",
+                accounts_file,
+                err
+            )
+        })?;
+    }
+
+    let mut graph = args.graph_file.as_ref().map(|_| TxGraph::new());
+    let mut client_counts = args.hot_clients.map(|_| ClientCounts::new());
+    let mut enrichers = args
+        .enrichers
+        .iter()
+        .try_fold(EnricherChain::new(), |chain, spec| {
+            anyhow::Ok(chain.with(spec.build()?))
+        })?;
+    let mut account_map = match &args.account_map_file {
+        Some(account_map_file) => {
+            Some(AccountMap::load(account_map_file)?.strict(args.strict_account_map))
+        }
+        None => None,
+    };
+
+    let mut receipts = match &args.receipts_file {
+        Some(receipts_file) => {
+            info!("Writing receipts to {}", receipts_file);
+            Some(ReceiptWriter::new(
+                BufWriter::new(std::fs::File::create(receipts_file)?),
+                args.output_format.clone(),
+            )?)
+        }
+        None => None,
+    };
+    let mut ledger = match &args.ledger_file {
+        Some(ledger_file) => {
+            info!("Writing the journal to {}", ledger_file);
+            let mut ledger = LedgerWriter::new(
+                BufWriter::new(std::fs::File::create(ledger_file)?),
+                args.ledger_format,
+                &args.ledger_currency,
+                std::time::SystemTime::now(),
+            );
+            ledger.open_balances(&engine.get_accounts())?;
+            Some(ledger)
+        }
+        None => None,
+    };
+    let mut quarantined = (args.unknown_types == UnknownTypePolicy::Quarantine
+        || args.quarantine_blocked)
+        .then(Vec::new);
+    let mut rows = 0;
+    let mut rejected = 0;
+    let mut stopped = false;
+
+    for (i, transactions_file) in args.transactions_files.iter().enumerate() {
+        if stopped || engine.abort_reason().is_some() {
+            break;
+        }
+        if i > 0 {
+            engine.start_source();
+        }
+
+        info!("Processing transaction file {}", transactions_file);
+
+        let mut input = match (args.input_format, args.mmap, args.io_uring) {
+            (InputFormat::Xlsx, _, _) => xlsx_input(transactions_file)?,
+            (_, true, _) => map_input(transactions_file)?,
+            (_, false, true) => uring_input(transactions_file)?,
+            (_, false, false) => Input::open(transactions_file)?,
+        };
+
+        let mut csv_reader;
+        let records: Box<dyn Iterator<Item = anyhow::Result<Transaction>>> = match args.input_format
+        {
+            InputFormat::Csv | InputFormat::Xlsx => {
+                csv_reader = reader::new_csv_reader(input.reader());
+                Box::new(
+                    csv_tx_reader(
+                        args,
+                        &mut csv_reader,
+                        account_map.as_mut(),
+                        anonymizer.as_ref(),
+                    )
+                    .into_iter(),
+                )
+            }
+            InputFormat::Iso8583 => {
+                let mut iso_reader = Iso8583Reader::new(BufReader::new(input.reader()));
+                if let Some(account_map) = account_map.as_mut() {
+                    iso_reader = iso_reader.with_account_map(account_map);
+                }
+                if let Some(anonymizer) = &anonymizer {
+                    iso_reader = iso_reader.with_anonymizer(anonymizer);
+                }
+                Box::new(iso_reader)
+            }
+        };
+
+        let source = records.map(|result| enrichers.apply(result));
+        // Throttling the source also paces receipts, which are written as records are applied.
+        let source: Box<dyn Iterator<Item = anyhow::Result<Transaction>>> = match args.max_tps {
+            Some(max_tps) => Box::new(source.throttle(max_tps)),
+            None => Box::new(source),
+        };
+
+        let transactions = source
+            .take_while(|result| {
+                // Include the row that reaches the stop point, then end the run.
+                if stopped {
+                    return false;
+                }
+                rows += 1;
+                stopped = args.until_row == Some(rows)
+                    || matches!((args.until_tx, result), (Some(until_tx), Ok(tx)) if tx.tx_id == until_tx);
+                true
+            })
+            .enumerate()
+            .inspect(|(_, result)| {
+                if let (Some(client_counts), Ok(tx)) = (client_counts.as_mut(), result) {
+                    client_counts.record(tx.client_id);
+                }
+            })
+            .inspect(|(index, result)| {
+                let error = match result {
+                    Err(err)
+                        if args.unknown_types == UnknownTypePolicy::Quarantine
+                            && RejectCode::of(err) == RejectCode::UnknownType =>
+                    {
+                        err.to_string()
+                    }
+                    Ok(tx)
+                        if args.quarantine_blocked
+                            && client_access
+                                .as_ref()
+                                .is_some_and(|access| !access.permits(tx.client_id)) =>
+                    {
+                        format!("Client id {} is blocked", tx.client_id)
+                    }
+                    _ => return,
+                };
+                if let Some(quarantined) = quarantined.as_mut() {
+                    quarantined.push(QuarantinedRow {
+                        file: transactions_file.clone(),
+                        row: index + 1,
+                        error,
+                    });
+                }
+            });
+
+        let summary = if receipts.is_none() && ledger.is_none() && graph.is_none() {
+            engine.process_all(transactions.map(|(_, result)| result))
+        } else {
+            let mut summary = ProcessingSummary::default();
+            for (index, result) in transactions {
+                if engine.abort_reason().is_some() {
+                    break;
+                }
+                let tx = match (&ledger, &graph) {
+                    (None, None) => None,
+                    _ => result.as_ref().ok().cloned(),
+                };
+                let receipt = engine.process_with_receipt(index + 1, result);
+                summary.record(&receipt.result);
+                if let Some(receipts) = receipts.as_mut() {
+                    receipts.write(transactions_file, &receipt)?;
+                }
+                if let (Some(graph), Some(tx)) = (graph.as_mut(), &tx) {
+                    graph.record(tx, &receipt.result.outcome);
+                }
+                if let (Some(ledger), Some(tx)) = (ledger.as_mut(), tx) {
+                    ledger.write(&tx, &receipt)?;
+                }
+            }
+            summary
+        };
+
+        info!(
+            "Processed {}: {} applied, {} ignored, {} rejected",
+            transactions_file, summary.applied, summary.ignored, summary.rejected
+        );
+        if !summary.corrupt_rows.is_empty() {
+            warn!(
+                "{} records in {} could not be read",
+                summary.corrupt_rows.len(),
+                transactions_file
+            );
+        }
+        rejected += summary.rejected;
+    }
+
+    if let Some(mut receipts) = receipts {
+        receipts.flush()?;
+    }
+
+    if let Some(reason) = engine.abort_reason() {
+        return Err(anyhow!("Aborted run: {}", reason));
+    }
+
+    if let (Some(quarantined), Some(quarantine_file)) = (quarantined, &args.quarantine_file) {
+        info!(
+            "Writing {} quarantined records to {}",
+            quarantined.len(),
+            quarantine_file
+        );
+        let mut w = BufWriter::new(std::fs::File::create(quarantine_file)?);
+        output::write_quarantine(&mut w, &quarantined, &args.output_format)?;
+    }
+
+    match (stopped, args.until_tx, args.until_row) {
+        (true, _, _) => info!("Stopped replay after {} rows", rows),
+        (false, Some(until_tx), _) => warn!(
+            "Transaction {} was not found, replayed all {} rows",
+            until_tx, rows
+        ),
+        (false, None, Some(until_row)) => warn!(
+            "Input has fewer than {} rows, replayed all {} rows",
+            until_row, rows
+        ),
+        (false, None, None) => {}
+    }
+
+    if let Some(resolution) = args.close_open_disputes {
+        let closed = engine.close_open_disputes(resolution)?;
+        info!("Closed {} open disputes with {:?}", closed, resolution);
+    }
+
+    if args.reward_rate.is_some() {
+        let payout = engine.pay_out_rewards();
+        info!("Paid out rewards to {} accounts", payout.paid.len());
+        for (client_id, err) in &payout.failed {
+            warn!(
+                "Reward for client id {} could not be paid out: {}",
+                client_id, err
+            );
+        }
+        if let Some(report_file) = &args.rewards_report {
+            info!("Writing rewards to {}", report_file);
+            let mut w = BufWriter::new(std::fs::File::create(report_file)?);
+            output::write_rewards(&mut w, &payout.paid, &args.output_format)?;
+        }
+    }
+
+    if let Some(mut ledger) = ledger {
+        ledger.reconcile(&engine.get_accounts())?;
+        ledger.flush()?;
+    }
+
+    if let Some(history_file) = &args.balance_history_file {
+        info!("Writing balance history to {}", history_file);
+        let mut w = BufWriter::new(std::fs::File::create(history_file)?);
+        output::write_balance_history(&mut w, engine.balance_history(), &args.output_format)?;
+    }
+
+    if let Some(report_file) = &args.open_disputes_report {
+        let disputes = engine.open_disputes()?;
+        info!(
+            "Writing {} open disputes to {}",
+            disputes.len(),
+            report_file
+        );
+        let mut w = BufWriter::new(std::fs::File::create(report_file)?);
+        output::write_open_disputes(&mut w, &disputes, &args.output_format)?;
+    }
+
+    if let (Some(query), Some(report_file)) = (&args.query, &args.query_report) {
+        let transactions = engine.query(query)?;
+        info!(
+            "Writing {} transactions matching the query to {}",
+            transactions.len(),
+            report_file
+        );
+        let mut w = BufWriter::new(std::fs::File::create(report_file)?);
+        output::write_stored_transactions(&mut w, &transactions, &args.output_format)?;
+    }
+
+    if let (Some(template_file), Some(report_file)) = (&args.report_template, &args.report_file) {
+        info!(
+            "Writing report to {} with template {}",
+            report_file, template_file
+        );
+        write_template_report(
+            template_file,
+            report_file,
+            &engine.get_accounts(),
+            &engine.stats(),
+            &args.output_format,
+        )?;
+    }
+
+    if !engine.suspects().is_empty() {
+        warn!(
+            "Found {} suspected duplicate deposits",
+            engine.suspects().len()
+        );
+    }
+
+    if let (Some(account_map), Some(account_map_file)) = (account_map, &args.account_map_file) {
+        info!(
+            "Saving {} account references to {}",
+            account_map.len(),
+            account_map_file
+        );
+        account_map.save(account_map_file)?;
+    }
+
+    if let Some(state_file) = &args.state_file {
+        info!("Saving state to {}", state_file);
+        state::save_state(state_file, &engine.state()?)?;
+    }
+
+    if let (Some(graph), Some(graph_file)) = (graph, &args.graph_file) {
+        info!("Writing transaction graph to {}", graph_file);
+        let mut w = BufWriter::new(std::fs::File::create(graph_file)?);
+        graph.write(args.graph_format, &mut w)?;
+    }
+
+    if engine.evicted_transactions() > 0 {
+        info!(
+            "Evicted {} transactions from the transaction index",
+            engine.evicted_transactions()
+        );
+    }
+
+    info!("Finished processing: {}", engine.stats());
+    if let Some(latencies) = engine.latencies() {
+        info!("Processing latency: {}", latencies);
+    }
+    if let (Some(client_counts), Some(hot_clients)) = (&client_counts, args.hot_clients) {
+        info!(
+            "Hottest clients of {} transactions: {}",
+            client_counts.total(),
+            client_counts.display_top(hot_clients)
+        );
+    }
+
+    let accounts = engine.get_accounts();
+    let mut written = accounts.clone();
+    if args.skip_empty_accounts {
+        written.retain(|acc| !engine.is_empty_account(acc));
+        info!("Skipping {} empty accounts", accounts.len() - written.len());
+    }
+
+    match &args.output_dir {
+        Some(output_dir) => {
+            info!(
+                "Writing accounts to {} shards in {}",
+                args.output_shards, output_dir
+            );
+            std::fs::create_dir_all(output_dir)?;
+            output::write_sharded_accounts(
+                output_dir,
+                args.output_shards,
+                &written,
+                engine.rewards(),
+                &args.output_format,
+            )?;
+        }
+        None => output::write_accounts(
+            &mut io::stdout().lock(),
+            &written,
+            engine.rewards(),
+            &args.output_format,
+        )?,
+    }
+
+    Ok(RunSummary {
+        rows,
+        rejected,
+        accounts: accounts.len(),
+        locked_accounts: accounts.iter().filter(|acc| acc.is_locked).count(),
+        skipped_accounts: accounts.len() - written.len(),
+    })
+}
+
+/// Configures the engine from the options shared by processing and reprocessing.
+fn engine_builder<A: account::Manager>(
+    args: &Args,
+    accounts: A,
+    budget: Option<MemoryBudget>,
+    client_access: Option<&ClientAccess>,
+) -> anyhow::Result<EngineBuilder<A>> {
+    let mut builder = Engine::builder(accounts);
+    // An explicit cap takes precedence over the memory budget.
+    let max_stored_txs = args
+        .max_stored_txs
+        .or_else(|| budget.map(|budget| budget.max_stored_txs()));
+    if let Some(max_stored_txs) = max_stored_txs {
+        builder = builder.max_stored_transactions(max_stored_txs);
+    }
+    if let Some(spill_file) = &args.spill_file {
+        builder =
+            builder.eviction_policy(EvictionPolicy::SpillToDisk(SpillFile::create(spill_file)?));
+    }
+    if let Some(lmdb_dir) = &args.lmdb_dir {
+        builder = with_lmdb_index(builder, lmdb_dir)?;
+    }
+    if let Some(disputable_types) = &args.disputable_types {
+        builder = builder.disputable_types(disputable_types.clone());
+    }
+    if let Some(stats_interval) = args.stats_interval {
+        builder = builder.stats_interval(stats_interval);
+    }
+    if let Some(tx_id_collisions) = args.tx_id_collisions {
+        builder = builder.tx_id_collisions(tx_id_collisions);
+    }
+    if let Some(reward_rate) = args.reward_rate {
+        builder = builder.reward_rate(reward_rate);
+    }
+    builder = builder.rounding(args.output_format.rounding);
+    if args.balance_history_file.is_some() {
+        builder = builder.balance_history_interval(args.balance_history_interval);
+    }
+    if let Some(client_access) = client_access {
+        builder = builder.client_access(client_access.clone());
+    }
+    if let Some(threshold) = args.duplicate_threshold {
+        let window = budget.map_or(args.duplicate_window, |budget| {
+            args.duplicate_window.min(budget.duplicate_window())
+        });
+        builder = builder.duplicate_detector(DuplicateDetector::new(
+            threshold,
+            window,
+            args.strict_duplicates,
+        ));
+    }
+    builder = builder
+        .unknown_types(args.unknown_types)
+        .record_latencies(args.latency_stats)
+        .unlock_on_chargeback_reversal(args.unlock_on_chargeback_reversal)
+        .require_open_accounts(args.require_open_accounts)
+        .create_accounts_on_disputes(!args.no_dispute_only_accounts)
+        .abort_on_missing_references(args.require_prior_state);
+    Ok(builder)
+}
+
+fn load_anonymizer(args: &Args) -> anyhow::Result<Option<Anonymizer>> {
+    match &args.anonymize_key_file {
+        Some(key_file) => Ok(Some(Anonymizer::load(key_file)?)),
+        None => Ok(None),
+    }
+}
+
+fn load_client_access(
+    args: &Args,
+    anonymizer: Option<&Anonymizer>,
+) -> anyhow::Result<Option<ClientAccess>> {
+    let client_access = match (&args.allow_clients_file, &args.block_clients_file) {
+        (Some(allow_file), _) => ClientAccess::load_allowlist(allow_file)?,
+        (None, Some(block_file)) => ClientAccess::load_blocklist(block_file)?,
+        (None, None) => return Ok(None),
+    };
+    // Listed clients are given as real ids but transactions only carry pseudonyms.
+    Ok(Some(match anonymizer {
+        Some(anonymizer) => client_access.map_clients(|id| anonymizer.pseudonym(id)),
+        None => client_access,
+    }))
+}
+
+/// Reads transactions from CSV with the column, amount and client id options of `args`.
+fn csv_tx_reader<'a, R: io::Read>(
+    args: &Args,
+    csv_reader: &'a mut csv::Reader<R>,
+    account_map: Option<&'a mut AccountMap>,
+    anonymizer: Option<&'a Anonymizer>,
+) -> CsvTxReader<'a, R> {
+    let mut tx_reader = CsvTxReader::new(csv_reader).with_amount_locale(args.amount_locale);
+    if args.hashed_tx_ids {
+        tx_reader = tx_reader.with_hashed_tx_ids();
+    }
+    if args.signed_amounts {
+        tx_reader = tx_reader.with_signed_amounts();
+    }
+    if let Some(account_map) = account_map {
+        tx_reader = tx_reader.with_account_map(account_map);
+    }
+    if let Some(anonymizer) = anonymizer {
+        tx_reader = tx_reader.with_anonymizer(anonymizer);
+    }
+    tx_reader
+}
+
+#[cfg(feature = "lmdb")]
+fn with_lmdb_index<A: account::Manager>(
+    builder: EngineBuilder<A>,
+    dir: &str,
+) -> anyhow::Result<EngineBuilder<A>> {
+    const LMDB_MAP_SIZE: usize = 64 * 1024 * 1024 * 1024;
+
+    info!("Storing transaction index in LMDB environment {}", dir);
+    Ok(
+        builder.tx_index(payment_transaction_engine::lmdb::LmdbTxStore::open(
+            dir,
+            LMDB_MAP_SIZE,
+        )?),
+    )
+}
+
+#[cfg(not(feature = "lmdb"))]
+fn with_lmdb_index<A: account::Manager>(
+    _builder: EngineBuilder<A>,
+    _dir: &str,
+) -> anyhow::Result<EngineBuilder<A>> {
+    Err(anyhow!(
+        "--lmdb-dir requires building with the lmdb feature enabled"
+    ))
+}
+
+/// Samples the CPU while `run` executes and writes a flamegraph of the samples to `svg_file`.
+#[cfg(feature = "profile")]
+fn profile<T>(svg_file: &str, run: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    const SAMPLES_PER_SEC: i32 = 997;
 
-    info!("Processing transaction file {}", args.transactions_file);
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(SAMPLES_PER_SEC)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    let result = run();
 
-    let file = std::fs::File::open(args.transactions_file)?;
+    info!("Writing flamegraph to {}", svg_file);
+    guard
+        .report()
+        .build()?
+        .flamegraph(std::fs::File::create(svg_file)?)?;
+    result
+}
 
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .from_reader(file);
+#[cfg(not(feature = "profile"))]
+fn profile<T>(_svg_file: &str, _run: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    Err(anyhow!(
+        "--profile requires building with the profile feature enabled"
+    ))
+}
 
-    let tx_reader = CsvTxReader::new(&mut csv_reader);
+#[cfg(feature = "io-uring")]
+fn uring_input(path: &str) -> anyhow::Result<Input> {
+    Ok(Input::uring(path)?)
+}
 
-    let accounts = account::SimpleManager::new();
-    let mut engine = Engine::new(accounts);
-    engine.process_all(tx_reader);
+#[cfg(not(feature = "io-uring"))]
+fn uring_input(_path: &str) -> anyhow::Result<Input> {
+    Err(anyhow!(
+        "--io-uring requires building with the io-uring feature enabled"
+    ))
+}
 
-    print_accounts(engine.get_accounts());
+/// Posts the outcome of `run` to the configured notification endpoints. A notification that
+/// cannot be sent is logged rather than failing the run.
+#[cfg(feature = "notify")]
+fn notify(
+    args: &Args,
+    run: impl FnOnce() -> anyhow::Result<RunSummary>,
+) -> anyhow::Result<RunSummary> {
+    use payment_transaction_engine::notify::Notifier;
 
+    let notifiers: Vec<_> = (args.notify_slack.iter().map(Notifier::slack))
+        .chain(args.notify_webhook.iter().map(Notifier::webhook))
+        .collect();
+    let result = run();
+
+    let name = args.transactions_files.join(", ");
+    for notifier in &notifiers {
+        if let Err(err) = notifier.notify(&name, result.as_ref()) {
+            warn!("{:#}", err);
+        }
+    }
+    result
+}
+
+#[cfg(not(feature = "notify"))]
+fn notify(
+    _args: &Args,
+    _run: impl FnOnce() -> anyhow::Result<RunSummary>,
+) -> anyhow::Result<RunSummary> {
+    Err(anyhow!(
+        "--notify-slack and --notify-webhook require building with the notify feature enabled"
+    ))
+}
+
+#[cfg(feature = "template")]
+fn write_template_report(
+    template_file: &str,
+    report_file: &str,
+    accounts: &[&Account],
+    stats: &EngineStats,
+    format: &OutputFormat,
+) -> anyhow::Result<()> {
+    let template = std::fs::read_to_string(template_file)?;
+    let report = payment_transaction_engine::report::render_report(
+        template_file,
+        &template,
+        accounts,
+        stats,
+        format,
+    )?;
+    std::fs::write(report_file, report)?;
     Ok(())
 }
 
+#[cfg(not(feature = "template"))]
+fn write_template_report(
+    _template_file: &str,
+    _report_file: &str,
+    _accounts: &[&Account],
+    _stats: &EngineStats,
+    _format: &OutputFormat,
+) -> anyhow::Result<()> {
+    Err(anyhow!(
+        "--report-template requires building with the template feature enabled"
+    ))
+}
+
+#[cfg(feature = "xlsx")]
+fn xlsx_input(path: &str) -> anyhow::Result<Input> {
+    Input::xlsx(path)
+}
+
+#[cfg(not(feature = "xlsx"))]
+fn xlsx_input(_path: &str) -> anyhow::Result<Input> {
+    Err(anyhow!(
+        "--input-format xlsx requires building with the xlsx feature enabled"
+    ))
+}
+
+#[cfg(feature = "mmap")]
+fn map_input(path: &str) -> anyhow::Result<Input> {
+    Ok(Input::map(path)?)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn map_input(_path: &str) -> anyhow::Result<Input> {
+    Err(anyhow!(
+        "--mmap requires building with the mmap feature enabled"
+    ))
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Process(Box<Args>),
+    FuzzRun {
+        seed: u64,
+        rows: usize,
+    },
+    Bench {
+        rows: usize,
+        max_stored_txs: Option<usize>,
+        track_disputes: bool,
+    },
+    Reprocess(Box<Args>),
+}
+
 #[derive(Debug, PartialEq)]
 struct Args {
-    transactions_file: String,
+    transactions_files: Vec<String>,
+    graph_file: Option<String>,
+    graph_format: GraphFormat,
+    duplicate_threshold: Option<usize>,
+    duplicate_window: usize,
+    strict_duplicates: bool,
+    failure_rate: Option<f64>,
+    seed: u64,
+    max_stored_txs: Option<usize>,
+    max_memory: Option<MemoryLimit>,
+    spill_file: Option<String>,
+    lmdb_dir: Option<String>,
+    disputable_types: Option<Vec<TxType>>,
+    enrichers: Vec<EnricherSpec>,
+    stats_interval: Option<usize>,
+    latency_stats: bool,
+    hot_clients: Option<usize>,
+    max_tps: Option<NonZeroU32>,
+    tx_id_collisions: Option<TxIdCollisionPolicy>,
+    hashed_tx_ids: bool,
+    signed_amounts: bool,
+    mmap: bool,
+    io_uring: bool,
+    anonymize_key_file: Option<String>,
+    account_map_file: Option<String>,
+    strict_account_map: bool,
+    output_format: OutputFormat,
+    output_dir: Option<String>,
+    output_shards: usize,
+    amount_locale: AmountLocale,
+    input_format: InputFormat,
+    redis_url: Option<String>,
+    redis_prefix: String,
+    account_cache: Option<usize>,
+    until_tx: Option<TxId>,
+    until_row: Option<usize>,
+    close_open_disputes: Option<DisputeResolution>,
+    unlock_on_chargeback_reversal: bool,
+    require_open_accounts: bool,
+    no_dispute_only_accounts: bool,
+    open_disputes_report: Option<String>,
+    reward_rate: Option<Amount>,
+    rewards_report: Option<String>,
+    report_template: Option<String>,
+    report_file: Option<String>,
+    query: Option<TxQuery>,
+    query_report: Option<String>,
+    skip_empty_accounts: bool,
+    notify_slack: Option<String>,
+    notify_webhook: Option<String>,
+    balance_history_file: Option<String>,
+    balance_history_interval: u64,
+    profile_file: Option<String>,
+    receipts_file: Option<String>,
+    ledger_file: Option<String>,
+    ledger_format: LedgerFormat,
+    ledger_currency: String,
+    unknown_types: UnknownTypePolicy,
+    quarantine_file: Option<String>,
+    allow_clients_file: Option<String>,
+    block_clients_file: Option<String>,
+    quarantine_blocked: bool,
+    state_file: Option<String>,
+    require_prior_state: bool,
+    initial_accounts_file: Option<String>,
 }
 
-fn parse_args(args: Vec<String>) -> anyhow::Result<Args> {
-    match args.get(1) {
-        Some(tx_file) => Ok(Args {
-            transactions_file: tx_file.to_string(),
-        }),
-        None => Err(anyhow!("No transaction file provided")),
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            transactions_files: Vec::new(),
+            graph_file: None,
+            graph_format: GraphFormat::Dot,
+            duplicate_threshold: None,
+            duplicate_window: 100,
+            strict_duplicates: false,
+            failure_rate: None,
+            seed: 0,
+            max_stored_txs: None,
+            max_memory: None,
+            spill_file: None,
+            lmdb_dir: None,
+            disputable_types: None,
+            enrichers: Vec::new(),
+            stats_interval: None,
+            latency_stats: false,
+            hot_clients: None,
+            max_tps: None,
+            tx_id_collisions: None,
+            hashed_tx_ids: false,
+            signed_amounts: false,
+            mmap: false,
+            io_uring: false,
+            anonymize_key_file: None,
+            account_map_file: None,
+            strict_account_map: false,
+            output_format: OutputFormat::default(),
+            output_dir: None,
+            output_shards: 16,
+            amount_locale: AmountLocale::default(),
+            input_format: InputFormat::default(),
+            redis_url: None,
+            redis_prefix: "payments".to_string(),
+            account_cache: None,
+            until_tx: None,
+            until_row: None,
+            close_open_disputes: None,
+            unlock_on_chargeback_reversal: false,
+            require_open_accounts: false,
+            no_dispute_only_accounts: false,
+            open_disputes_report: None,
+            reward_rate: None,
+            rewards_report: None,
+            report_template: None,
+            report_file: None,
+            query: None,
+            query_report: None,
+            skip_empty_accounts: false,
+            notify_slack: None,
+            notify_webhook: None,
+            balance_history_file: None,
+            balance_history_interval: 100,
+            profile_file: None,
+            receipts_file: None,
+            ledger_file: None,
+            ledger_format: LedgerFormat::default(),
+            ledger_currency: "USD".to_string(),
+            unknown_types: UnknownTypePolicy::default(),
+            quarantine_file: None,
+            allow_clients_file: None,
+            block_clients_file: None,
+            quarantine_blocked: false,
+            state_file: None,
+            require_prior_state: false,
+            initial_accounts_file: None,
+        }
     }
 }
 
-fn print_accounts(accounts: Vec<&Account>) {
-    println!("client, available, held, total, locked");
-    for acc in accounts {
-        println!(
-            "{}, {}, {}, {}, {}",
-            acc.client_id,
-            acc.available_amount.round_dp(4),
-            acc.held_amount.round_dp(4),
-            (acc.available_amount + acc.held_amount).round_dp(4),
-            acc.is_locked
-        );
+fn parse_command(args: Vec<String>) -> anyhow::Result<Command> {
+    match args.get(1).map(String::as_str) {
+        Some("fuzz-run") => parse_fuzz_args(args),
+        Some("bench") => parse_bench_args(args),
+        Some("replay") => parse_replay_args(args),
+        Some("reprocess") => parse_reprocess_args(args),
+        _ => Ok(Command::Process(Box::new(parse_args(args)?))),
     }
 }
 
+fn parse_fuzz_args(args: Vec<String>) -> anyhow::Result<Command> {
+    let mut seed = 0;
+    let mut rows = 10_000;
+
+    let mut args = args.into_iter().skip(2);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => seed = parse_option(&arg, args.next())?,
+            "--rows" => rows = parse_option(&arg, args.next())?,
+            _ => return Err(anyhow!("Unknown option {}", arg)),
+        }
+    }
+
+    Ok(Command::FuzzRun { seed, rows })
+}
+
+fn parse_bench_args(args: Vec<String>) -> anyhow::Result<Command> {
+    let mut rows = 10_000_000;
+    let mut max_stored_txs = None;
+    let mut track_disputes = true;
+
+    let mut args = args.into_iter().skip(2);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Allow readable counts such as 50_000_000.
+            "--rows" => rows = parse_option(&arg, args.next().map(|v| v.replace('_', "")))?,
+            "--max-stored-txs" => max_stored_txs = Some(parse_option(&arg, args.next())?),
+            "--no-disputes" => track_disputes = false,
+            _ => return Err(anyhow!("Unknown option {}", arg)),
+        }
+    }
+
+    Ok(Command::Bench {
+        rows,
+        max_stored_txs,
+        track_disputes,
+    })
+}
+
+/// Reprocess takes the same options as the run that saved the state, so the engine and reader
+/// are configured the same way.
+fn parse_reprocess_args(mut args: Vec<String>) -> anyhow::Result<Command> {
+    args.remove(1);
+    let parsed = parse_args(args)?;
+
+    if parsed.state_file.is_none() {
+        return Err(anyhow!("reprocess requires --state"));
+    }
+    if parsed.transactions_files.len() > 1 {
+        return Err(anyhow!("reprocess takes a single file"));
+    }
+    if parsed.input_format != InputFormat::Csv {
+        return Err(anyhow!("reprocess only reads CSV files"));
+    }
+
+    Ok(Command::Reprocess(Box::new(parsed)))
+}
+
+/// Replay takes the same options as processing but must be told where to stop.
+fn parse_replay_args(mut args: Vec<String>) -> anyhow::Result<Command> {
+    args.remove(1);
+    let parsed = parse_args(args)?;
+
+    if parsed.until_tx.is_none() && parsed.until_row.is_none() {
+        return Err(anyhow!("replay requires --until-tx or --until-row"));
+    }
+
+    Ok(Command::Process(Box::new(parsed)))
+}
+
+fn parse_args(args: Vec<String>) -> anyhow::Result<Args> {
+    let mut parsed = Args::default();
+
+    let mut args = args.into_iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--graph" => parsed.graph_file = Some(option_value(&arg, args.next())?),
+            "--graph-format" => parsed.graph_format = option_value(&arg, args.next())?.parse()?,
+            "--detect-duplicates" => {
+                parsed.duplicate_threshold = Some(parse_option(&arg, args.next())?)
+            }
+            "--duplicate-window" => parsed.duplicate_window = parse_option(&arg, args.next())?,
+            "--strict-duplicates" => parsed.strict_duplicates = true,
+            "--simulate-failures" => parsed.failure_rate = Some(parse_option(&arg, args.next())?),
+            "--seed" => parsed.seed = parse_option(&arg, args.next())?,
+            "--max-stored-txs" => parsed.max_stored_txs = Some(parse_option(&arg, args.next())?),
+            "--max-memory" => parsed.max_memory = Some(option_value(&arg, args.next())?.parse()?),
+            "--spill-file" => parsed.spill_file = Some(option_value(&arg, args.next())?),
+            "--lmdb-dir" => parsed.lmdb_dir = Some(option_value(&arg, args.next())?),
+            "--disputable-types" => {
+                parsed.disputable_types = Some(
+                    option_value(&arg, args.next())?
+                        .split(',')
+                        .map(|tx_type| Ok(tx_type.trim().parse()?))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            "--no-disputes" => parsed.disputable_types = Some(Vec::new()),
+            "--stats-interval" => parsed.stats_interval = Some(parse_option(&arg, args.next())?),
+            "--latency-stats" => parsed.latency_stats = true,
+            "--hot-clients" => parsed.hot_clients = Some(parse_option(&arg, args.next())?),
+            "--max-tps" => parsed.max_tps = Some(parse_option(&arg, args.next())?),
+            "--tx-id-collisions" => {
+                parsed.tx_id_collisions = Some(option_value(&arg, args.next())?.parse()?)
+            }
+            "--hashed-tx-ids" => parsed.hashed_tx_ids = true,
+            "--signed-amounts" => parsed.signed_amounts = true,
+            "--mmap" => parsed.mmap = true,
+            "--io-uring" => parsed.io_uring = true,
+            "--enrich" => parsed
+                .enrichers
+                .push(option_value(&arg, args.next())?.parse()?),
+            "--anonymize" => parsed.anonymize_key_file = Some(option_value(&arg, args.next())?),
+            "--account-map" => parsed.account_map_file = Some(option_value(&arg, args.next())?),
+            "--strict-account-map" => parsed.strict_account_map = true,
+            "--redis-url" => parsed.redis_url = Some(option_value(&arg, args.next())?),
+            "--redis-prefix" => parsed.redis_prefix = option_value(&arg, args.next())?,
+            "--account-cache" => parsed.account_cache = Some(parse_option(&arg, args.next())?),
+            "--until-tx" => parsed.until_tx = Some(parse_option(&arg, args.next())?),
+            "--close-open-disputes" => {
+                parsed.close_open_disputes = Some(option_value(&arg, args.next())?.parse()?)
+            }
+            "--unlock-on-chargeback-reversal" => parsed.unlock_on_chargeback_reversal = true,
+            "--require-open-accounts" => parsed.require_open_accounts = true,
+            "--no-dispute-only-accounts" => parsed.no_dispute_only_accounts = true,
+            "--open-disputes-report" => {
+                parsed.open_disputes_report = Some(option_value(&arg, args.next())?)
+            }
+            "--reward-rate" => parsed.reward_rate = Some(parse_option(&arg, args.next())?),
+            "--rewards-report" => parsed.rewards_report = Some(option_value(&arg, args.next())?),
+            "--report-template" => parsed.report_template = Some(option_value(&arg, args.next())?),
+            "--report" => parsed.report_file = Some(option_value(&arg, args.next())?),
+            "--query" => parsed.query = Some(option_value(&arg, args.next())?.parse()?),
+            "--query-report" => parsed.query_report = Some(option_value(&arg, args.next())?),
+            "--notify-slack" => parsed.notify_slack = Some(option_value(&arg, args.next())?),
+            "--notify-webhook" => parsed.notify_webhook = Some(option_value(&arg, args.next())?),
+            "--profile" => parsed.profile_file = Some(option_value(&arg, args.next())?),
+            "--balance-history" => {
+                parsed.balance_history_file = Some(option_value(&arg, args.next())?)
+            }
+            "--balance-history-interval" => {
+                parsed.balance_history_interval = parse_option(&arg, args.next())?
+            }
+            "--receipts" => parsed.receipts_file = Some(option_value(&arg, args.next())?),
+            "--ledger" => parsed.ledger_file = Some(option_value(&arg, args.next())?),
+            "--ledger-format" => parsed.ledger_format = option_value(&arg, args.next())?.parse()?,
+            "--ledger-currency" => parsed.ledger_currency = option_value(&arg, args.next())?,
+            "--unknown-types" => parsed.unknown_types = option_value(&arg, args.next())?.parse()?,
+            "--quarantine-file" => parsed.quarantine_file = Some(option_value(&arg, args.next())?),
+            "--allow-clients" => parsed.allow_clients_file = Some(option_value(&arg, args.next())?),
+            "--block-clients" => parsed.block_clients_file = Some(option_value(&arg, args.next())?),
+            "--quarantine-blocked" => parsed.quarantine_blocked = true,
+            "--state" => parsed.state_file = Some(option_value(&arg, args.next())?),
+            "--require-prior-state" => parsed.require_prior_state = true,
+            "--initial-accounts" => {
+                parsed.initial_accounts_file = Some(option_value(&arg, args.next())?)
+            }
+            "--until-row" => parsed.until_row = Some(parse_option(&arg, args.next())?),
+            "--amount-locale" => parsed.amount_locale = option_value(&arg, args.next())?.parse()?,
+            "--input-format" => parsed.input_format = option_value(&arg, args.next())?.parse()?,
+            "--output-dir" => parsed.output_dir = Some(option_value(&arg, args.next())?),
+            "--output-shards" => parsed.output_shards = parse_option(&arg, args.next())?,
+            "--decimal-places" => {
+                parsed.output_format.decimal_places = parse_option(&arg, args.next())?
+            }
+            "--rounding" => parsed.output_format.rounding = parse_option(&arg, args.next())?,
+            "--pad-zeros" => parsed.output_format.pad_zeros = true,
+            "--separator" => parsed.output_format.separator = option_value(&arg, args.next())?,
+            "--output-version" => {
+                parsed.output_format.version = option_value(&arg, args.next())?.parse()?
+            }
+            "--legacy-output" => parsed.output_format.version = OutputVersion::V1,
+            "--skip-empty-accounts" => parsed.skip_empty_accounts = true,
+            "--integrity-footer" => parsed.output_format.integrity_footer = true,
+            _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {}", arg)),
+            _ => parsed.transactions_files.push(arg),
+        }
+    }
+
+    if parsed.transactions_files.is_empty() {
+        return Err(anyhow!("No transaction file provided"));
+    }
+    if parsed.unknown_types == UnknownTypePolicy::Quarantine && parsed.quarantine_file.is_none() {
+        return Err(anyhow!(
+            "--unknown-types quarantine requires --quarantine-file"
+        ));
+    }
+    if parsed.quarantine_blocked && parsed.quarantine_file.is_none() {
+        return Err(anyhow!("--quarantine-blocked requires --quarantine-file"));
+    }
+    if parsed.require_prior_state && parsed.state_file.is_none() {
+        return Err(anyhow!("--require-prior-state requires --state"));
+    }
+    if parsed.input_format == InputFormat::Iso8583
+        && (parsed.hashed_tx_ids || parsed.signed_amounts)
+    {
+        return Err(anyhow!(
+            "--hashed-tx-ids and --signed-amounts only apply to CSV and xlsx input"
+        ));
+    }
+    if parsed.input_format == InputFormat::Xlsx && (parsed.mmap || parsed.io_uring) {
+        return Err(anyhow!(
+            "--mmap and --io-uring cannot be combined with xlsx input"
+        ));
+    }
+    if parsed.report_template.is_some() != parsed.report_file.is_some() {
+        return Err(anyhow!(
+            "--report and --report-template must be given together"
+        ));
+    }
+    if parsed.query.is_some() != parsed.query_report.is_some() {
+        return Err(anyhow!("--query and --query-report must be given together"));
+    }
+    if parsed.account_cache.is_some() && parsed.redis_url.is_none() {
+        return Err(anyhow!("--account-cache requires --redis-url"));
+    }
+    if parsed.allow_clients_file.is_some() && parsed.block_clients_file.is_some() {
+        return Err(anyhow!(
+            "--allow-clients and --block-clients cannot be combined"
+        ));
+    }
+
+    Ok(parsed)
+}
+
+fn option_value(name: &str, value: Option<String>) -> anyhow::Result<String> {
+    value.ok_or_else(|| anyhow!("No value provided for option {}", name))
+}
+
+fn parse_option<T: FromStr>(name: &str, value: Option<String>) -> anyhow::Result<T> {
+    let value = option_value(name, value)?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid value {} for option {}", value, name))
+}
+
 #[cfg(test)]
 mod tests {
+    use payment_transaction_engine::amount::Rounding;
+
     use super::*;
 
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
     #[test]
     fn parse_args_should_return_ok() {
         let result = parse_args(vec!["app".to_string(), "transactions.csv".to_string()]);
@@ -74,7 +1274,11 @@ mod tests {
         assert!(result.is_ok());
 
         let args = result.unwrap();
-        assert_eq!(args.transactions_file, "transactions.csv");
+        assert_eq!(args.transactions_files, vec!["transactions.csv"]);
+        assert_eq!(args.graph_file, None);
+        assert_eq!(args.graph_format, GraphFormat::Dot);
+        assert_eq!(args.duplicate_threshold, None);
+        assert!(!args.strict_duplicates);
     }
 
     #[test]
@@ -86,4 +1290,918 @@ mod tests {
         let err = result.err().unwrap();
         assert_eq!(err.to_string(), "No transaction file provided");
     }
+
+    #[test]
+    fn parse_args_should_parse_graph_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "--graph",
+            "graph.graphml",
+            "--graph-format",
+            "graphml",
+            "transactions.csv",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.transactions_files, vec!["transactions.csv"]);
+        assert_eq!(args.graph_file, Some("graph.graphml".to_string()));
+        assert_eq!(args.graph_format, GraphFormat::GraphMl);
+    }
+
+    #[test]
+    fn parse_args_should_parse_duplicate_detection_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--detect-duplicates",
+            "3",
+            "--duplicate-window",
+            "20",
+            "--strict-duplicates",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.duplicate_threshold, Some(3));
+        assert_eq!(args.duplicate_window, 20);
+        assert!(args.strict_duplicates);
+    }
+
+    #[test]
+    fn parse_args_should_parse_failure_simulation_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--simulate-failures",
+            "0.25",
+            "--seed",
+            "42",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.failure_rate, Some(0.25));
+        assert_eq!(args.seed, 42);
+    }
+
+    #[test]
+    fn parse_args_should_return_err_when_option_value_invalid() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--detect-duplicates",
+            "x",
+        ]));
+
+        let err = result.err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value x for option --detect-duplicates"
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_transaction_index_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--max-stored-txs",
+            "1000",
+            "--spill-file",
+            "spill.csv",
+            "--lmdb-dir",
+            "txs.lmdb",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.max_stored_txs, Some(1000));
+        assert_eq!(args.spill_file, Some("spill.csv".to_string()));
+        assert_eq!(args.lmdb_dir, Some("txs.lmdb".to_string()));
+    }
+
+    #[test]
+    fn parse_args_should_parse_max_memory() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--max-memory",
+            "4GiB",
+        ]));
+
+        assert_eq!(
+            result.unwrap().max_memory,
+            Some(MemoryLimit::Bytes(4 << 30))
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_no_disputes() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--no-disputes"]));
+
+        assert_eq!(result.unwrap().disputable_types, Some(Vec::new()));
+    }
+
+    #[test]
+    fn parse_args_should_parse_disputable_types() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--disputable-types",
+            "deposit, withdrawal",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(
+            args.disputable_types,
+            Some(vec![TxType::Deposit, TxType::Withdrawal])
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_stats_interval() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--stats-interval",
+            "100000",
+        ]));
+
+        assert_eq!(result.unwrap().stats_interval, Some(100000));
+    }
+
+    #[test]
+    fn parse_args_should_parse_max_tps() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--max-tps", "5000"]));
+        assert_eq!(result.unwrap().max_tps, NonZeroU32::new(5000));
+
+        let result = parse_args(to_args(&["app", "transactions.csv", "--max-tps", "0"]));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid value 0 for option --max-tps"
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_hot_clients() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--hot-clients", "10"]));
+
+        assert_eq!(result.unwrap().hot_clients, Some(10));
+    }
+
+    #[test]
+    fn parse_args_should_parse_latency_stats() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--latency-stats"]));
+
+        assert!(result.unwrap().latency_stats);
+    }
+
+    #[test]
+    fn parse_args_should_accept_multiple_files_and_collision_policy() {
+        let result = parse_args(to_args(&[
+            "app",
+            "january.csv",
+            "february.csv",
+            "--tx-id-collisions",
+            "namespaced",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.transactions_files, vec!["january.csv", "february.csv"]);
+        assert_eq!(args.tx_id_collisions, Some(TxIdCollisionPolicy::Namespaced));
+    }
+
+    #[test]
+    fn parse_args_should_parse_hashed_tx_ids() {
+        let result = parse_args(to_args(&["app", "--hashed-tx-ids", "transactions.csv"]));
+
+        assert!(result.unwrap().hashed_tx_ids);
+    }
+
+    #[test]
+    fn parse_args_should_parse_signed_amounts() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--signed-amounts"]));
+
+        assert!(result.unwrap().signed_amounts);
+    }
+
+    #[test]
+    fn parse_args_should_parse_mmap() {
+        let result = parse_args(to_args(&["app", "--mmap", "transactions.csv"]));
+
+        assert!(result.unwrap().mmap);
+    }
+
+    #[test]
+    fn parse_args_should_parse_io_uring() {
+        let result = parse_args(to_args(&["app", "--io-uring", "transactions.csv"]));
+
+        assert!(result.unwrap().io_uring);
+    }
+
+    #[test]
+    fn parse_args_should_parse_enrichers_in_order() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--enrich",
+            "uppercase:currency",
+            "--enrich",
+            "metadata:source=bank-a",
+        ]));
+
+        let enrichers = result.unwrap().enrichers;
+        assert_eq!(
+            enrichers,
+            vec![
+                EnricherSpec::Uppercase {
+                    key: "currency".to_string()
+                },
+                EnricherSpec::Metadata {
+                    key: "source".to_string(),
+                    value: "bank-a".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_state_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--state",
+            "state.csv",
+            "--require-prior-state",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.state_file, Some("state.csv".to_string()));
+        assert!(args.require_prior_state);
+    }
+
+    #[test]
+    fn parse_args_should_parse_initial_accounts() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--initial-accounts",
+            "accounts.csv",
+        ]));
+
+        assert_eq!(
+            result.unwrap().initial_accounts_file,
+            Some("accounts.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_should_require_state_for_require_prior_state() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--require-prior-state",
+        ]));
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "--require-prior-state requires --state"
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_anonymize() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--anonymize",
+            "key.txt",
+        ]));
+
+        assert_eq!(
+            result.unwrap().anonymize_key_file,
+            Some("key.txt".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_account_map_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--account-map",
+            "accounts-map.csv",
+            "--strict-account-map",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.account_map_file, Some("accounts-map.csv".to_string()));
+        assert!(args.strict_account_map);
+    }
+
+    #[test]
+    fn parse_args_should_parse_output_format_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--decimal-places",
+            "2",
+            "--rounding",
+            "half-up",
+            "--pad-zeros",
+            "--separator",
+            ";",
+            "--legacy-output",
+            "--integrity-footer",
+        ]));
+
+        assert_eq!(
+            result.unwrap().output_format,
+            OutputFormat {
+                decimal_places: 2,
+                rounding: Rounding::HalfUp,
+                pad_zeros: true,
+                separator: ";".to_string(),
+                version: OutputVersion::V1,
+                integrity_footer: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_output_version() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--output-version",
+            "1",
+        ]));
+        assert_eq!(args.unwrap().output_format.version, OutputVersion::V1);
+
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--output-version",
+            "v2",
+        ]));
+        assert_eq!(args.unwrap().output_format.version, OutputVersion::V2);
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--output-version",
+            "3",
+        ]));
+        assert_eq!(result.unwrap().output_format.version, OutputVersion::V3);
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--output-version",
+            "4",
+        ]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_args_should_parse_skip_empty_accounts() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--skip-empty-accounts",
+        ]));
+
+        assert!(result.unwrap().skip_empty_accounts);
+    }
+
+    #[test]
+    fn parse_args_should_parse_sharded_output_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--output-dir",
+            "accounts",
+            "--output-shards",
+            "64",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.output_dir, Some("accounts".to_string()));
+        assert_eq!(args.output_shards, 64);
+    }
+
+    #[test]
+    fn parse_args_should_parse_amount_locale() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--amount-locale",
+            "comma",
+        ]));
+
+        assert_eq!(result.unwrap().amount_locale, AmountLocale::Comma);
+    }
+
+    #[test]
+    fn parse_args_should_parse_input_format() {
+        let result = parse_args(to_args(&[
+            "app",
+            "settlement.txt",
+            "--input-format",
+            "iso8583",
+        ]));
+        assert_eq!(result.unwrap().input_format, InputFormat::Iso8583);
+
+        let result = parse_args(to_args(&[
+            "app",
+            "settlement.txt",
+            "--input-format",
+            "iso8583",
+            "--signed-amounts",
+        ]));
+        assert!(result.is_err());
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.xlsx",
+            "--input-format",
+            "xlsx",
+            "--signed-amounts",
+        ]));
+        assert_eq!(result.unwrap().input_format, InputFormat::Xlsx);
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.xlsx",
+            "--input-format",
+            "xlsx",
+            "--mmap",
+        ]));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "--mmap and --io-uring cannot be combined with xlsx input"
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_redis_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--redis-url",
+            "redis://127.0.0.1/",
+            "--redis-prefix",
+            "eu",
+            "--account-cache",
+            "10000",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.redis_url, Some("redis://127.0.0.1/".to_string()));
+        assert_eq!(args.redis_prefix, "eu");
+        assert_eq!(args.account_cache, Some(10000));
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--account-cache",
+            "10",
+        ]));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "--account-cache requires --redis-url"
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_close_open_disputes() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--close-open-disputes",
+            "write-off",
+        ]));
+
+        assert_eq!(
+            result.unwrap().close_open_disputes,
+            Some(DisputeResolution::WriteOff)
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_unlock_on_chargeback_reversal() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--unlock-on-chargeback-reversal",
+        ]));
+
+        assert!(result.unwrap().unlock_on_chargeback_reversal);
+    }
+
+    #[test]
+    fn parse_args_should_parse_require_open_accounts() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--require-open-accounts",
+        ]));
+
+        assert!(result.unwrap().require_open_accounts);
+    }
+
+    #[test]
+    fn parse_args_should_parse_no_dispute_only_accounts() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--no-dispute-only-accounts",
+        ]));
+
+        assert!(result.unwrap().no_dispute_only_accounts);
+    }
+
+    #[test]
+    fn parse_args_should_parse_open_disputes_report() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--open-disputes-report",
+            "open-disputes.csv",
+        ]));
+
+        assert_eq!(
+            result.unwrap().open_disputes_report,
+            Some("open-disputes.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_report_options() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--report-template",
+            "report.html",
+            "--report",
+            "out.html",
+        ]))
+        .unwrap();
+        assert_eq!(args.report_template, Some("report.html".to_string()));
+        assert_eq!(args.report_file, Some("out.html".to_string()));
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--report-template",
+            "report.html",
+        ]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_args_should_parse_query_options() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--query",
+            "type = withdrawal AND amount > 1000",
+            "--query-report",
+            "large-withdrawals.csv",
+        ]))
+        .unwrap();
+        assert_eq!(
+            args.query,
+            Some("type = withdrawal AND amount > 1000".parse().unwrap())
+        );
+        assert_eq!(args.query_report, Some("large-withdrawals.csv".to_string()));
+
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--query",
+            "amount >",
+            "--query-report",
+            "out.csv",
+        ]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_args_should_parse_notify_options() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--notify-slack",
+            "https://hooks.slack.com/services/T0/B0/X",
+            "--notify-webhook",
+            "https://example.com/runs",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            args.notify_slack,
+            Some("https://hooks.slack.com/services/T0/B0/X".to_string())
+        );
+        assert_eq!(
+            args.notify_webhook,
+            Some("https://example.com/runs".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_profile() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--profile",
+            "flame.svg",
+        ]));
+
+        assert_eq!(result.unwrap().profile_file, Some("flame.svg".to_string()));
+    }
+
+    #[test]
+    fn parse_args_should_parse_balance_history_options() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--balance-history",
+            "history.csv",
+            "--balance-history-interval",
+            "10",
+        ]))
+        .unwrap();
+
+        assert_eq!(args.balance_history_file, Some("history.csv".to_string()));
+        assert_eq!(args.balance_history_interval, 10);
+    }
+
+    #[test]
+    fn parse_args_should_parse_reward_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--reward-rate",
+            "0.01",
+            "--rewards-report",
+            "rewards.csv",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.reward_rate, Some(Amount::new(1, 2)));
+        assert_eq!(args.rewards_report, Some("rewards.csv".to_string()));
+    }
+
+    #[test]
+    fn parse_args_should_parse_unknown_type_policy() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--unknown-types",
+            "quarantine",
+            "--quarantine-file",
+            "quarantine.csv",
+        ]))
+        .unwrap();
+
+        assert_eq!(args.unknown_types, UnknownTypePolicy::Quarantine);
+        assert_eq!(args.quarantine_file, Some("quarantine.csv".to_string()));
+    }
+
+    #[test]
+    fn parse_args_should_require_quarantine_file() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--unknown-types",
+            "quarantine",
+        ]));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_args_should_parse_client_lists() {
+        let args = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--block-clients",
+            "blocked.txt",
+            "--quarantine-blocked",
+            "--quarantine-file",
+            "quarantine.csv",
+        ]))
+        .unwrap();
+
+        assert_eq!(args.block_clients_file, Some("blocked.txt".to_string()));
+        assert!(args.quarantine_blocked);
+        assert!(parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--allow-clients",
+            "allowed.txt",
+            "--block-clients",
+            "blocked.txt",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_args_should_parse_receipts() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--receipts",
+            "receipts.csv",
+        ]));
+
+        assert_eq!(
+            result.unwrap().receipts_file,
+            Some("receipts.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_args_should_parse_ledger_options() {
+        let result = parse_args(to_args(&[
+            "app",
+            "transactions.csv",
+            "--ledger",
+            "journal.ledger",
+            "--ledger-format",
+            "ledger",
+            "--ledger-currency",
+            "EUR",
+        ]));
+
+        let args = result.unwrap();
+        assert_eq!(args.ledger_file, Some("journal.ledger".to_string()));
+        assert_eq!(args.ledger_format, LedgerFormat::Ledger);
+        assert_eq!(args.ledger_currency, "EUR");
+    }
+
+    #[test]
+    fn parse_command_should_parse_fuzz_run() {
+        let result = parse_command(to_args(&["app", "fuzz-run", "--seed", "9", "--rows", "50"]));
+
+        assert_eq!(result.unwrap(), Command::FuzzRun { seed: 9, rows: 50 });
+    }
+
+    #[test]
+    fn parse_command_should_parse_bench() {
+        let result = parse_command(to_args(&["app", "bench", "--rows", "50_000_000"]));
+
+        assert_eq!(
+            result.unwrap(),
+            Command::Bench {
+                rows: 50_000_000,
+                max_stored_txs: None,
+                track_disputes: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_should_parse_bench_without_disputes() {
+        let result = parse_command(to_args(&["app", "bench", "--no-disputes"]));
+
+        assert!(matches!(
+            result.unwrap(),
+            Command::Bench {
+                track_disputes: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_command_should_parse_reprocess() {
+        let result = parse_command(to_args(&[
+            "app",
+            "reprocess",
+            "quarantine.csv",
+            "--state",
+            "state.csv",
+            "--tx-id-collisions",
+            "namespaced",
+        ]));
+
+        assert_eq!(
+            result.unwrap(),
+            Command::Reprocess(Box::new(Args {
+                transactions_files: vec!["quarantine.csv".to_string()],
+                state_file: Some("state.csv".to_string()),
+                tx_id_collisions: Some(TxIdCollisionPolicy::Namespaced),
+                ..Default::default()
+            }))
+        );
+        assert!(parse_command(to_args(&["app", "reprocess", "quarantine.csv"])).is_err());
+        assert!(parse_command(to_args(&[
+            "app",
+            "reprocess",
+            "quarantine.csv",
+            "--state",
+            "state.csv",
+            "--input-format",
+            "iso8583",
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn reprocess_uses_the_namespaced_ids_of_the_saved_state() {
+        let mut first = Engine::builder(account::SimpleManager::new())
+            .tx_id_collisions(TxIdCollisionPolicy::Namespaced)
+            .build();
+        first.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            1,
+            5,
+            Amount::new(10, 0),
+        ))]);
+        first.start_source();
+        first.process_all(vec![Ok(Transaction::new(
+            TxType::Deposit,
+            2,
+            1,
+            Amount::new(3, 0),
+        ))]);
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("state.csv");
+        let file = dir.path().join("corrected.csv");
+        state::save_state(&state_file, &first.state().unwrap()).unwrap();
+        std::fs::write(
+            &file,
+            "type,client,tx,amount\ndeposit,1,1,4\ndispute,1,5,0\n",
+        )
+        .unwrap();
+        let mut args = Args {
+            transactions_files: vec![file.to_str().unwrap().to_string()],
+            state_file: Some(state_file.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        assert!(reprocess(&args, io::sink()).is_err());
+
+        args.tx_id_collisions = Some(TxIdCollisionPolicy::Namespaced);
+        let mut out = Vec::new();
+        reprocess(&args, &mut out).unwrap();
+
+        let saved = state::load_state(&state_file).unwrap().unwrap();
+        assert_eq!(saved.transactions.len(), 3);
+        let acc = saved
+            .accounts
+            .iter()
+            .find(|acc| acc.client_id == 1)
+            .unwrap();
+        assert_eq!(acc.available_amount, Amount::new(4, 0));
+        assert_eq!(acc.held_amount, Amount::new(10, 0));
+    }
+
+    #[test]
+    fn parse_command_should_parse_replay() {
+        let result = parse_command(to_args(&[
+            "app",
+            "replay",
+            "transactions.csv",
+            "--until-tx",
+            "500000",
+        ]));
+
+        match result.unwrap() {
+            Command::Process(args) => {
+                assert_eq!(args.transactions_files, vec!["transactions.csv"]);
+                assert_eq!(args.until_tx, Some(500000));
+                assert_eq!(args.until_row, None);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let err = parse_command(to_args(&["app", "replay", "transactions.csv"])).unwrap_err();
+        assert_eq!(err.to_string(), "replay requires --until-tx or --until-row");
+    }
+
+    #[test]
+    fn parse_command_should_default_to_processing_a_file() {
+        let result = parse_command(to_args(&["app", "transactions.csv"]));
+
+        match result.unwrap() {
+            Command::Process(args) => assert_eq!(args.transactions_files, vec!["transactions.csv"]),
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn parse_args_should_return_err_when_option_value_missing() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--graph"]));
+
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "No value provided for option --graph");
+    }
+
+    #[test]
+    fn parse_args_should_return_err_for_unknown_option() {
+        let result = parse_args(to_args(&["app", "transactions.csv", "--foo"]));
+
+        let err = result.err().unwrap();
+        assert_eq!(err.to_string(), "Unknown option --foo");
+    }
 }
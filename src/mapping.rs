@@ -0,0 +1,162 @@
+use std::{collections::HashMap, fs::File, io, path::Path};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rejection::{reject, RejectCode},
+    types::ClientId,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct MappingRecord {
+    reference: String,
+    client: ClientId,
+}
+
+/// Translates external account references, such as IBANs, to internal client ids.
+///
+/// Unknown references are given the next free client id unless the map is strict, in which case
+/// they are rejected.
+#[derive(Debug, Default)]
+pub struct AccountMap {
+    ids: HashMap<String, ClientId>,
+    next_id: ClientId,
+    strict: bool,
+}
+
+impl AccountMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Loads a map from a CSV file with `reference` and `client` columns, or starts an empty map
+    /// if the file does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::read(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn read(r: impl io::Read) -> anyhow::Result<Self> {
+        let mut map = Self::new();
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(r);
+
+        for result in csv_reader.deserialize() {
+            let record: MappingRecord = result?;
+            if map
+                .ids
+                .insert(record.reference.clone(), record.client)
+                .is_some()
+            {
+                return Err(anyhow!("Duplicate account reference {}", record.reference));
+            }
+            map.next_id = map.next_id.max(record.client.saturating_add(1));
+        }
+
+        Ok(map)
+    }
+
+    /// Rejects unknown references instead of creating client ids for them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn resolve(&mut self, reference: &str) -> anyhow::Result<ClientId> {
+        if let Some(client_id) = self.ids.get(reference) {
+            return Ok(*client_id);
+        }
+
+        if self.strict {
+            return Err(reject(
+                RejectCode::AccountNotFound,
+                format!("Unknown account reference {}", reference),
+            ));
+        }
+
+        let client_id = self.next_id;
+        self.next_id = client_id
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("No client ids left for account reference {}", reference))?;
+        self.ids.insert(reference.to_string(), client_id);
+        Ok(client_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Writes the map sorted by client id, in the format read by [`AccountMap::read`].
+    pub fn write(&self, w: impl io::Write) -> anyhow::Result<()> {
+        let mut records: Vec<_> = self.ids.iter().collect();
+        records.sort_by_key(|(_, client_id)| **client_id);
+
+        let mut csv_writer = csv::Writer::from_writer(w);
+        for (reference, client_id) in records {
+            csv_writer.serialize(MappingRecord {
+                reference: reference.clone(),
+                client: *client_id,
+            })?;
+        }
+        csv_writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.write(File::create(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_client_ids_on_first_sight() {
+        let mut map =
+            AccountMap::read("reference,client\nDE89370400440532013000,7\n".as_bytes()).unwrap();
+
+        assert_eq!(map.resolve("DE89370400440532013000").unwrap(), 7);
+        assert_eq!(map.resolve("GB29NWBK60161331926819").unwrap(), 8);
+        assert_eq!(map.resolve("GB29NWBK60161331926819").unwrap(), 8);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn strict_map_rejects_unknown_references() {
+        let mut map = AccountMap::new().strict(true);
+
+        let err = map.resolve("GB29NWBK60161331926819").unwrap_err();
+
+        assert_eq!(RejectCode::of(&err), RejectCode::AccountNotFound);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn write_and_read_round_trip() {
+        let mut map = AccountMap::new();
+        map.resolve("a").unwrap();
+        map.resolve("b").unwrap();
+
+        let mut out = Vec::new();
+        map.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out.clone()).unwrap(),
+            "reference,client\na,0\nb,1\n"
+        );
+
+        let mut read = AccountMap::read(out.as_slice()).unwrap();
+        assert_eq!(read.resolve("b").unwrap(), 1);
+        assert_eq!(read.resolve("c").unwrap(), 2);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use crate::types::{ClientId, TxType};
+
+/// Each power of two is split into this many linear buckets, bounding the error of a percentile
+/// to 1/8 of its value.
+const SUB_BUCKETS: u64 = 8;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+const BUCKETS: usize = ((64 - SUB_BUCKET_BITS) as usize + 1) * SUB_BUCKETS as usize;
+
+/// Histogram of latencies in nanoseconds with log-linear buckets, so recording is constant time
+/// and memory does not grow with the number of samples.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            count: 0,
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the upper bound of the bucket holding the `percentile` (0-100) latency, or `None`
+    /// if nothing was recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank.max(1) {
+                return Some(Duration::from_nanos(bucket_upper_bound(index)));
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS {
+        return nanos as usize;
+    }
+
+    let exponent = 63 - nanos.leading_zeros();
+    let sub_bucket = (nanos >> (exponent - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BUCKET_BITS + 1) as usize) * SUB_BUCKETS as usize + sub_bucket as usize
+}
+
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+
+    let shift = (index / SUB_BUCKETS) as u32 - 1;
+    let lower = (SUB_BUCKETS + index % SUB_BUCKETS) << shift;
+    lower.saturating_add((1 << shift) - 1)
+}
+
+const TX_TYPES: [TxType; 7] = [
+    TxType::Deposit,
+    TxType::Withdrawal,
+    TxType::Dispute,
+    TxType::Resolve,
+    TxType::Chargeback,
+    TxType::ChargebackReversal,
+    TxType::OpenAccount,
+];
+
+/// Processing latency histograms for each transaction type.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyMetrics {
+    histograms: [LatencyHistogram; 7],
+}
+
+impl LatencyMetrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn slot(tx_type: &TxType) -> usize {
+        match tx_type {
+            TxType::Deposit => 0,
+            TxType::Withdrawal => 1,
+            TxType::Dispute => 2,
+            TxType::Resolve => 3,
+            TxType::Chargeback => 4,
+            TxType::ChargebackReversal => 5,
+            TxType::OpenAccount => 6,
+        }
+    }
+
+    pub fn record(&mut self, tx_type: &TxType, latency: Duration) {
+        self.histograms[Self::slot(tx_type)].record(latency);
+    }
+
+    pub fn histogram(&self, tx_type: &TxType) -> &LatencyHistogram {
+        &self.histograms[Self::slot(tx_type)]
+    }
+}
+
+/// Lists p50 and p99 for each transaction type that was processed.
+impl fmt::Display for LatencyMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for tx_type in &TX_TYPES {
+            let histogram = self.histogram(tx_type);
+            if let (Some(p50), Some(p99)) = (histogram.percentile(50.0), histogram.percentile(99.0))
+            {
+                write!(
+                    f,
+                    "{}{} p50={:?} p99={:?} (n={})",
+                    separator,
+                    tx_type.as_str(),
+                    p50,
+                    p99,
+                    histogram.count()
+                )?;
+                separator = ", ";
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Transactions seen for each client, to find the hottest clients.
+#[derive(Debug, Clone, Default)]
+pub struct ClientCounts {
+    counts: HashMap<ClientId, u64>,
+    total: u64,
+}
+
+impl ClientCounts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record(&mut self, client_id: ClientId) {
+        *self.counts.entry(client_id).or_default() += 1;
+        self.total += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns up to `n` clients with the most transactions, busiest first and by client id
+    /// between equal counts.
+    pub fn top(&self, n: usize) -> Vec<(ClientId, u64)> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(client_id, count)| (*client_id, *count))
+            .collect();
+        counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Lists the top `n` clients with their transactions and share of all transactions.
+    pub fn display_top(&self, n: usize) -> String {
+        self.top(n)
+            .iter()
+            .map(|(client_id, count)| {
+                format!(
+                    "{} ({} txs, {:.1}%)",
+                    client_id,
+                    count,
+                    *count as f64 * 100.0 / self.total as f64
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Totals of a batch run, for logs and run notifications.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunSummary {
+    pub rows: usize,
+    pub rejected: usize,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+    /// Empty accounts left out of the output, see [`crate::engine::Engine::is_empty_account`].
+    pub skipped_accounts: usize,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows processed, {} rejected, {} accounts ({} locked)",
+            self.rows, self.rejected, self.accounts, self.locked_accounts
+        )?;
+        if self.skipped_accounts > 0 {
+            write!(f, ", {} empty accounts skipped", self.skipped_accounts)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_all_latencies_in_order() {
+        let mut previous = 0;
+        for nanos in (0..10_000).chain([u64::MAX / 2, u64::MAX]) {
+            let index = bucket(nanos);
+            assert!(index >= previous && index < BUCKETS, "{}", nanos);
+            assert!(bucket_upper_bound(index) >= nanos, "{}", nanos);
+            previous = index;
+        }
+        assert_eq!(bucket_upper_bound(bucket(16)), 17);
+    }
+
+    #[test]
+    fn percentiles_are_within_bucket_error() {
+        let mut histogram = LatencyHistogram::new();
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let p50 = histogram.percentile(50.0).unwrap();
+        let p99 = histogram.percentile(99.0).unwrap();
+        assert!(p50 >= Duration::from_micros(50) && p50 < Duration::from_micros(57));
+        assert!(p99 >= Duration::from_micros(99) && p99 < Duration::from_micros(112));
+        assert_eq!(LatencyHistogram::new().percentile(50.0), None);
+    }
+
+    #[test]
+    fn display_lists_recorded_types() {
+        let mut metrics = LatencyMetrics::new();
+        metrics.record(&TxType::Dispute, Duration::from_nanos(5));
+
+        assert_eq!(metrics.to_string(), "dispute p50=5ns p99=5ns (n=1)");
+    }
+
+    #[test]
+    fn top_lists_busiest_clients_first() {
+        let mut counts = ClientCounts::new();
+        for client_id in [3, 1, 2, 3, 2, 3, 4] {
+            counts.record(client_id);
+        }
+
+        assert_eq!(counts.top(3), vec![(3, 3), (2, 2), (1, 1)]);
+        assert_eq!(counts.display_top(1), "3 (3 txs, 42.9%)");
+        assert_eq!(counts.total(), 7);
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde_json::{json, Value};
+
+use crate::metrics::RunSummary;
+
+/// How long to wait for the receiving end before giving up on a notification.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Message layout a [`Notifier`] posts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotifyFormat {
+    /// A `text` message for a Slack incoming webhook.
+    Slack,
+    /// The run's status and totals as JSON fields, for other services to consume.
+    Webhook,
+}
+
+/// Posts the outcome of a batch run to a Slack incoming webhook or any other HTTP endpoint.
+pub struct Notifier {
+    url: String,
+    format: NotifyFormat,
+    agent: ureq::Agent,
+}
+
+impl Notifier {
+    pub fn new(url: impl Into<String>, format: NotifyFormat) -> Self {
+        Self {
+            url: url.into(),
+            format,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    pub fn slack(url: impl Into<String>) -> Self {
+        Self::new(url, NotifyFormat::Slack)
+    }
+
+    pub fn webhook(url: impl Into<String>) -> Self {
+        Self::new(url, NotifyFormat::Webhook)
+    }
+
+    /// Returns the JSON body posted for the run of `name` ending with `result`.
+    pub fn payload(&self, name: &str, result: Result<&RunSummary, &anyhow::Error>) -> Value {
+        match (self.format, result) {
+            (NotifyFormat::Slack, Ok(summary)) => json!({
+                "text": format!(":white_check_mark: Run of {} completed: {}", name, summary),
+            }),
+            (NotifyFormat::Slack, Err(err)) => json!({
+                "text": format!(":x: Run of {} failed: {:#}", name, err),
+            }),
+            (NotifyFormat::Webhook, Ok(summary)) => json!({
+                "run": name,
+                "status": "completed",
+                "rows": summary.rows,
+                "rejected": summary.rejected,
+                "accounts": summary.accounts,
+                "locked_accounts": summary.locked_accounts,
+                "skipped_accounts": summary.skipped_accounts,
+            }),
+            (NotifyFormat::Webhook, Err(err)) => json!({
+                "run": name,
+                "status": "failed",
+                "error": format!("{:#}", err),
+            }),
+        }
+    }
+
+    /// Posts the outcome of the run of `name`, failing if the endpoint does not accept it.
+    pub fn notify(
+        &self,
+        name: &str,
+        result: Result<&RunSummary, &anyhow::Error>,
+    ) -> anyhow::Result<()> {
+        let body = self.payload(name, result).to_string();
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+            .map_err(|err| anyhow!("Could not notify {}: {}", self.url, err))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    fn summary() -> RunSummary {
+        RunSummary {
+            rows: 10,
+            rejected: 2,
+            accounts: 3,
+            locked_accounts: 1,
+            skipped_accounts: 0,
+        }
+    }
+
+    #[test]
+    fn formats_slack_messages() {
+        let notifier = Notifier::slack("https://hooks.slack.com/services/T0/B0/X");
+
+        assert_eq!(
+            notifier.payload("transactions.csv", Ok(&summary())),
+            json!({
+                "text": ":white_check_mark: Run of transactions.csv completed: 10 rows processed, \
+                         2 rejected, 3 accounts (1 locked)"
+            })
+        );
+        assert_eq!(
+            notifier.payload(
+                "transactions.csv",
+                Err(&anyhow!("Aborted run: unknown type"))
+            ),
+            json!({ "text": ":x: Run of transactions.csv failed: Aborted run: unknown type" })
+        );
+    }
+
+    #[test]
+    fn posts_webhook_payloads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/runs", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut r = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                r.read_line(&mut line).unwrap();
+                if let Some(length) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            r.read_exact(&mut body).unwrap();
+            r.get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        });
+
+        Notifier::webhook(url)
+            .notify("transactions.csv", Ok(&summary()))
+            .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            json!({
+                "run": "transactions.csv",
+                "status": "completed",
+                "rows": 10,
+                "rejected": 2,
+                "accounts": 3,
+                "locked_accounts": 1,
+                "skipped_accounts": 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,944 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    thread,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amount::{Amount, Rounding},
+    engine::{BalanceSample, DisputeSummary, Receipt},
+    types::{fnv1a, Account, ClientId, StoredTx, Transaction, TxId, FNV1A_EMPTY},
+};
+
+/// A row of the accounts output, as read back from a previously written file.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AccountRecord {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+}
+
+impl AccountRecord {
+    pub fn from_account(acc: &Account) -> Self {
+        Self::rounded(acc, &OutputFormat::default())
+    }
+
+    /// The account the row was written from, up to rounding. `total` is derived from the other
+    /// balances, so it is not used.
+    pub fn to_account(&self) -> Account {
+        Account {
+            client_id: self.client_id,
+            is_locked: self.locked,
+            available_amount: self.available,
+            held_amount: self.held,
+        }
+    }
+
+    fn rounded(acc: &Account, format: &OutputFormat) -> Self {
+        let round = |amount| format.rounding.round(amount, format.decimal_places);
+        Self {
+            client_id: acc.client_id,
+            available: round(acc.available_amount),
+            held: round(acc.held_amount),
+            total: round(acc.available_amount + acc.held_amount),
+            locked: acc.is_locked,
+        }
+    }
+}
+
+/// Schema of the accounts output. Versions only ever add columns after the existing ones, so a
+/// parser written for an earlier version can read later output by ignoring trailing columns, and
+/// [`read_accounts`] reads every version.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputVersion {
+    /// The original five columns: `client, available, held, total, locked`.
+    V1,
+    /// Adds `status` (`active` or `locked`).
+    #[default]
+    V2,
+    /// Adds `pending_rewards`, the rewards accrued but not paid out yet.
+    V3,
+}
+
+impl OutputVersion {
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            OutputVersion::V1 => &["client", "available", "held", "total", "locked"],
+            OutputVersion::V2 => &["client", "available", "held", "total", "locked", "status"],
+            OutputVersion::V3 => &[
+                "client",
+                "available",
+                "held",
+                "total",
+                "locked",
+                "status",
+                "pending_rewards",
+            ],
+        }
+    }
+}
+
+impl FromStr for OutputVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" | "v1" => Ok(OutputVersion::V1),
+            "2" | "v2" => Ok(OutputVersion::V2),
+            "3" | "v3" => Ok(OutputVersion::V3),
+            _ => Err(anyhow!("Unknown output version {}", s)),
+        }
+    }
+}
+
+/// How amounts and columns are written to the accounts output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFormat {
+    /// Amounts are rounded to this many decimal places.
+    pub decimal_places: u32,
+    pub rounding: Rounding,
+    /// Pads amounts with trailing zeros to exactly `decimal_places` decimal places.
+    pub pad_zeros: bool,
+    pub separator: String,
+    pub version: OutputVersion,
+    /// Ends the accounts output with an integrity footer, see [`verify_footer`].
+    pub integrity_footer: bool,
+}
+
+impl OutputFormat {
+    pub const DEFAULT_DECIMAL_PLACES: u32 = 4;
+
+    /// Rounds `amount` to `decimal_places` and formats it, so every output rounds the same way.
+    pub(crate) fn amount(&self, amount: Amount) -> String {
+        let amount = self.rounding.round(amount, self.decimal_places);
+        match self.pad_zeros {
+            true => format!("{:.*}", self.decimal_places as usize, amount),
+            false => amount.to_string(),
+        }
+    }
+
+    fn write_row<S: AsRef<str>>(&self, w: &mut impl Write, fields: &[S]) -> io::Result<()> {
+        write_row(w, fields, &self.separator)
+    }
+}
+
+/// Writes one line of output, always ending with `\n` and in the order given.
+///
+/// Fields containing a quote, a line break or a non-space character of the separator are quoted,
+/// doubling any quotes inside them, so the line can be parsed back by a CSV reader.
+fn write_row<S: AsRef<str>>(w: &mut impl Write, fields: &[S], separator: &str) -> io::Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            w.write_all(separator.as_bytes())?;
+        }
+
+        let field = field.as_ref();
+        let needs_quotes = field
+            .chars()
+            .any(|c| matches!(c, '"' | '\n' | '\r') || (c != ' ' && separator.contains(c)));
+        if needs_quotes {
+            write!(w, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            w.write_all(field.as_bytes())?;
+        }
+    }
+    w.write_all(b"\n")
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self {
+            decimal_places: Self::DEFAULT_DECIMAL_PLACES,
+            rounding: Rounding::default(),
+            pad_zeros: false,
+            separator: ", ".to_string(),
+            version: OutputVersion::default(),
+            integrity_footer: false,
+        }
+    }
+}
+
+/// Passes writes through while hashing them, for the integrity footer.
+struct ChecksumWriter<'a, W: Write> {
+    inner: &'a mut W,
+    checksum: u64,
+}
+
+impl<W: Write> Write for ChecksumWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum = fnv1a(self.checksum, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the accounts, failing rather than writing any client id twice.
+pub fn write_accounts(
+    w: &mut impl Write,
+    accounts: &[&Account],
+    rewards: &BTreeMap<ClientId, Amount>,
+    format: &OutputFormat,
+) -> io::Result<()> {
+    let mut w = ChecksumWriter {
+        inner: w,
+        checksum: FNV1A_EMPTY,
+    };
+    let mut written = HashSet::with_capacity(accounts.len());
+
+    format.write_row(&mut w, format.version.columns())?;
+
+    for acc in accounts {
+        if !written.insert(acc.client_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Client id {} would be written twice", acc.client_id),
+            ));
+        }
+
+        let record = AccountRecord::rounded(acc, format);
+        let mut fields = vec![
+            record.client_id.to_string(),
+            format.amount(record.available),
+            format.amount(record.held),
+            format.amount(record.total),
+            record.locked.to_string(),
+        ];
+        if format.version != OutputVersion::V1 {
+            fields.push(acc.status().as_str().to_string());
+        }
+        if format.version == OutputVersion::V3 {
+            let pending = rewards.get(&acc.client_id).copied().unwrap_or_default();
+            fields.push(format.amount(pending));
+        }
+        format.write_row(&mut w, &fields)?;
+    }
+
+    if format.integrity_footer {
+        writeln!(
+            w.inner,
+            "# rows={} checksum=fnv1a64:{:016x}",
+            accounts.len(),
+            w.checksum
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Checks the integrity footer ending `contents` and returns the number of account rows it lists.
+///
+/// The footer is a final `# rows=N checksum=fnv1a64:HEX` line, where `N` counts the rows after
+/// the header and `HEX` is the 64-bit FNV-1a hash of every byte before the footer. A missing
+/// footer is an error, as the file may have been truncated.
+pub fn verify_footer(contents: &[u8]) -> anyhow::Result<usize> {
+    let (_, rows) = split_footer(contents)?
+        .ok_or_else(|| anyhow!("No integrity footer, the file may be truncated"))?;
+    Ok(rows)
+}
+
+/// Splits off and checks an integrity footer, if `contents` ends with one.
+fn split_footer(contents: &[u8]) -> anyhow::Result<Option<(&[u8], usize)>> {
+    let without_newline = contents.strip_suffix(b"\n").unwrap_or(contents);
+    let start = without_newline
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let (body, footer) = contents.split_at(start);
+    let Some(footer) = footer.strip_prefix(b"# ") else {
+        return Ok(None);
+    };
+
+    let footer = std::str::from_utf8(footer)?.trim_end();
+    let (rows, checksum) = footer
+        .strip_prefix("rows=")
+        .and_then(|footer| footer.split_once(" checksum=fnv1a64:"))
+        .and_then(|(rows, checksum)| {
+            Some((rows.parse().ok()?, u64::from_str_radix(checksum, 16).ok()?))
+        })
+        .ok_or_else(|| anyhow!("Malformed integrity footer: {}", footer))?;
+
+    if fnv1a(FNV1A_EMPTY, body) != checksum {
+        return Err(anyhow!("Checksum does not match the integrity footer"));
+    }
+    let body_rows = body
+        .iter()
+        .filter(|b| **b == b'\n')
+        .count()
+        .saturating_sub(1);
+    if body_rows != rows {
+        return Err(anyhow!(
+            "Found {} rows but the integrity footer lists {}",
+            body_rows,
+            rows
+        ));
+    }
+
+    Ok(Some((body, rows)))
+}
+
+/// Writes disputes that are still open, with their age in whole seconds and any attached
+/// metadata as `key=value` pairs separated by `;`.
+pub fn write_open_disputes(
+    w: &mut impl Write,
+    disputes: &[DisputeSummary],
+    format: &OutputFormat,
+) -> io::Result<()> {
+    format.write_row(w, &["client", "tx", "amount", "age_secs", "metadata"])?;
+    for dispute in disputes {
+        let metadata: Vec<String> = dispute
+            .metadata
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        format.write_row(
+            w,
+            &[
+                dispute.client_id.to_string(),
+                dispute.tx_id.to_string(),
+                format.amount(dispute.amount),
+                dispute.age.as_secs().to_string(),
+                metadata.join(";"),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes stored transactions, such as the results of a query, with their current state.
+pub fn write_stored_transactions(
+    w: &mut impl Write,
+    transactions: &[(TxId, StoredTx)],
+    format: &OutputFormat,
+) -> io::Result<()> {
+    format.write_row(w, &["type", "client", "tx", "amount", "state"])?;
+    for (tx_id, tx) in transactions {
+        format.write_row(
+            w,
+            &[
+                tx.tx_type.as_str().to_string(),
+                tx.client_id.to_string(),
+                tx_id.to_string(),
+                format.amount(tx.amount),
+                tx.state.as_str().to_string(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes a receipts file with one row per processed record, giving the file it was read from,
+/// its 1-based position in that file, its tx id, outcome code and the resulting balances.
+pub struct ReceiptWriter<W: Write> {
+    w: W,
+    format: OutputFormat,
+}
+
+impl<W: Write> ReceiptWriter<W> {
+    pub fn new(mut w: W, format: OutputFormat) -> io::Result<Self> {
+        format.write_row(
+            &mut w,
+            &["file", "row", "tx", "outcome", "available", "held"],
+        )?;
+        Ok(Self { w, format })
+    }
+
+    pub fn write(&mut self, file: &str, receipt: &Receipt) -> io::Result<()> {
+        let amount = |amount: Option<Amount>| {
+            amount.map_or(String::new(), |amount| self.format.amount(amount))
+        };
+        let fields = [
+            file.to_string(),
+            receipt.result.row.to_string(),
+            receipt
+                .result
+                .tx_id
+                .map_or(String::new(), |tx_id| tx_id.to_string()),
+            receipt.result.outcome.code().to_string(),
+            amount(receipt.available),
+            amount(receipt.held),
+        ];
+        self.format.write_row(&mut self.w, &fields)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// A record set aside under [`crate::engine::UnknownTypePolicy::Quarantine`].
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct QuarantinedRow {
+    pub file: String,
+    /// 1-based position of the record in its file.
+    pub row: usize,
+    pub error: String,
+}
+
+/// Writes quarantined records, quoting errors that contain the separator.
+pub fn write_quarantine(
+    w: &mut impl Write,
+    rows: &[QuarantinedRow],
+    format: &OutputFormat,
+) -> io::Result<()> {
+    format.write_row(w, &["file", "row", "error"])?;
+    for row in rows {
+        format.write_row(
+            w,
+            &[row.file.clone(), row.row.to_string(), row.error.clone()],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes balance samples in client order, oldest first, for charting balances over a run.
+pub fn write_balance_history(
+    w: &mut impl Write,
+    history: &BTreeMap<ClientId, Vec<BalanceSample>>,
+    format: &OutputFormat,
+) -> io::Result<()> {
+    format.write_row(w, &["client", "seq", "available", "held"])?;
+    for (client_id, samples) in history {
+        for sample in samples {
+            format.write_row(
+                w,
+                &[
+                    client_id.to_string(),
+                    sample.seq.to_string(),
+                    format.amount(sample.available),
+                    format.amount(sample.held),
+                ],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the rewards paid out by [`crate::engine::Engine::pay_out_rewards`], one row per client.
+pub fn write_rewards(
+    w: &mut impl Write,
+    payouts: &[Transaction],
+    format: &OutputFormat,
+) -> io::Result<()> {
+    format.write_row(w, &["client", "reward"])?;
+    for payout in payouts {
+        format.write_row(
+            w,
+            &[payout.client_id.to_string(), format.amount(payout.amount)],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the shard an account is written to by [`write_sharded_accounts`].
+///
+/// Client ids are spread with a Fibonacci hash, so loaders can recompute the shard of a client as
+/// `(client * 0x9E3779B97F4A7C15 mod 2^64) >> 32 mod shards`.
+pub fn shard_of(client_id: ClientId, shards: usize) -> usize {
+    // ClientId is already u64 with the client-id-u64 feature.
+    #[allow(clippy::unnecessary_cast)]
+    let hash = (client_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+    (hash % shards as u64) as usize
+}
+
+/// Writes the accounts into `shards` files in `dir`, one thread per file, plus a `manifest.csv`
+/// listing each shard's file and number of accounts.
+pub fn write_sharded_accounts(
+    dir: impl AsRef<Path>,
+    shards: usize,
+    accounts: &[&Account],
+    rewards: &BTreeMap<ClientId, Amount>,
+    format: &OutputFormat,
+) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    let shards = shards.max(1);
+
+    let mut sharded = vec![Vec::new(); shards];
+    for acc in accounts {
+        sharded[shard_of(acc.client_id, shards)].push(*acc);
+    }
+
+    let file_name = |shard: usize| format!("accounts-{:04}.csv", shard);
+
+    thread::scope(|scope| {
+        let writers: Vec<_> = sharded
+            .iter()
+            .enumerate()
+            .map(|(shard, accounts)| {
+                let path = dir.join(file_name(shard));
+                scope.spawn(move || -> anyhow::Result<()> {
+                    let mut w = BufWriter::new(File::create(path)?);
+                    write_accounts(&mut w, accounts, rewards, format)?;
+                    w.flush()?;
+                    Ok(())
+                })
+            })
+            .collect();
+
+        writers
+            .into_iter()
+            .try_for_each(|writer| writer.join().expect("shard writer panicked"))
+    })?;
+
+    let mut manifest = BufWriter::new(File::create(dir.join("manifest.csv"))?);
+    write_row(&mut manifest, &["shard", "file", "accounts"], ",")?;
+    for (shard, accounts) in sharded.iter().enumerate() {
+        write_row(
+            &mut manifest,
+            &[
+                shard.to_string(),
+                file_name(shard),
+                accounts.len().to_string(),
+            ],
+            ",",
+        )?;
+    }
+    manifest.flush()?;
+
+    Ok(())
+}
+
+/// Reads back accounts written with `format`, checking the integrity footer if there is one and
+/// that no client id is repeated.
+pub fn read_accounts(
+    mut r: impl io::Read,
+    format: &OutputFormat,
+) -> anyhow::Result<Vec<AccountRecord>> {
+    let mut contents = Vec::new();
+    r.read_to_end(&mut contents)?;
+    let body = match split_footer(&contents)? {
+        Some((body, _)) => body,
+        None => &contents,
+    };
+
+    // Spaces around the separator are trimmed from the fields, so only the rest delimits them.
+    let delimiter = match format.separator.trim() {
+        "" => format.separator.as_str(),
+        trimmed => trimmed,
+    };
+    let delimiter = match delimiter.as_bytes() {
+        [delimiter] => *delimiter,
+        _ => {
+            return Err(anyhow!(
+                "Cannot read accounts separated by {:?}",
+                format.separator
+            ))
+        }
+    };
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .trim(csv::Trim::All)
+        .from_reader(body);
+
+    let mut records = Vec::new();
+    let mut client_ids = HashSet::new();
+    for result in csv_reader.deserialize() {
+        let record: AccountRecord = result?;
+        if !client_ids.insert(record.client_id) {
+            return Err(anyhow!("Client id {} is listed twice", record.client_id));
+        }
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{amount::dec, types::TxType};
+
+    // Minor units cannot hold more than 4 decimal places.
+    #[cfg(not(feature = "amount-minor-units"))]
+    #[test]
+    fn write_accounts_rounds_amounts() {
+        let mut acc = Account::new(1);
+        acc.available_amount = dec!(1.23456);
+        acc.held_amount = dec!(2.0);
+        acc.is_locked = true;
+
+        let mut out = Vec::new();
+        write_accounts(
+            &mut out,
+            &[&acc],
+            &BTreeMap::new(),
+            &OutputFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client, available, held, total, locked, status\n1, 1.2346, 2.0, 3.2346, true, locked\n"
+        );
+    }
+
+    #[test]
+    fn write_accounts_uses_output_format() {
+        let mut acc = Account::new(1);
+        acc.available_amount = dec!(1.2345);
+        acc.held_amount = dec!(2);
+
+        let format = OutputFormat {
+            decimal_places: 2,
+            rounding: Rounding::HalfEven,
+            pad_zeros: true,
+            separator: ";".to_string(),
+            version: OutputVersion::V1,
+            integrity_footer: false,
+        };
+        let mut out = Vec::new();
+        write_accounts(&mut out, &[&acc], &BTreeMap::new(), &format).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client;available;held;total;locked\n1;1.23;2.00;3.23;false\n"
+        );
+    }
+
+    #[test]
+    fn write_accounts_uses_the_rounding_strategy() {
+        let mut acc = Account::new(1);
+        acc.available_amount = dec!(0.125);
+        acc.held_amount = dec!(1.0199);
+
+        let written = |rounding| {
+            let format = OutputFormat {
+                decimal_places: 2,
+                rounding,
+                version: OutputVersion::V1,
+                ..Default::default()
+            };
+            let mut out = Vec::new();
+            write_accounts(&mut out, &[&acc], &BTreeMap::new(), &format).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .nth(1)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(written(Rounding::HalfEven), "1, 0.12, 1.02, 1.14, false");
+        assert_eq!(written(Rounding::HalfUp), "1, 0.13, 1.02, 1.14, false");
+        assert_eq!(written(Rounding::Truncate), "1, 0.12, 1.01, 1.14, false");
+    }
+
+    #[test]
+    fn write_accounts_adds_pending_rewards_from_version_3() {
+        let accounts = [Account::new(1), Account::new(2)];
+        let format = OutputFormat {
+            version: OutputVersion::V3,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_accounts(
+            &mut out,
+            &accounts.iter().collect::<Vec<_>>(),
+            &[(2, dec!(1.5))].into(),
+            &format,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client, available, held, total, locked, status, pending_rewards\n\
+             1, 0, 0, 0, false, active, 0\n\
+             2, 0, 0, 0, false, active, 1.5\n"
+        );
+    }
+
+    #[test]
+    fn write_open_disputes_lists_disputes() {
+        let dispute = DisputeSummary {
+            client_id: 1,
+            tx_id: 7,
+            amount: dec!(2.5),
+            age: std::time::Duration::from_millis(90_500),
+            metadata: [("case_id".to_string(), "C-1".to_string())].into(),
+        };
+
+        let mut out = Vec::new();
+        write_open_disputes(&mut out, &[dispute], &OutputFormat::default()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client, tx, amount, age_secs, metadata\n1, 7, 2.5, 90, case_id=C-1\n"
+        );
+    }
+
+    #[test]
+    fn receipt_writer_writes_a_row_per_receipt() {
+        use crate::engine::{TxOutcome, TxResult};
+
+        let mut out = Vec::new();
+        let mut writer = ReceiptWriter::new(&mut out, OutputFormat::default()).unwrap();
+        writer
+            .write(
+                "transactions.csv",
+                &Receipt {
+                    result: TxResult {
+                        row: 1,
+                        tx_id: Some(5),
+                        outcome: TxOutcome::Applied,
+                    },
+                    available: Some(dec!(10)),
+                    held: Some(dec!(0)),
+                },
+            )
+            .unwrap();
+        writer
+            .write(
+                "transactions.csv",
+                &Receipt {
+                    result: TxResult {
+                        row: 2,
+                        tx_id: None,
+                        outcome: TxOutcome::Rejected {
+                            code: crate::rejection::RejectCode::MalformedRecord,
+                            message: "bad record".to_string(),
+                        },
+                    },
+                    available: None,
+                    held: None,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "file, row, tx, outcome, available, held\n\
+             transactions.csv, 1, 5, APPLIED, 10, 0\n\
+             transactions.csv, 2, , MALFORMED_RECORD, , \n"
+        );
+    }
+
+    #[test]
+    fn write_balance_history_lists_samples_by_client() {
+        let sample = |seq, available| BalanceSample {
+            seq,
+            available,
+            held: dec!(0),
+        };
+        let history = BTreeMap::from([
+            (2, vec![sample(4, dec!(1))]),
+            (1, vec![sample(1, dec!(10)), sample(3, dec!(5))]),
+        ]);
+
+        let mut out = Vec::new();
+        write_balance_history(&mut out, &history, &OutputFormat::default()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client, seq, available, held\n1, 1, 10, 0\n1, 3, 5, 0\n2, 4, 1, 0\n"
+        );
+    }
+
+    #[test]
+    fn write_rewards_lists_payouts() {
+        let payouts = [Transaction::new(TxType::Deposit, 3, 0, dec!(1.5))];
+
+        let mut out = Vec::new();
+        write_rewards(&mut out, &payouts, &OutputFormat::default()).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "client, reward\n3, 1.5\n");
+    }
+
+    #[test]
+    fn write_quarantine_quotes_errors() {
+        let rows = [QuarantinedRow {
+            file: "transactions.csv".to_string(),
+            row: 4,
+            error: "unknown variant `refund`, expected `deposit`".to_string(),
+        }];
+
+        let mut out = Vec::new();
+        write_quarantine(&mut out, &rows, &OutputFormat::default()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "file, row, error\n\
+             transactions.csv, 4, \"unknown variant `refund`, expected `deposit`\"\n"
+        );
+    }
+
+    #[test]
+    fn write_sharded_accounts_splits_accounts_by_shard() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts: Vec<_> = (1..=20).map(Account::new).collect();
+        let refs: Vec<_> = accounts.iter().collect();
+
+        write_sharded_accounts(
+            dir.path(),
+            3,
+            &refs,
+            &BTreeMap::new(),
+            &OutputFormat::default(),
+        )
+        .unwrap();
+
+        let manifest = std::fs::read_to_string(dir.path().join("manifest.csv")).unwrap();
+        assert_eq!(manifest.lines().count(), 4);
+
+        let mut client_ids = Vec::new();
+        for shard in 0..3 {
+            let file = File::open(dir.path().join(format!("accounts-{:04}.csv", shard))).unwrap();
+            for record in read_accounts(file, &OutputFormat::default()).unwrap() {
+                assert_eq!(shard_of(record.client_id, 3), shard);
+                client_ids.push(record.client_id);
+            }
+        }
+        client_ids.sort();
+        assert_eq!(client_ids, (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn read_accounts_parses_written_accounts() {
+        let mut acc = Account::new(7);
+        acc.available_amount = dec!(5.5);
+
+        let mut out = Vec::new();
+        write_accounts(
+            &mut out,
+            &[&acc],
+            &BTreeMap::new(),
+            &OutputFormat::default(),
+        )
+        .unwrap();
+
+        let records = read_accounts(out.as_slice(), &OutputFormat::default()).unwrap();
+
+        assert_eq!(records, vec![AccountRecord::from_account(&acc)]);
+    }
+
+    #[test]
+    fn written_accounts_round_trip_in_every_format() {
+        let mut accounts: Vec<_> = (1..=3).map(Account::new).collect();
+        accounts[0].available_amount = dec!(1.2345);
+        accounts[1].available_amount = dec!(-3);
+        accounts[1].held_amount = dec!(10.5);
+        accounts[1].is_locked = true;
+        let refs: Vec<_> = accounts.iter().collect();
+
+        for format in [
+            OutputFormat::default(),
+            OutputFormat {
+                pad_zeros: true,
+                version: OutputVersion::V1,
+                separator: ",".to_string(),
+                ..Default::default()
+            },
+            OutputFormat {
+                separator: " | ".to_string(),
+                ..Default::default()
+            },
+            OutputFormat {
+                separator: "\t".to_string(),
+                ..Default::default()
+            },
+        ] {
+            let mut out = Vec::new();
+            write_accounts(&mut out, &refs, &BTreeMap::new(), &format).unwrap();
+
+            let records = read_accounts(out.as_slice(), &format).unwrap();
+            let expected: Vec<_> = accounts.iter().map(AccountRecord::from_account).collect();
+            assert_eq!(records, expected, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn read_accounts_round_trip_to_accounts() {
+        let mut locked = Account::new(2);
+        locked.available_amount = dec!(-1.5);
+        locked.held_amount = dec!(3);
+        locked.is_locked = true;
+        let accounts = vec![Account::new(1), locked];
+
+        let mut out = Vec::new();
+        write_accounts(
+            &mut out,
+            &accounts.iter().collect::<Vec<_>>(),
+            &BTreeMap::new(),
+            &OutputFormat::default(),
+        )
+        .unwrap();
+        let read: Vec<_> = read_accounts(out.as_slice(), &OutputFormat::default())
+            .unwrap()
+            .iter()
+            .map(AccountRecord::to_account)
+            .collect();
+
+        assert_eq!(read, accounts);
+    }
+
+    #[test]
+    fn integrity_footer_detects_truncation_and_corruption() {
+        let accounts: Vec<_> = (1..=3).map(Account::new).collect();
+        let refs: Vec<_> = accounts.iter().collect();
+        let format = OutputFormat {
+            integrity_footer: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        write_accounts(&mut out, &refs, &BTreeMap::new(), &format).unwrap();
+        assert_eq!(verify_footer(&out).unwrap(), 3);
+        assert_eq!(read_accounts(out.as_slice(), &format).unwrap().len(), 3);
+
+        let truncated = &out[..out.len() - 50];
+        assert!(verify_footer(truncated).is_err());
+
+        let mut corrupted = out.clone();
+        corrupted[out.len() - 60] = b'9';
+        assert!(read_accounts(corrupted.as_slice(), &format).is_err());
+    }
+
+    #[test]
+    fn client_ids_are_never_written_twice() {
+        let acc = Account::new(1);
+
+        let err = write_accounts(
+            &mut Vec::new(),
+            &[&acc, &acc],
+            &BTreeMap::new(),
+            &OutputFormat::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Client id 1 would be written twice");
+
+        let err = read_accounts(
+            "client,available,held,total,locked\n1,0,0,0,false\n1,0,0,0,false\n".as_bytes(),
+            &OutputFormat::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Client id 1 is listed twice");
+    }
+
+    #[test]
+    fn write_row_quotes_fields_that_would_break_the_line() {
+        let mut out = Vec::new();
+        write_row(&mut out, &["a b", "c,d", "say \"hi\"", "x\ny"], ", ").unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a b, \"c,d\", \"say \"\"hi\"\"\", \"x\ny\"\n"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{
+    amount::Amount,
+    types::{ClientId, StoredTx, TxId, TxState, TxType},
+};
+
+/// Field of a stored transaction tested by a [`Condition`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Type,
+    Client,
+    Tx,
+    Amount,
+    State,
+}
+
+impl Field {
+    fn value(&self, tx_id: TxId, tx: &StoredTx) -> Value {
+        match self {
+            Field::Type => Value::Type(tx.tx_type),
+            Field::Client => Value::Client(tx.client_id),
+            Field::Tx => Value::Tx(tx_id),
+            Field::Amount => Value::Amount(tx.amount),
+            Field::State => Value::State(tx.state),
+        }
+    }
+
+    fn parse_value(&self, s: &str) -> anyhow::Result<Value> {
+        Ok(match self {
+            Field::Type => Value::Type(s.parse()?),
+            Field::Client => {
+                Value::Client(s.parse().map_err(|_| anyhow!("Invalid client id {}", s))?)
+            }
+            Field::Tx => Value::Tx(s.parse().map_err(|_| anyhow!("Invalid tx id {}", s))?),
+            Field::Amount => Value::Amount(
+                s.parse::<Amount>()
+                    .map_err(|_| anyhow!("Invalid amount {}", s))?,
+            ),
+            Field::State => Value::State(s.parse()?),
+        })
+    }
+
+    fn is_ordered(&self) -> bool {
+        matches!(self, Field::Client | Field::Tx | Field::Amount)
+    }
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "type" => Ok(Field::Type),
+            "client" => Ok(Field::Client),
+            "tx" => Ok(Field::Tx),
+            "amount" => Ok(Field::Amount),
+            "state" => Ok(Field::State),
+            _ => Err(anyhow!("Unknown query field {}", s)),
+        }
+    }
+}
+
+/// Comparison of a [`Condition`]. `In` matches any of the condition's values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+}
+
+/// Value a field is compared with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Client(ClientId),
+    Tx(TxId),
+    Amount(Amount),
+    Type(TxType),
+    State(TxState),
+}
+
+impl Value {
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Client(a), Value::Client(b)) => a.partial_cmp(b),
+            (Value::Tx(a), Value::Tx(b)) => a.partial_cmp(b),
+            (Value::Amount(a), Value::Amount(b)) => a.partial_cmp(b),
+            _ => (self == other).then_some(Ordering::Equal),
+        }
+    }
+}
+
+/// A test of one field of a stored transaction, such as `amount > 1000`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    pub values: Vec<Value>,
+}
+
+impl Condition {
+    pub fn new(field: Field, op: Op, value: Value) -> Self {
+        Self {
+            field,
+            op,
+            values: vec![value],
+        }
+    }
+
+    /// Matches transactions whose field equals any of `values`.
+    pub fn any_of(field: Field, values: Vec<Value>) -> Self {
+        Self {
+            field,
+            op: Op::In,
+            values,
+        }
+    }
+
+    /// Ordering comparisons never match types and states, which have no order.
+    pub fn matches(&self, tx_id: TxId, tx: &StoredTx) -> bool {
+        let actual = self.field.value(tx_id, tx);
+        let ordering = |value: &Value| actual.compare(value);
+        match (self.op, self.values.first()) {
+            (Op::In, _) => self
+                .values
+                .iter()
+                .any(|value| ordering(value) == Some(Ordering::Equal)),
+            (_, None) => false,
+            (Op::Eq, Some(value)) => ordering(value) == Some(Ordering::Equal),
+            (Op::Ne, Some(value)) => ordering(value) != Some(Ordering::Equal),
+            (Op::Lt, Some(value)) => ordering(value) == Some(Ordering::Less),
+            (Op::Le, Some(value)) => {
+                matches!(ordering(value), Some(Ordering::Less | Ordering::Equal))
+            }
+            (Op::Gt, Some(value)) => ordering(value) == Some(Ordering::Greater),
+            (Op::Ge, Some(value)) => {
+                matches!(ordering(value), Some(Ordering::Greater | Ordering::Equal))
+            }
+        }
+    }
+}
+
+/// Conditions a stored transaction must all meet to be returned by
+/// [`crate::engine::Engine::query`]. An empty query matches every stored transaction.
+///
+/// Queries are built with [`TxQuery::and`] or parsed from conditions joined by `AND`, such as
+/// `type = withdrawal AND amount > 1000 AND client IN (1, 2)`. Conditions compare a field (`type`,
+/// `client`, `tx`, `amount` or `state`) with `=`, `!=`, `<`, `<=`, `>` or `>=`, or with a list of
+/// values with `IN`. Types and states can only be compared for equality.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxQuery {
+    pub conditions: Vec<Condition>,
+}
+
+impl TxQuery {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn and(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn matches(&self, tx_id: TxId, tx: &StoredTx) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(tx_id, tx))
+    }
+}
+
+impl FromStr for TxQuery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut tokens = tokens.iter().map(String::as_str).peekable();
+        let mut query = TxQuery::new();
+
+        while tokens.peek().is_some() {
+            if !query.conditions.is_empty() {
+                match tokens.next() {
+                    Some(and) if and.eq_ignore_ascii_case("and") => {}
+                    token => return Err(unexpected(token, "AND")),
+                }
+            }
+            query = query.and(condition(&mut tokens)?);
+        }
+
+        Ok(query)
+    }
+}
+
+fn condition<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> anyhow::Result<Condition> {
+    let field: Field = tokens
+        .next()
+        .ok_or_else(|| anyhow!("Expected a field at the end of the query"))?
+        .parse()?;
+    let op = match tokens.next() {
+        Some("=") => Op::Eq,
+        Some("!=") => Op::Ne,
+        Some("<") => Op::Lt,
+        Some("<=") => Op::Le,
+        Some(">") => Op::Gt,
+        Some(">=") => Op::Ge,
+        Some(op) if op.eq_ignore_ascii_case("in") => Op::In,
+        token => return Err(unexpected(token, "a comparison")),
+    };
+    if !matches!(op, Op::Eq | Op::Ne | Op::In) && !field.is_ordered() {
+        return Err(anyhow!("{:?} can only be compared with = or !=", field));
+    }
+
+    if op != Op::In {
+        let value = tokens
+            .next()
+            .ok_or_else(|| anyhow!("Expected a value at the end of the query"))?;
+        return Ok(Condition::new(field, op, field.parse_value(value)?));
+    }
+
+    match tokens.next() {
+        Some("(") => {}
+        token => return Err(unexpected(token, "(")),
+    }
+    let mut values = Vec::new();
+    loop {
+        match tokens.next() {
+            Some(")") if values.is_empty() => break,
+            Some(value) if value != ")" && value != "," => values.push(field.parse_value(value)?),
+            token => return Err(unexpected(token, "a value")),
+        }
+        match tokens.next() {
+            Some(",") => {}
+            Some(")") => break,
+            token => return Err(unexpected(token, ", or )")),
+        }
+    }
+    Ok(Condition::any_of(field, values))
+}
+
+/// Splits a query into words, comparison operators and the `(`, `,` and `)` of lists.
+fn tokenize(s: &str) -> anyhow::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' | ')' | ',' | '=' => tokens.push(c.to_string()),
+            '<' | '>' | '!' => {
+                let mut op = c.to_string();
+                if chars.next_if_eq(&'=').is_some() {
+                    op.push('=');
+                } else if c == '!' {
+                    return Err(anyhow!("Expected != in query"));
+                }
+                tokens.push(op);
+            }
+            c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-') => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+                {
+                    word.push(c);
+                }
+                tokens.push(word);
+            }
+            _ => return Err(anyhow!("Unexpected {} in query", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn unexpected(token: Option<&str>, expected: &str) -> anyhow::Error {
+    match token {
+        Some(token) => anyhow!("Expected {} in query but found {}", expected, token),
+        None => anyhow!("Expected {} at the end of the query", expected),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    fn matching(query: &str, txs: &[(TxId, StoredTx)]) -> Vec<TxId> {
+        let query: TxQuery = query.parse().unwrap();
+        txs.iter()
+            .filter(|(tx_id, tx)| query.matches(*tx_id, tx))
+            .map(|(tx_id, _)| *tx_id)
+            .collect()
+    }
+
+    #[test]
+    fn filters_on_every_condition() {
+        let txs = [
+            (1, StoredTx::new(TxType::Withdrawal, 1, dec!(1500))),
+            (2, StoredTx::new(TxType::Withdrawal, 2, dec!(999.99))),
+            (3, StoredTx::new(TxType::Deposit, 1, dec!(2000))),
+            (4, StoredTx::new(TxType::Withdrawal, 3, dec!(5000))),
+            (
+                5,
+                StoredTx::new(TxType::Deposit, 2, dec!(10)).with_state(TxState::Disputed),
+            ),
+        ];
+
+        assert_eq!(
+            matching("type=withdrawal AND amount>1000 AND client IN (1, 2)", &txs),
+            vec![1]
+        );
+        assert_eq!(matching("state = disputed", &txs), vec![5]);
+        assert_eq!(matching("tx >= 2 and type != withdrawal", &txs), vec![3, 5]);
+        assert_eq!(matching("", &txs), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn builds_queries() {
+        let query = TxQuery::new()
+            .and(Condition::new(
+                Field::Amount,
+                Op::Le,
+                Value::Amount(dec!(10)),
+            ))
+            .and(Condition::any_of(
+                Field::Type,
+                vec![Value::Type(TxType::Deposit)],
+            ));
+
+        assert_eq!(query, "amount <= 10 AND type IN (deposit)".parse().unwrap());
+        assert!(query.matches(1, &StoredTx::new(TxType::Deposit, 1, dec!(10))));
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        for query in [
+            "amount",
+            "amount >",
+            "color = red",
+            "type > deposit",
+            "type = gift",
+            "amount > ten",
+            "client IN (1, 2",
+            "tx = 1 OR tx = 2",
+            "tx ! 1",
+        ] {
+            assert!(query.parse::<TxQuery>().is_err(), "{}", query);
+        }
+    }
+}
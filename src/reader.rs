@@ -1,8 +1,104 @@
-use std::io;
+use std::{io, str::FromStr};
 
-use crate::types::Transaction;
+#[cfg(feature = "anonymize")]
+use crate::anonymize::Anonymizer;
+use crate::{
+    amount::{Amount, AmountArithmetic},
+    mapping::AccountMap,
+    rejection::{reject, RejectCode},
+    types::{hash_tx_id, Transaction, TxType},
+};
 use anyhow::anyhow;
-use csv::{Reader, StringRecordsIter};
+use csv::{Reader, ReaderBuilder, StringRecord, StringRecordsIter, Trim};
+use rust_decimal::Decimal;
+
+/// Creates a CSV reader configured for transaction files.
+pub fn new_csv_reader<R: io::Read>(rdr: R) -> Reader<R> {
+    ReaderBuilder::new().trim(Trim::All).from_reader(rdr)
+}
+
+/// The decimal and thousands separators used by amounts in transaction files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AmountLocale {
+    /// `1,234.56`
+    #[default]
+    Point,
+    /// `1.234,56`, as used by many European exports.
+    Comma,
+}
+
+impl AmountLocale {
+    /// Returns the decimal and thousands separators.
+    fn separators(&self) -> (char, char) {
+        match self {
+            AmountLocale::Point => ('.', ','),
+            AmountLocale::Comma => (',', '.'),
+        }
+    }
+
+    /// Rewrites an amount with thousands separators or in scientific notation to a plain decimal,
+    /// or returns `None` if it is already plain.
+    ///
+    /// Thousands separators are only accepted between groups of three digits, so `1,5` is never
+    /// silently read as `15` and, in the comma locale, `1.5` is not read as `1.5`. Such amounts are
+    /// rejected with [`RejectCode::BadAmount`].
+    fn normalize(&self, amount: &str) -> anyhow::Result<Option<String>> {
+        let (decimal_separator, thousands_separator) = self.separators();
+        if *self == AmountLocale::Point && !amount.contains([thousands_separator, 'e', 'E']) {
+            return Ok(None);
+        }
+        let ambiguous = || {
+            reject(
+                RejectCode::BadAmount,
+                format!("Ambiguous amount {}", amount),
+            )
+        };
+
+        let (integer, fraction) = match amount.split_once(decimal_separator) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (amount, None),
+        };
+
+        let mut groups = integer.split(thousands_separator);
+        let first = groups.next().unwrap_or_default();
+        let mut normalized = first.to_string();
+        for group in groups {
+            let leading_digits = first.trim_start_matches(['-', '+']).len();
+            if !(1..=3).contains(&leading_digits)
+                || group.len() != 3
+                || !group.bytes().all(|b| b.is_ascii_digit())
+            {
+                return Err(ambiguous());
+            }
+            normalized.push_str(group);
+        }
+
+        if let Some(fraction) = fraction {
+            normalized.push('.');
+            normalized.push_str(fraction);
+        }
+
+        if normalized.contains(['e', 'E']) {
+            normalized = Decimal::from_scientific(&normalized)
+                .map_err(|_| ambiguous())?
+                .to_string();
+        }
+
+        Ok((normalized != amount).then_some(normalized))
+    }
+}
+
+impl FromStr for AmountLocale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "point" => Ok(AmountLocale::Point),
+            "comma" => Ok(AmountLocale::Comma),
+            _ => Err(anyhow!("Unknown amount locale {}", s)),
+        }
+    }
+}
 
 pub struct CsvTxIter<'a, R: io::Read> {
     reader: CsvTxReader<'a, R>,
@@ -16,27 +112,174 @@ impl<'a, R: io::Read> Iterator for CsvTxIter<'a, R> {
     }
 }
 
+/// Reads transactions from CSV records, matching columns by header name.
+///
+/// Rows without a `trace_id` column value are given a trace id derived from their line number so
+/// every transaction can be correlated across outputs.
 pub struct CsvTxReader<'a, R: io::Read> {
+    headers: Option<StringRecord>,
     iter: StringRecordsIter<'a, R>,
+    hashed_tx_column: Option<usize>,
+    account_map: Option<(usize, &'a mut AccountMap)>,
+    #[cfg(feature = "anonymize")]
+    anonymizer: Option<&'a Anonymizer>,
+    amount_column: Option<usize>,
+    amount_locale: AmountLocale,
+    signed_amounts: bool,
 }
 
 impl<'a, R: io::Read> CsvTxReader<'a, R> {
     pub fn new(reader: &'a mut Reader<R>) -> Self {
-        Self {
+        let mut tx_reader = Self {
+            headers: reader.headers().ok().cloned(),
             iter: reader.records(),
-        }
+            hashed_tx_column: None,
+            account_map: None,
+            #[cfg(feature = "anonymize")]
+            anonymizer: None,
+            amount_column: None,
+            amount_locale: AmountLocale::default(),
+            signed_amounts: false,
+        };
+        tx_reader.amount_column = tx_reader.column("amount");
+        tx_reader
+    }
+
+    /// Sets the separators used by amounts. Amounts with thousands separators or in scientific
+    /// notation are accepted in any locale.
+    pub fn with_amount_locale(mut self, amount_locale: AmountLocale) -> Self {
+        self.amount_locale = amount_locale;
+        self
+    }
+
+    /// Reads deposits with a negative amount as withdrawals of the absolute amount, for exports
+    /// that encode withdrawals as negative deposits.
+    pub fn with_signed_amounts(mut self) -> Self {
+        self.signed_amounts = true;
+        self
+    }
+
+    /// Treats every `tx` value as an opaque string id, such as a UUID, and hashes it to a numeric
+    /// tx id with [`hash_tx_id`].
+    pub fn with_hashed_tx_ids(mut self) -> Self {
+        self.hashed_tx_column = self.column("tx");
+        self
+    }
+
+    /// Treats every `client` value as an external account reference and translates it to a client
+    /// id with `account_map`.
+    pub fn with_account_map(mut self, account_map: &'a mut AccountMap) -> Self {
+        self.account_map = self.column("client").map(|column| (column, account_map));
+        self
+    }
+
+    /// Replaces every client id with its pseudonym, after any account reference translation.
+    #[cfg(feature = "anonymize")]
+    pub fn with_anonymizer(mut self, anonymizer: &'a Anonymizer) -> Self {
+        self.anonymizer = Some(anonymizer);
+        self
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.headers
+            .as_ref()
+            .and_then(|headers| headers.iter().position(|header| header == name))
     }
 
     fn next(&mut self) -> Option<anyhow::Result<Transaction>> {
         match self.iter.next() {
-            Some(Ok(record)) => match record.deserialize::<Transaction>(None) {
-                Ok(tx) => Some(Ok(tx)),
-                Err(err) => Some(Err(anyhow!(err))),
-            },
-            Some(Err(err)) => Some(Err(anyhow!(err))),
+            Some(Ok(record)) => Some(self.parse(record)),
+            Some(Err(err)) => Some(Err(reject(RejectCode::MalformedRecord, err.to_string()))),
             None => None,
         }
     }
+
+    fn parse(&mut self, mut record: StringRecord) -> anyhow::Result<Transaction> {
+        if let Some(column) = self.hashed_tx_column {
+            let tx_id = hash_tx_id(record.get(column).unwrap_or_default());
+            record = replace_field(&record, column, &tx_id.to_string());
+        }
+
+        if let Some(column) = self.amount_column {
+            if let Some(amount) = self
+                .amount_locale
+                .normalize(record.get(column).unwrap_or_default())?
+            {
+                record = replace_field(&record, column, &amount);
+            }
+        }
+
+        if let Some((column, account_map)) = self.account_map.as_mut() {
+            let client_id = account_map.resolve(record.get(*column).unwrap_or_default())?;
+            record = replace_field(&record, *column, &client_id.to_string());
+        }
+
+        match record.deserialize::<Transaction>(self.headers.as_ref()) {
+            Ok(mut tx) => {
+                tx.amount = tx.amount.canonical();
+                #[cfg(feature = "anonymize")]
+                if let Some(anonymizer) = self.anonymizer {
+                    tx.client_id = anonymizer.pseudonym(tx.client_id);
+                }
+                if self.signed_amounts
+                    && tx.tx_type == TxType::Deposit
+                    && !tx.amount.is_sign_positive()
+                {
+                    tx.tx_type = TxType::Withdrawal;
+                    tx.amount = -tx.amount;
+                }
+                if tx.trace_id.is_none() {
+                    let line = record.position().map_or(0, |pos| pos.line());
+                    tx.trace_id = Some(format!("line-{}", line));
+                }
+                Ok(tx)
+            }
+            Err(err) => Err(reject(
+                classify(&record, self.headers.as_ref()),
+                err.to_string(),
+            )),
+        }
+    }
+}
+
+fn replace_field(record: &StringRecord, column: usize, replacement: &str) -> StringRecord {
+    let mut replaced: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match index == column {
+            true => replacement,
+            false => value,
+        })
+        .collect();
+    replaced.set_position(record.position().cloned());
+    replaced
+}
+
+/// Picks the rejection code for a record that could not be deserialized, based on which column
+/// holds an invalid value.
+fn classify(record: &StringRecord, headers: Option<&StringRecord>) -> RejectCode {
+    let field = |name| {
+        headers
+            .and_then(|headers| headers.iter().position(|header| header == name))
+            .and_then(|index| record.get(index))
+    };
+
+    if let Some(tx_type) = field("type") {
+        if StringRecord::from(vec![tx_type])
+            .deserialize::<TxType>(None)
+            .is_err()
+        {
+            return RejectCode::UnknownType;
+        }
+    }
+
+    if let Some(amount) = field("amount") {
+        if amount.parse::<Amount>().is_err() {
+            return RejectCode::BadAmount;
+        }
+    }
+
+    RejectCode::MalformedRecord
 }
 
 impl<'a, R: io::Read> IntoIterator for CsvTxReader<'a, R> {
@@ -53,18 +296,16 @@ impl<'a, R: io::Read> IntoIterator for CsvTxReader<'a, R> {
 mod tests {
     use std::io::BufReader;
 
-    use crate::types::TxType;
-
     use super::*;
-    use csv::{ReaderBuilder, Trim};
-    use rust_decimal_macros::dec;
+    use crate::amount::dec;
 
     #[test]
+    #[allow(clippy::get_first)]
     fn iterates_rows() {
         let src =
             "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\nfoo, foo\nfoo, foo, foo, foo";
         let buf = BufReader::new(src.as_bytes());
-        let mut csv_reader = ReaderBuilder::new().trim(Trim::All).from_reader(buf);
+        let mut csv_reader = new_csv_reader(buf);
         let tx_reader = CsvTxReader::new(&mut csv_reader);
 
         let txs: Vec<_> = tx_reader.into_iter().collect();
@@ -89,4 +330,190 @@ mod tests {
         let tx4 = txs.get(3).unwrap();
         assert!(tx4.is_err());
     }
+
+    #[cfg(feature = "client-id-u64")]
+    #[test]
+    fn reads_wide_client_ids() {
+        let src = "type, client, tx, amount\ndeposit, 10000000000, 1, 1.0";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let tx = CsvTxReader::new(&mut csv_reader)
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(tx.client_id, 10_000_000_000);
+    }
+
+    #[test]
+    fn hashes_string_tx_ids() {
+        let src = "type, client, tx, amount\ndeposit, 1, 6f1c2a7e-3b1d-4c5e-9f0a-1b2c3d4e5f60, 1.0\ndispute, 1, 6f1c2a7e-3b1d-4c5e-9f0a-1b2c3d4e5f60, 0.0";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let txs: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .with_hashed_tx_ids()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        let expected = hash_tx_id("6f1c2a7e-3b1d-4c5e-9f0a-1b2c3d4e5f60");
+        assert_eq!(txs[0].tx_id, expected);
+        assert_eq!(txs[1].tx_id, expected);
+        assert_eq!(txs[1].trace_id(), "line-3");
+    }
+
+    #[test]
+    fn translates_account_references() {
+        let src = "type, client, tx, amount\ndeposit, GB29NWBK60161331926819, 1, 1.0\ndeposit, DE89370400440532013000, 2, 1.0\ndeposit, GB29NWBK60161331926819, 3, 1.0";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let mut account_map = AccountMap::new();
+
+        let client_ids: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .with_account_map(&mut account_map)
+            .into_iter()
+            .map(|result| result.unwrap().client_id)
+            .collect();
+
+        assert_eq!(client_ids, vec![0, 1, 0]);
+        assert_eq!(account_map.len(), 2);
+    }
+
+    #[cfg(feature = "anonymize")]
+    #[test]
+    fn replaces_client_ids_with_pseudonyms() {
+        let src =
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 1.0\ndeposit, 1, 3, 1.0";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let anonymizer = Anonymizer::new(b"secret").unwrap();
+
+        let client_ids: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .with_anonymizer(&anonymizer)
+            .into_iter()
+            .map(|result| result.unwrap().client_id)
+            .collect();
+
+        let (one, two) = (anonymizer.pseudonym(1), anonymizer.pseudonym(2));
+        assert_eq!(client_ids, vec![one, two, one]);
+    }
+
+    #[test]
+    fn normalizes_locale_formatted_amounts() {
+        let amounts = [
+            (AmountLocale::Point, "1.5", None),
+            (AmountLocale::Point, "1,234.56", Some("1234.56")),
+            (AmountLocale::Point, "-12,345,678", Some("-12345678")),
+            (AmountLocale::Point, "1.5e3", Some("1500")),
+            (AmountLocale::Point, "2E-2", Some("0.02")),
+            (AmountLocale::Comma, "1.234,56", Some("1234.56")),
+            (AmountLocale::Comma, "0,5", Some("0.5")),
+            (AmountLocale::Comma, "1,5e2", Some("150")),
+            (AmountLocale::Comma, "15", None),
+        ];
+
+        for (locale, amount, expected) in amounts {
+            assert_eq!(
+                locale.normalize(amount).unwrap().as_deref(),
+                expected,
+                "{:?} {}",
+                locale,
+                amount
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_ambiguous_amounts() {
+        let amounts = [
+            (AmountLocale::Point, "1,5"),
+            (AmountLocale::Point, "1234,567"),
+            (AmountLocale::Comma, "1.5"),
+            (AmountLocale::Comma, "1.5e"),
+        ];
+
+        for (locale, amount) in amounts {
+            let err = locale.normalize(amount).unwrap_err();
+            assert_eq!(RejectCode::of(&err), RejectCode::BadAmount, "{}", amount);
+            assert_eq!(err.to_string(), format!("Ambiguous amount {}", amount));
+        }
+    }
+
+    #[test]
+    fn reads_comma_locale_amounts() {
+        let src = "type, client, tx, amount\ndeposit, 1, 1,\"1.234,56\"\ndeposit, 1, 2,\"1,5\"";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let amounts: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .with_amount_locale(AmountLocale::Comma)
+            .into_iter()
+            .map(|result| result.unwrap().amount)
+            .collect();
+
+        assert_eq!(amounts, vec![dec!(1234.56), dec!(1.5)]);
+    }
+
+    #[test]
+    fn corrupt_records_have_reject_codes() {
+        let src = "type, client, tx, amount\nrefund, 1, 1, 1.0\ndeposit, 1, 2, abc\ndeposit, x, 3, 1.0\ndeposit, 1";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let codes: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .into_iter()
+            .map(|result| RejectCode::of(&result.unwrap_err()))
+            .collect();
+
+        assert_eq!(
+            codes,
+            vec![
+                RejectCode::UnknownType,
+                RejectCode::BadAmount,
+                RejectCode::MalformedRecord,
+                RejectCode::MalformedRecord,
+            ]
+        );
+    }
+
+    #[test]
+    fn generates_trace_id_from_line_number() {
+        let src = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let txs: Vec<_> = CsvTxReader::new(&mut csv_reader).into_iter().collect();
+
+        assert_eq!(txs[0].as_ref().unwrap().trace_id(), "line-2");
+        assert_eq!(txs[1].as_ref().unwrap().trace_id(), "line-3");
+    }
+
+    #[test]
+    fn reads_trace_id_column() {
+        let src = "type, client, tx, amount, trace_id\ndeposit, 1, 1, 1.0, abc-123";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let txs: Vec<_> = CsvTxReader::new(&mut csv_reader).into_iter().collect();
+
+        assert_eq!(txs[0].as_ref().unwrap().trace_id(), "abc-123");
+    }
+
+    #[test]
+    fn reads_negative_deposits_as_withdrawals() {
+        let src = "type, client, tx, amount\ndeposit, 1, 1, 5.0\ndeposit, 1, 2, -2.5";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let txs: Vec<_> = CsvTxReader::new(&mut csv_reader)
+            .with_signed_amounts()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(txs[0].tx_type, TxType::Deposit);
+        assert_eq!(txs[1].tx_type, TxType::Withdrawal);
+        assert_eq!(txs[1].amount, dec!(2.5));
+    }
+
+    #[test]
+    fn reads_metadata_column() {
+        let src =
+            "type, client, tx, amount, metadata\ndeposit, 1, 1, 1.0,\ndispute, 1, 1, 0, case_id=C-1";
+        let mut csv_reader = new_csv_reader(src.as_bytes());
+        let txs: Vec<_> = CsvTxReader::new(&mut csv_reader).into_iter().collect();
+
+        assert_eq!(txs[0].as_ref().unwrap().metadata, None);
+        assert_eq!(
+            txs[1].as_ref().unwrap().metadata.as_deref(),
+            Some("case_id=C-1")
+        );
+    }
 }
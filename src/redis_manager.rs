@@ -0,0 +1,320 @@
+use std::fmt;
+
+use redis::{Client, Connection, ErrorKind, RedisError, Script};
+
+use crate::{
+    account::{AccountError, Manager, SimpleManager},
+    amount::Amount,
+    types::{Account, ClientId},
+};
+
+/// Number of times a balance update is retried when another instance changed the account first.
+const MAX_ATTEMPTS: usize = 16;
+
+const FIELDS: [&str; 3] = ["available", "held", "locked"];
+
+/// Creates the account hash if it does not exist and records the client id.
+const ENSURE_ACCOUNT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    redis.call('HSET', KEYS[1], 'available', '0', 'held', '0', 'locked', '0')
+end
+redis.call('SADD', KEYS[2], ARGV[1])
+return 1
+";
+
+/// Replaces the account fields only if they still hold the values the update was based on.
+const COMPARE_AND_SET: &str = r"
+local current = redis.call('HMGET', KEYS[1], 'available', 'held', 'locked')
+for i = 1, 3 do
+    if current[i] ~= ARGV[i] then
+        return 0
+    end
+end
+redis.call('HSET', KEYS[1], 'available', ARGV[4], 'held', ARGV[5], 'locked', ARGV[6])
+return 1
+";
+
+/// Error returned by [`RedisManager`]. Rejections are kept as the source so their reject code can
+/// still be found.
+#[derive(Debug)]
+pub enum RedisManagerError {
+    Rejected(AccountError),
+    Redis(RedisError),
+    /// The account kept being changed by other instances while an update was attempted.
+    Contention {
+        client_id: ClientId,
+    },
+}
+
+impl fmt::Display for RedisManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedisManagerError::Rejected(err) => err.fmt(f),
+            RedisManagerError::Redis(err) => err.fmt(f),
+            RedisManagerError::Contention { client_id } => write!(
+                f,
+                "Account for client {} was changed concurrently {} times",
+                client_id, MAX_ATTEMPTS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RedisManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RedisManagerError::Rejected(err) => Some(err),
+            RedisManagerError::Redis(err) => Some(err),
+            RedisManagerError::Contention { .. } => None,
+        }
+    }
+}
+
+impl From<RedisError> for RedisManagerError {
+    fn from(err: RedisError) -> Self {
+        RedisManagerError::Redis(err)
+    }
+}
+
+/// A [`Manager`] keeping accounts in Redis so several engine instances can share account state.
+///
+/// Each account is a hash of its available and held amounts and locked flag, stored without a
+/// TTL so accounts persist for as long as the Redis server does. Updates apply the
+/// same balance rules as [`SimpleManager`] to the current account and write the result back with
+/// a compare-and-set Lua script, retrying if another instance changed the account in between, so
+/// amounts are never rounded through Lua's floating point numbers.
+///
+/// [`Manager::all`] returns the accounts this instance has touched, as of its last access; call
+/// [`RedisManager::refresh`] first to include every account in Redis.
+pub struct RedisManager {
+    connection: Connection,
+    prefix: String,
+    accounts: SimpleManager,
+    ensure_account: Script,
+    compare_and_set: Script,
+}
+
+impl RedisManager {
+    /// Connects to the Redis server at `url`, storing keys under `prefix`.
+    pub fn connect(url: &str, prefix: impl Into<String>) -> Result<Self, RedisError> {
+        Ok(Self {
+            connection: Client::open(url)?.get_connection()?,
+            prefix: prefix.into(),
+            accounts: SimpleManager::new(),
+            ensure_account: Script::new(ENSURE_ACCOUNT),
+            compare_and_set: Script::new(COMPARE_AND_SET),
+        })
+    }
+
+    /// Loads every account stored in Redis.
+    pub fn refresh(&mut self) -> Result<(), RedisManagerError> {
+        let client_ids: Vec<ClientId> = redis::cmd("SMEMBERS")
+            .arg(self.clients_key())
+            .query(&mut self.connection)?;
+
+        for client_id in client_ids {
+            self.load(client_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn account_key(&self, client_id: ClientId) -> String {
+        format!("{}:account:{}", self.prefix, client_id)
+    }
+
+    fn clients_key(&self) -> String {
+        format!("{}:clients", self.prefix)
+    }
+
+    /// Reads the account from Redis into the local copy, returning the raw field values it was
+    /// read from, or `None` if it does not exist.
+    fn load(&mut self, client_id: ClientId) -> Result<Option<[String; 3]>, RedisManagerError> {
+        let fields: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(self.account_key(client_id))
+            .arg(&FIELDS)
+            .query(&mut self.connection)?;
+
+        let fields = match fields.as_slice() {
+            [Some(available), Some(held), Some(locked)] => {
+                [available.clone(), held.clone(), locked.clone()]
+            }
+            [None, None, None] => return Ok(None),
+            _ => return Err(corrupt(client_id)),
+        };
+
+        let parse = |value: &str| value.parse::<Amount>().map_err(|_| corrupt(client_id));
+        self.accounts.insert(Account {
+            client_id,
+            available_amount: parse(&fields[0])?,
+            held_amount: parse(&fields[1])?,
+            is_locked: fields[2] == "1",
+        });
+
+        Ok(Some(fields))
+    }
+
+    /// Applies `op` to the current account and writes the result back if the account was not
+    /// changed in the meantime.
+    fn update(
+        &mut self,
+        client_id: ClientId,
+        op: impl Fn(&mut SimpleManager) -> Result<(), AccountError>,
+    ) -> Result<(), RedisManagerError> {
+        for _ in 0..MAX_ATTEMPTS {
+            let expected = self.load(client_id)?.ok_or(RedisManagerError::Rejected(
+                AccountError::AccountNotFound(client_id),
+            ))?;
+
+            op(&mut self.accounts).map_err(RedisManagerError::Rejected)?;
+
+            let acc = self
+                .accounts
+                .get(client_id)
+                .ok_or(RedisManagerError::Rejected(AccountError::AccountNotFound(
+                    client_id,
+                )))?;
+            let updated: i32 = self
+                .compare_and_set
+                .key(self.account_key(client_id))
+                .arg(&expected)
+                .arg(acc.available_amount.to_string())
+                .arg(acc.held_amount.to_string())
+                .arg(if acc.is_locked { "1" } else { "0" })
+                .invoke(&mut self.connection)?;
+
+            if updated == 1 {
+                return Ok(());
+            }
+        }
+
+        Err(RedisManagerError::Contention { client_id })
+    }
+}
+
+impl Manager for RedisManager {
+    type Error = RedisManagerError;
+
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        let _: i32 = self
+            .ensure_account
+            .key(self.account_key(client_id))
+            .key(self.clients_key())
+            .arg(client_id)
+            .invoke(&mut self.connection)?;
+
+        self.load(client_id)?;
+        Ok(())
+    }
+
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.deposit(client_id, amount))
+    }
+
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.withdraw(client_id, amount))
+    }
+
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| {
+            accounts.withdraw_held(client_id, amount)
+        })
+    }
+
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.hold(client_id, amount))
+    }
+
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.release(client_id, amount))
+    }
+
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.lock(client_id))
+    }
+
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.update(client_id, |accounts| accounts.unlock(client_id))
+    }
+
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
+        match self.load(client_id)? {
+            Some(_) => self
+                .accounts
+                .is_locked(client_id)
+                .map_err(RedisManagerError::Rejected),
+            None => Err(RedisManagerError::Rejected(AccountError::AccountNotFound(
+                client_id,
+            ))),
+        }
+    }
+
+    fn all(&self) -> Vec<&Account> {
+        self.accounts.all()
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(client_id)
+    }
+
+    fn page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        self.accounts.page(after, limit)
+    }
+}
+
+fn corrupt(client_id: ClientId) -> RedisManagerError {
+    RedisManagerError::Redis(RedisError::from((
+        ErrorKind::TypeError,
+        "Stored account is not valid",
+        format!("client {}", client_id),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    /// Connects to the server in `REDIS_URL`, using a key prefix unique to the test run.
+    fn connect(test: &str) -> RedisManager {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+        RedisManager::connect(&url, format!("test:{}:{}", std::process::id(), test)).unwrap()
+    }
+
+    #[test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    fn instances_share_account_state() {
+        let mut first = connect("shared");
+        let mut second = connect("shared");
+
+        first.ensure_account(1).unwrap();
+        first.deposit(1, dec!(10.5)).unwrap();
+        second.ensure_account(1).unwrap();
+        second.hold(1, dec!(3)).unwrap();
+        first.withdraw(1, dec!(7.5)).unwrap();
+
+        let err = second.withdraw(1, dec!(0.1)).unwrap_err();
+        assert!(matches!(
+            err,
+            RedisManagerError::Rejected(AccountError::InsufficientAvailable)
+        ));
+
+        second.refresh().unwrap();
+        let acc = second.all()[0];
+        assert_eq!(acc.available_amount, dec!(0));
+        assert_eq!(acc.held_amount, dec!(3));
+    }
+
+    #[test]
+    #[ignore = "requires a Redis server at REDIS_URL"]
+    fn updates_fail_for_unknown_accounts() {
+        let mut accounts = connect("unknown");
+
+        let err = accounts.deposit(1, dec!(1)).unwrap_err();
+
+        assert!(matches!(
+            err,
+            RedisManagerError::Rejected(AccountError::AccountNotFound(1))
+        ));
+    }
+}
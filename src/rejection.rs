@@ -0,0 +1,149 @@
+use alloc::string::String;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::{account::AccountError, engine::EngineError};
+
+/// Stable, machine-readable reasons for rejecting a transaction.
+///
+/// The string form of each code is part of the engine's output contract and must not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectCode {
+    InsufficientFunds,
+    AccountLocked,
+    DuplicateTx,
+    ClientMismatch,
+    InvalidTxState,
+    BadAmount,
+    UnknownType,
+    MalformedRecord,
+    Overflow,
+    AccountNotFound,
+    ClientBlocked,
+    SuspectedDuplicate,
+    Unknown,
+}
+
+impl RejectCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectCode::InsufficientFunds => "INSUFFICIENT_FUNDS",
+            RejectCode::AccountLocked => "ACCOUNT_LOCKED",
+            RejectCode::DuplicateTx => "DUPLICATE_TX",
+            RejectCode::ClientMismatch => "CLIENT_MISMATCH",
+            RejectCode::InvalidTxState => "INVALID_TX_STATE",
+            RejectCode::BadAmount => "BAD_AMOUNT",
+            RejectCode::UnknownType => "UNKNOWN_TYPE",
+            RejectCode::MalformedRecord => "MALFORMED_RECORD",
+            RejectCode::Overflow => "OVERFLOW",
+            RejectCode::AccountNotFound => "ACCOUNT_NOT_FOUND",
+            RejectCode::ClientBlocked => "CLIENT_BLOCKED",
+            RejectCode::SuspectedDuplicate => "SUSPECTED_DUPLICATE",
+            RejectCode::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Returns the code of the first [`Rejection`], [`AccountError`] or [`EngineError`] among an
+    /// error and its causes, or [`RejectCode::Unknown`] if there is none.
+    #[cfg(feature = "std")]
+    pub fn of(err: &anyhow::Error) -> RejectCode {
+        err.chain()
+            .find_map(|cause| {
+                if let Some(rejection) = cause.downcast_ref::<Rejection>() {
+                    Some(rejection.code)
+                } else if let Some(err) = cause.downcast_ref::<AccountError>() {
+                    Some(err.code())
+                } else {
+                    cause.downcast_ref::<EngineError>().map(EngineError::code)
+                }
+            })
+            .unwrap_or(RejectCode::Unknown)
+    }
+}
+
+impl fmt::Display for RejectCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct Rejection {
+    pub code: RejectCode,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(code: RejectCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl core::error::Error for Rejection {}
+
+#[cfg(feature = "std")]
+pub fn reject(code: RejectCode, message: impl Into<String>) -> anyhow::Error {
+    Rejection::new(code, message).into()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn code_strings_are_stable() {
+        assert_eq!(
+            RejectCode::InsufficientFunds.to_string(),
+            "INSUFFICIENT_FUNDS"
+        );
+        assert_eq!(RejectCode::AccountLocked.to_string(), "ACCOUNT_LOCKED");
+        assert_eq!(RejectCode::DuplicateTx.to_string(), "DUPLICATE_TX");
+        assert_eq!(RejectCode::ClientMismatch.to_string(), "CLIENT_MISMATCH");
+        assert_eq!(RejectCode::BadAmount.to_string(), "BAD_AMOUNT");
+        assert_eq!(RejectCode::UnknownType.to_string(), "UNKNOWN_TYPE");
+        assert_eq!(RejectCode::ClientBlocked.to_string(), "CLIENT_BLOCKED");
+        assert_eq!(
+            RejectCode::SuspectedDuplicate.to_string(),
+            "SUSPECTED_DUPLICATE"
+        );
+    }
+
+    #[test]
+    fn of_returns_code_of_rejection() {
+        let err = reject(RejectCode::BadAmount, "The amount is not positive");
+
+        assert_eq!(RejectCode::of(&err), RejectCode::BadAmount);
+        assert_eq!(err.to_string(), "The amount is not positive");
+    }
+
+    #[test]
+    fn of_finds_rejection_in_error_chain() {
+        let err = anyhow::Error::new(Rejection::new(RejectCode::Overflow, "too large"))
+            .context("Deposit failed");
+
+        assert_eq!(RejectCode::of(&err), RejectCode::Overflow);
+    }
+
+    #[test]
+    fn of_returns_code_of_account_errors() {
+        let err = anyhow::Error::new(AccountError::InsufficientHeld);
+
+        assert_eq!(RejectCode::of(&err), RejectCode::InsufficientFunds);
+    }
+
+    #[test]
+    fn of_returns_unknown_for_other_errors() {
+        assert_eq!(RejectCode::of(&anyhow!("boom")), RejectCode::Unknown);
+    }
+}
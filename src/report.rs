@@ -0,0 +1,156 @@
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{
+    amount::Amount,
+    engine::EngineStats,
+    output::OutputFormat,
+    types::{Account, ClientId},
+};
+
+#[derive(Serialize)]
+struct ReportAccount {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReportSummary {
+    accounts: usize,
+    locked_accounts: usize,
+    stored_transactions: usize,
+    open_disputes: usize,
+    unknown_types: usize,
+    available: String,
+    held: String,
+    total: String,
+}
+
+/// Renders a [Tera](https://keats.github.io/tera/docs/) template over the final accounts and a
+/// summary of the run, for report layouts that the fixed outputs do not cover.
+///
+/// The template sees `accounts`, sorted by client, with `client`, `available`, `held`, `total`,
+/// `locked` and `status` fields, and `summary`, with the `accounts`, `locked_accounts`,
+/// `stored_transactions`, `open_disputes` and `unknown_types` counts and the `available`, `held`
+/// and `total` balances over all accounts. Amounts are strings formatted as in the accounts
+/// output, so they keep their exact value. As in Tera, output is HTML-escaped if `name` ends in
+/// `.html`, `.htm` or `.xml`.
+pub fn render_report(
+    name: &str,
+    template: &str,
+    accounts: &[&Account],
+    stats: &EngineStats,
+    format: &OutputFormat,
+) -> anyhow::Result<String> {
+    let mut tera = Tera::default();
+    tera.add_raw_template(name, template)?;
+
+    let mut sorted = accounts.to_vec();
+    sorted.sort_by_key(|acc| acc.client_id);
+    let accounts: Vec<_> = sorted
+        .iter()
+        .map(|acc| ReportAccount {
+            client: acc.client_id,
+            available: format.amount(acc.available_amount),
+            held: format.amount(acc.held_amount),
+            total: format.amount(acc.available_amount + acc.held_amount),
+            locked: acc.is_locked,
+            status: acc.status().as_str(),
+        })
+        .collect();
+
+    let available = sorted
+        .iter()
+        .fold(Amount::ZERO, |sum, acc| sum + acc.available_amount);
+    let held = sorted
+        .iter()
+        .fold(Amount::ZERO, |sum, acc| sum + acc.held_amount);
+    let summary = ReportSummary {
+        accounts: sorted.len(),
+        locked_accounts: sorted.iter().filter(|acc| acc.is_locked).count(),
+        stored_transactions: stats.stored_transactions,
+        open_disputes: stats.open_disputes,
+        unknown_types: stats.unknown_types,
+        available: format.amount(available),
+        held: format.amount(held),
+        total: format.amount(available + held),
+    };
+
+    let mut context = Context::new();
+    context.insert("accounts", &accounts);
+    context.insert("summary", &summary);
+    Ok(tera.render(name, &context)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::dec;
+
+    fn accounts() -> Vec<Account> {
+        vec![
+            Account {
+                client_id: 2,
+                is_locked: true,
+                available_amount: dec!(0.5),
+                held_amount: dec!(1),
+            },
+            Account {
+                client_id: 1,
+                is_locked: false,
+                available_amount: dec!(10.25),
+                held_amount: dec!(0),
+            },
+        ]
+    }
+
+    fn stats() -> EngineStats {
+        EngineStats {
+            accounts: 2,
+            stored_transactions: 0,
+            open_disputes: 0,
+            unknown_types: 0,
+            memory_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn renders_accounts_and_summary() {
+        let accounts = accounts();
+        let template =
+            "{% for acc in accounts %}{{ acc.client }}: {{ acc.total }} ({{ acc.status }})\n\
+                        {% endfor %}{{ summary.accounts }} accounts, {{ summary.locked_accounts }} \
+                        locked, {{ summary.total }} in total";
+
+        let report = render_report(
+            "report.txt",
+            template,
+            &accounts.iter().collect::<Vec<_>>(),
+            &stats(),
+            &OutputFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            report,
+            "1: 10.25 (active)\n2: 1.5 (locked)\n2 accounts, 1 locked, 11.75 in total"
+        );
+    }
+
+    #[test]
+    fn reports_template_errors() {
+        let report = render_report(
+            "report.html",
+            "<p>{{ title }}</p>",
+            &[],
+            &stats(),
+            &OutputFormat::default(),
+        );
+
+        assert!(report.is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    account::SimpleManager,
+    engine::Engine,
+    output::{self, AccountRecord, OutputFormat},
+    reader::{self, CsvTxReader},
+};
+
+pub const INPUT_FILE: &str = "input.csv";
+pub const EXPECTED_FILE: &str = "expected_accounts.csv";
+
+#[derive(Debug)]
+pub struct ScenarioFailure {
+    pub scenario: PathBuf,
+    pub differences: Vec<String>,
+}
+
+/// Runs every scenario directory under `root`, returning the scenarios whose resulting accounts
+/// differ from their expected accounts.
+///
+/// Each scenario is a directory containing an `input.csv` transaction file and an
+/// `expected_accounts.csv` file in the engine's output format.
+pub fn run_scenarios(root: impl AsRef<Path>) -> anyhow::Result<Vec<ScenarioFailure>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        if path.join(INPUT_FILE).is_file() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+
+    let mut failures = Vec::new();
+    for dir in dirs {
+        let differences = run_scenario(&dir)?;
+        if !differences.is_empty() {
+            failures.push(ScenarioFailure {
+                scenario: dir,
+                differences,
+            });
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Runs a single scenario directory and returns a description of each difference found.
+pub fn run_scenario(dir: impl AsRef<Path>) -> anyhow::Result<Vec<String>> {
+    let dir = dir.as_ref();
+
+    let mut csv_reader = reader::new_csv_reader(fs::File::open(dir.join(INPUT_FILE))?);
+    let mut engine = Engine::new(SimpleManager::new());
+    engine.process_all(CsvTxReader::new(&mut csv_reader));
+
+    let actual: Vec<_> = engine
+        .get_accounts()
+        .into_iter()
+        .map(AccountRecord::from_account)
+        .collect();
+    let expected = output::read_accounts(
+        fs::File::open(dir.join(EXPECTED_FILE))?,
+        &OutputFormat::default(),
+    )?;
+
+    Ok(diff_accounts(&expected, &actual))
+}
+
+fn diff_accounts(expected: &[AccountRecord], actual: &[AccountRecord]) -> Vec<String> {
+    let expected: BTreeMap<_, _> = expected.iter().map(|acc| (acc.client_id, acc)).collect();
+    let actual: BTreeMap<_, _> = actual.iter().map(|acc| (acc.client_id, acc)).collect();
+
+    let mut differences = Vec::new();
+
+    for (client_id, expected_acc) in &expected {
+        match actual.get(client_id) {
+            Some(actual_acc) if actual_acc != expected_acc => differences.push(format!(
+                "client {}: expected {:?} but got {:?}",
+                client_id, expected_acc, actual_acc
+            )),
+            Some(_) => {}
+            None => differences.push(format!("client {}: missing account", client_id)),
+        }
+    }
+
+    for client_id in actual.keys() {
+        if !expected.contains_key(client_id) {
+            differences.push(format!("client {}: unexpected account", client_id));
+        }
+    }
+
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amount::{dec, Amount},
+        types::ClientId,
+    };
+
+    fn record(client_id: ClientId, available: Amount) -> AccountRecord {
+        AccountRecord {
+            client_id,
+            available,
+            held: dec!(0),
+            total: available,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn diff_accounts_ignores_order() {
+        let expected = vec![record(1, dec!(1)), record(2, dec!(2))];
+        let actual = vec![record(2, dec!(2)), record(1, dec!(1))];
+
+        assert!(diff_accounts(&expected, &actual).is_empty());
+    }
+
+    #[test]
+    fn diff_accounts_reports_differences() {
+        let expected = vec![record(1, dec!(1)), record(2, dec!(2))];
+        let actual = vec![record(1, dec!(5)), record(3, dec!(3))];
+
+        let differences = diff_accounts(&expected, &actual);
+
+        assert_eq!(differences.len(), 3);
+        assert!(differences[0].starts_with("client 1: expected"));
+        assert_eq!(differences[1], "client 2: missing account");
+        assert_eq!(differences[2], "client 3: unexpected account");
+    }
+}
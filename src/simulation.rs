@@ -0,0 +1,226 @@
+use std::fmt;
+
+use log::warn;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    account::Manager,
+    amount::Amount,
+    types::{Account, ClientId},
+};
+
+/// Error returned by [`FlakyManager`] for an injected failure. The wrapped manager is not called,
+/// so the operation can safely be retried.
+#[derive(Debug)]
+pub struct TransientError {
+    pub operation: &'static str,
+}
+
+impl fmt::Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Injected transient failure in {}", self.operation)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+/// Error returned by [`FlakyManager`]: either an injected failure or an error from the wrapped
+/// manager, which is kept as the source so its reject code can still be found.
+#[derive(Debug)]
+pub enum FlakyError<E> {
+    Transient(TransientError),
+    Inner(E),
+}
+
+impl<E: fmt::Display> fmt::Display for FlakyError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlakyError::Transient(err) => err.fmt(f),
+            FlakyError::Inner(err) => err.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for FlakyError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FlakyError::Transient(_) => None,
+            FlakyError::Inner(err) => Some(err),
+        }
+    }
+}
+
+/// Wraps a [`Manager`] and randomly fails a fraction of its operations with a [`TransientError`].
+///
+/// Failures are drawn from a seeded generator so a simulation run can be reproduced exactly.
+pub struct FlakyManager<M: Manager> {
+    inner: M,
+    failure_rate: f64,
+    rng: StdRng,
+}
+
+impl<M: Manager> FlakyManager<M> {
+    pub fn new(inner: M, failure_rate: f64, seed: u64) -> Self {
+        Self {
+            inner,
+            failure_rate: failure_rate.clamp(0.0, 1.0),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn maybe_fail(&mut self, operation: &'static str) -> Result<(), FlakyError<M::Error>> {
+        if self.rng.gen_bool(self.failure_rate) {
+            warn!("Injecting transient failure in {}", operation);
+            return Err(FlakyError::Transient(TransientError { operation }));
+        }
+
+        Ok(())
+    }
+}
+
+impl<M: Manager> Manager for FlakyManager<M> {
+    type Error = FlakyError<M::Error>;
+
+    fn ensure_account(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.maybe_fail("ensure_account")?;
+        self.inner
+            .ensure_account(client_id)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn deposit(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.maybe_fail("deposit")?;
+        self.inner
+            .deposit(client_id, amount)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn withdraw(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.maybe_fail("withdraw")?;
+        self.inner
+            .withdraw(client_id, amount)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn withdraw_held(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.maybe_fail("withdraw_held")?;
+        self.inner
+            .withdraw_held(client_id, amount)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn hold(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.maybe_fail("hold")?;
+        self.inner
+            .hold(client_id, amount)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn release(&mut self, client_id: ClientId, amount: Amount) -> Result<(), Self::Error> {
+        self.maybe_fail("release")?;
+        self.inner
+            .release(client_id, amount)
+            .map_err(FlakyError::Inner)
+    }
+
+    fn lock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.maybe_fail("lock")?;
+        self.inner.lock(client_id).map_err(FlakyError::Inner)
+    }
+
+    fn unlock(&mut self, client_id: ClientId) -> Result<(), Self::Error> {
+        self.maybe_fail("unlock")?;
+        self.inner.unlock(client_id).map_err(FlakyError::Inner)
+    }
+
+    fn is_locked(&mut self, client_id: ClientId) -> Result<bool, Self::Error> {
+        self.maybe_fail("is_locked")?;
+        self.inner.is_locked(client_id).map_err(FlakyError::Inner)
+    }
+
+    fn all(&self) -> Vec<&Account> {
+        self.inner.all()
+    }
+
+    fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.inner.account(client_id)
+    }
+
+    fn page(&self, after: Option<ClientId>, limit: usize) -> Vec<&Account> {
+        self.inner.page(after, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::SimpleManager,
+        amount::dec,
+        engine::Engine,
+        types::{Transaction, TxType},
+    };
+
+    #[test]
+    fn never_fails_with_zero_failure_rate() {
+        let mut manager = FlakyManager::new(SimpleManager::new(), 0.0, 1);
+
+        for _ in 0..100 {
+            assert!(manager.ensure_account(1).is_ok());
+        }
+    }
+
+    #[test]
+    fn always_fails_with_full_failure_rate() {
+        let mut manager = FlakyManager::new(SimpleManager::new(), 1.0, 1);
+
+        let err = manager.ensure_account(1).err().unwrap();
+
+        assert!(matches!(err, FlakyError::Transient(_)));
+        assert_eq!(
+            err.to_string(),
+            "Injected transient failure in ensure_account"
+        );
+        assert!(manager.all().is_empty());
+    }
+
+    #[test]
+    fn failures_are_reproducible_for_a_seed() {
+        let run = |seed| {
+            let mut manager = FlakyManager::new(SimpleManager::new(), 0.5, seed);
+            (0..50)
+                .map(|_| manager.ensure_account(1).is_ok())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(7), run(7));
+    }
+
+    #[test]
+    fn engine_keeps_balances_consistent_under_injected_failures() {
+        let accounts = FlakyManager::new(SimpleManager::new(), 0.2, 42);
+        let mut engine = Engine::new(accounts);
+
+        let txs = (1..=200).map(|tx_id| {
+            let client_id = (tx_id % 5) as ClientId;
+            let tx = match tx_id % 4 {
+                0 => Transaction::new(TxType::Withdrawal, client_id, tx_id, dec!(2.0)),
+                1 => Transaction::new(
+                    TxType::Dispute,
+                    client_id,
+                    tx_id.saturating_sub(3),
+                    dec!(0.0),
+                ),
+                _ => Transaction::new(TxType::Deposit, client_id, tx_id, dec!(3.0)),
+            };
+            Ok(tx)
+        });
+
+        engine.process_all(txs);
+
+        for acc in engine.get_accounts() {
+            assert!(acc.available_amount >= Amount::ZERO);
+            assert!(acc.held_amount >= Amount::ZERO);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    iter::Fuse,
+    num::NonZeroU32,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::types::Transaction;
+
+/// A source of transactions for the engine, yielding each record as a transaction or the error it
+/// failed to parse with.
+///
+/// Every iterator of transaction results is a source, such as the records of a `CsvTxReader` or a
+/// `Vec` of generated transactions, so sources compose with the standard iterator adapters
+/// (`take`, `chain`, ...) as well as the transaction-aware combinators below.
+pub trait TxSource: Iterator<Item = anyhow::Result<Transaction>> + Sized {
+    /// Keeps the transactions matching `predicate`. Records that failed to parse are kept, so they
+    /// are still rejected by the engine.
+    fn filter_ok<P: FnMut(&Transaction) -> bool>(self, predicate: P) -> FilterOk<Self, P> {
+        FilterOk {
+            source: self,
+            predicate,
+        }
+    }
+
+    /// Alternates between this source and `other` one record at a time until both are exhausted,
+    /// keeping the order of each source.
+    fn merge<S: TxSource>(self, other: S) -> Merge<Self, S> {
+        Merge {
+            first: self.fuse(),
+            second: other.fuse(),
+            second_next: false,
+        }
+    }
+
+    /// Yields at most `max_per_second` records a second, sleeping as needed, so a backfill does
+    /// not overwhelm whatever consumes the engine's output.
+    ///
+    /// Records are spaced evenly from the first one, so a slow consumer that falls behind catches
+    /// up at full speed rather than bursting later.
+    fn throttle(self, max_per_second: NonZeroU32) -> Throttle<Self> {
+        Throttle {
+            source: self,
+            max_per_second,
+            start: None,
+            yielded: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<Transaction>>> TxSource for I {}
+
+/// Source returned by [`TxSource::filter_ok`].
+pub struct FilterOk<S, P> {
+    source: S,
+    predicate: P,
+}
+
+impl<S: TxSource, P: FnMut(&Transaction) -> bool> Iterator for FilterOk<S, P> {
+    type Item = anyhow::Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.by_ref().find(|result| match result {
+            Ok(tx) => (self.predicate)(tx),
+            Err(_) => true,
+        })
+    }
+}
+
+/// Source returned by [`TxSource::throttle`].
+pub struct Throttle<S> {
+    source: S,
+    max_per_second: NonZeroU32,
+    start: Option<Instant>,
+    yielded: u64,
+}
+
+impl<S: TxSource> Iterator for Throttle<S> {
+    type Item = anyhow::Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.source.next()?;
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let due =
+            start + Duration::from_secs_f64(self.yielded as f64 / self.max_per_second.get() as f64);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        self.yielded += 1;
+        Some(item)
+    }
+}
+
+/// Source returned by [`TxSource::merge`].
+pub struct Merge<A, B> {
+    first: Fuse<A>,
+    second: Fuse<B>,
+    second_next: bool,
+}
+
+impl<A: TxSource, B: TxSource> Iterator for Merge<A, B> {
+    type Item = anyhow::Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.second_next = !self.second_next;
+        match self.second_next {
+            true => self.first.next().or_else(|| self.second.next()),
+            false => self.second.next().or_else(|| self.first.next()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::{
+        amount::dec,
+        types::{ClientId, TxType},
+    };
+
+    fn deposits(
+        client_id: ClientId,
+        tx_ids: std::ops::Range<u64>,
+    ) -> Vec<anyhow::Result<Transaction>> {
+        tx_ids
+            .map(|tx_id| Ok(Transaction::new(TxType::Deposit, client_id, tx_id, dec!(1))))
+            .collect()
+    }
+
+    #[test]
+    fn merge_alternates_until_both_are_exhausted() {
+        let tx_ids: Vec<_> = deposits(1, 1..3)
+            .into_iter()
+            .merge(deposits(2, 10..14).into_iter())
+            .map(|result| result.unwrap().tx_id)
+            .collect();
+
+        assert_eq!(tx_ids, vec![1, 10, 2, 11, 12, 13]);
+    }
+
+    #[test]
+    fn filter_ok_keeps_errors() {
+        let mut records = deposits(1, 1..5);
+        records.insert(1, Err(anyhow!("bad record")));
+
+        let results: Vec<_> = records
+            .into_iter()
+            .filter_ok(|tx| tx.tx_id % 2 == 0)
+            .take(2)
+            .collect();
+
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap().tx_id, 2);
+    }
+
+    #[test]
+    fn throttle_spaces_records() {
+        let start = Instant::now();
+        let records = deposits(1, 0..21)
+            .into_iter()
+            .throttle(NonZeroU32::new(1000).unwrap())
+            .count();
+
+        assert_eq!(records, 21);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
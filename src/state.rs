@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+
+use crate::{
+    engine::{EngineState, OpenDispute},
+    types::{Account, StoredTx},
+};
+
+const VERSION: &str = "3";
+
+/// Writes the state as CSV records tagged with their kind: a `state` version record and the tx id
+/// collision `policy`, then `account`, `tx`, `dispute`, namespaced tx `id`, accrued `reward`,
+/// opened account `open` and account `merge` records.
+pub fn write_state(w: impl Write, state: &EngineState) -> anyhow::Result<()> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_writer(w);
+
+    writer.write_record(["state", VERSION])?;
+    writer.write_record(["policy", state.tx_id_collisions.as_str()])?;
+    for acc in &state.accounts {
+        writer.write_record([
+            "account".to_string(),
+            acc.client_id.to_string(),
+            acc.available_amount.to_string(),
+            acc.held_amount.to_string(),
+            acc.is_locked.to_string(),
+        ])?;
+    }
+    for (index_id, tx) in &state.transactions {
+        writer.write_record([
+            "tx".to_string(),
+            index_id.to_string(),
+            tx.tx_type.as_str().to_string(),
+            tx.client_id.to_string(),
+            tx.amount.to_string(),
+            tx.state.as_str().to_string(),
+        ])?;
+    }
+    for (index_id, dispute) in &state.disputes {
+        let opened = dispute
+            .opened
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        writer.write_record([
+            "dispute".to_string(),
+            index_id.to_string(),
+            opened.as_millis().to_string(),
+            write_metadata(&dispute.metadata),
+        ])?;
+    }
+    for (source, tx_id, index_id) in &state.namespaced_ids {
+        writer.write_record([
+            "id".to_string(),
+            source.to_string(),
+            tx_id.to_string(),
+            index_id.to_string(),
+        ])?;
+    }
+    for (client_id, amount) in &state.rewards {
+        writer.write_record([
+            "reward".to_string(),
+            client_id.to_string(),
+            amount.to_string(),
+        ])?;
+    }
+    for (client_id, metadata) in &state.opened_accounts {
+        writer.write_record([
+            "open".to_string(),
+            client_id.to_string(),
+            write_metadata(metadata),
+        ])?;
+    }
+    for (source, target) in &state.merged_into {
+        writer.write_record(["merge".to_string(), source.to_string(), target.to_string()])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_state(r: impl io::Read) -> anyhow::Result<EngineState> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(r);
+
+    let mut state = EngineState::default();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let invalid = || anyhow!("Invalid state record on line {}", index + 1);
+        let field = |i: usize| record.get(i).ok_or_else(invalid);
+
+        match (index, field(0)?) {
+            (0, "state") if field(1)? == VERSION => {}
+            (0, _) => return Err(anyhow!("Not a state file, or written by another version")),
+            (_, "policy") => state.tx_id_collisions = field(1)?.parse().map_err(|_| invalid())?,
+            (_, "account") => state.accounts.push(Account {
+                client_id: field(1)?.parse().map_err(|_| invalid())?,
+                available_amount: field(2)?.parse().map_err(|_| invalid())?,
+                held_amount: field(3)?.parse().map_err(|_| invalid())?,
+                is_locked: field(4)?.parse().map_err(|_| invalid())?,
+            }),
+            (_, "tx") => state.transactions.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                StoredTx {
+                    tx_type: field(2)?.parse().map_err(|_| invalid())?,
+                    client_id: field(3)?.parse().map_err(|_| invalid())?,
+                    amount: field(4)?.parse().map_err(|_| invalid())?,
+                    state: field(5)?.parse().map_err(|_| invalid())?,
+                },
+            )),
+            (_, "dispute") => state.disputes.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                OpenDispute {
+                    opened: UNIX_EPOCH
+                        + Duration::from_millis(field(2)?.parse().map_err(|_| invalid())?),
+                    metadata: read_metadata(&record, 3),
+                },
+            )),
+            (_, "id") => state.namespaced_ids.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                field(2)?.parse().map_err(|_| invalid())?,
+                field(3)?.parse().map_err(|_| invalid())?,
+            )),
+            (_, "reward") => state.rewards.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                field(2)?.parse().map_err(|_| invalid())?,
+            )),
+            (_, "open") => state.opened_accounts.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                read_metadata(&record, 2),
+            )),
+            (_, "merge") => state.merged_into.push((
+                field(1)?.parse().map_err(|_| invalid())?,
+                field(2)?.parse().map_err(|_| invalid())?,
+            )),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(state)
+}
+
+fn write_metadata(metadata: &BTreeMap<String, String>) -> String {
+    let entries: Vec<String> = metadata
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    entries.join(";")
+}
+
+fn read_metadata(record: &StringRecord, i: usize) -> BTreeMap<String, String> {
+    record
+        .get(i)
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Loads the state saved by an earlier run, or `None` if there is no state file yet.
+pub fn load_state(path: impl AsRef<Path>) -> anyhow::Result<Option<EngineState>> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(read_state(io::BufReader::new(file))?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Saves the state, replacing the file only once it has been written in full.
+pub fn save_state(path: impl AsRef<Path>, state: &EngineState) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+
+    let mut w = BufWriter::new(File::create(&partial)?);
+    write_state(&mut w, state)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    fs::rename(partial, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amount::dec,
+        engine::TxIdCollisionPolicy,
+        types::{TxState, TxType},
+    };
+
+    #[test]
+    fn state_round_trips_through_a_file() {
+        let state = EngineState {
+            accounts: vec![Account {
+                client_id: 1,
+                available_amount: dec!(2.5),
+                held_amount: dec!(1),
+                is_locked: true,
+            }],
+            transactions: vec![
+                (
+                    1,
+                    StoredTx::new(TxType::Deposit, 1, dec!(1)).with_state(TxState::Disputed),
+                ),
+                (2, StoredTx::new(TxType::Withdrawal, 1, dec!(0.5))),
+            ],
+            disputes: vec![(
+                1,
+                OpenDispute {
+                    opened: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+                    metadata: [("case_id".to_string(), "C-1, urgent".to_string())].into(),
+                },
+            )],
+            namespaced_ids: vec![(0, 7, 1), (1, 7, 2)],
+            rewards: vec![(1, dec!(0.015)), (2, dec!(-0.5))],
+            opened_accounts: vec![
+                (1, [("currency".to_string(), "EUR".to_string())].into()),
+                (2, BTreeMap::new()),
+            ],
+            merged_into: vec![(2, 1)],
+            tx_id_collisions: TxIdCollisionPolicy::Namespaced,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.csv");
+
+        save_state(&path, &state).unwrap();
+
+        assert_eq!(load_state(&path).unwrap(), Some(state));
+        assert_eq!(load_state(dir.path().join("missing.csv")).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_state_files() {
+        let err = read_state("type,client,tx,amount\n".as_bytes()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Not a state file, or written by another version"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    mem,
+    path::Path,
+};
+
+use anyhow::anyhow;
+use log::info;
+use roaring::RoaringTreemap;
+
+use crate::types::{StoredTx, TxId};
+
+/// What to do with the oldest stored transaction when the store is full.
+pub enum EvictionPolicy {
+    /// Forget the transaction. Later disputes referencing it are ignored as unknown.
+    OldestFirst,
+    /// Move the transaction to a file on disk, keeping only its offset in memory.
+    SpillToDisk(SpillFile),
+}
+
+/// Append-only file of evicted transactions, indexed by tx id.
+pub struct SpillFile {
+    file: File,
+    offsets: HashMap<TxId, u64>,
+}
+
+impl SpillFile {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok(Self {
+            file,
+            offsets: HashMap::new(),
+        })
+    }
+
+    fn write(&mut self, tx_id: TxId, tx: &StoredTx) -> anyhow::Result<()> {
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        writeln!(
+            self.file,
+            "{},{},{},{},{}",
+            tx_id,
+            tx.tx_type.as_str(),
+            tx.client_id,
+            tx.amount,
+            tx.state.as_str()
+        )?;
+
+        self.offsets.insert(tx_id, offset);
+        Ok(())
+    }
+
+    fn read(&self, tx_id: TxId) -> anyhow::Result<Option<StoredTx>> {
+        let offset = match self.offsets.get(&tx_id) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line)?;
+
+        let fields: Vec<&str> = line.trim_end().split(',').collect();
+        match fields[..] {
+            [_, tx_type, client_id, amount, state] => Ok(Some(StoredTx {
+                tx_type: tx_type.parse()?,
+                client_id: client_id.parse()?,
+                amount: amount.parse()?,
+                state: state.parse()?,
+            })),
+            _ => Err(anyhow!("Spilled transaction {} could not be read", tx_id)),
+        }
+    }
+}
+
+/// Storage for processed transactions that can be referenced by later disputes.
+pub trait TxIndex {
+    fn get(&self, tx_id: TxId) -> anyhow::Result<Option<StoredTx>>;
+
+    /// Whether the tx id has ever been inserted.
+    fn contains(&self, tx_id: TxId) -> bool;
+
+    /// Inserts or replaces the stored transaction.
+    fn insert(&mut self, tx_id: TxId, tx: StoredTx) -> anyhow::Result<()>;
+
+    /// Number of transactions held by the index.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of transactions dropped or moved out of memory to respect a size limit.
+    fn evictions(&self) -> usize {
+        0
+    }
+
+    /// Approximate number of bytes of memory used by the index.
+    fn memory_bytes(&self) -> usize {
+        self.len() * (mem::size_of::<TxId>() + mem::size_of::<StoredTx>())
+    }
+
+    /// Every stored transaction in tx id order, including any moved out of memory, so the index
+    /// can be saved and restored in a later run.
+    fn entries(&self) -> anyhow::Result<Vec<(TxId, StoredTx)>> {
+        Err(anyhow!("This transaction index cannot be listed"))
+    }
+}
+
+/// In-memory index of processed transactions.
+///
+/// The number of transactions held in memory can be capped, in which case the oldest
+/// transaction is evicted according to the [`EvictionPolicy`] to make room for new ones.
+///
+/// Every tx id ever inserted is also recorded in a compressed bitmap (a [`RoaringTreemap`] for
+/// 64-bit ids), so lookups for unknown ids never reach the slow path and duplicate ids are detected
+/// even after eviction.
+pub struct TxStore {
+    seen: RoaringTreemap,
+    transactions: HashMap<TxId, StoredTx>,
+    order: VecDeque<TxId>,
+    capacity: Option<usize>,
+    policy: EvictionPolicy,
+    evictions: usize,
+}
+
+impl TxStore {
+    pub fn new(capacity: Option<usize>, policy: EvictionPolicy) -> Self {
+        Self {
+            seen: RoaringTreemap::new(),
+            transactions: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            policy,
+            evictions: 0,
+        }
+    }
+
+    fn evict_oldest(&mut self) -> anyhow::Result<bool> {
+        let tx_id = match self.order.pop_front() {
+            Some(tx_id) => tx_id,
+            None => return Ok(false),
+        };
+
+        if let Some(tx) = self.transactions.remove(&tx_id) {
+            if let EvictionPolicy::SpillToDisk(spill) = &mut self.policy {
+                spill.write(tx_id, &tx)?;
+            }
+
+            info!("Evicted transaction {}", tx_id);
+            self.evictions += 1;
+        }
+
+        Ok(true)
+    }
+}
+
+impl TxIndex for TxStore {
+    fn get(&self, tx_id: TxId) -> anyhow::Result<Option<StoredTx>> {
+        if !self.seen.contains(tx_id) {
+            return Ok(None);
+        }
+
+        match self.transactions.get(&tx_id) {
+            Some(tx) => Ok(Some(*tx)),
+            None => match &self.policy {
+                EvictionPolicy::SpillToDisk(spill) => spill.read(tx_id),
+                EvictionPolicy::OldestFirst => Ok(None),
+            },
+        }
+    }
+
+    fn contains(&self, tx_id: TxId) -> bool {
+        self.seen.contains(tx_id)
+    }
+
+    /// Spilled transactions are brought back into memory when replaced.
+    fn insert(&mut self, tx_id: TxId, tx: StoredTx) -> anyhow::Result<()> {
+        if let Some(existing) = self.transactions.get_mut(&tx_id) {
+            *existing = tx;
+            return Ok(());
+        }
+
+        if let Some(capacity) = self.capacity {
+            while self.transactions.len() >= capacity {
+                if !self.evict_oldest()? {
+                    break;
+                }
+            }
+        }
+
+        self.seen.insert(tx_id);
+        self.order.push_back(tx_id);
+        self.transactions.insert(tx_id, tx);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    /// Evicted transactions are only listed if they were spilled to disk.
+    fn entries(&self) -> anyhow::Result<Vec<(TxId, StoredTx)>> {
+        let mut entries: Vec<_> = self
+            .transactions
+            .iter()
+            .map(|(tx_id, tx)| (*tx_id, *tx))
+            .collect();
+        if let EvictionPolicy::SpillToDisk(spill) = &self.policy {
+            for tx_id in spill.offsets.keys() {
+                if !self.transactions.contains_key(tx_id) {
+                    if let Some(tx) = spill.read(*tx_id)? {
+                        entries.push((*tx_id, tx));
+                    }
+                }
+            }
+        }
+
+        entries.sort_by_key(|(tx_id, _)| *tx_id);
+        Ok(entries)
+    }
+
+    /// Counts allocated rather than used capacity, ignoring per-entry hash table overhead.
+    fn memory_bytes(&self) -> usize {
+        self.transactions.capacity() * (mem::size_of::<TxId>() + mem::size_of::<StoredTx>())
+            + self.order.capacity() * mem::size_of::<TxId>()
+            + self.seen.serialized_size()
+    }
+}
+
+impl Default for TxStore {
+    fn default() -> Self {
+        Self::new(None, EvictionPolicy::OldestFirst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        amount::dec,
+        types::{TxState, TxType},
+    };
+
+    fn stored() -> StoredTx {
+        StoredTx::new(TxType::Deposit, 1, dec!(1.5))
+    }
+
+    #[test]
+    fn unbounded_store_keeps_everything() {
+        let mut store = TxStore::default();
+
+        for tx_id in 1..=100 {
+            store.insert(tx_id, stored()).unwrap();
+        }
+
+        assert_eq!(store.len(), 100);
+        assert_eq!(store.evictions(), 0);
+        assert!(store.get(1).unwrap().is_some());
+    }
+
+    #[test]
+    fn stores_tx_ids_beyond_32_bits() {
+        let mut store = TxStore::default();
+        let tx_id = u64::from(u32::MAX) + 1;
+
+        store.insert(tx_id, stored()).unwrap();
+
+        assert!(store.contains(tx_id));
+        assert!(!store.contains(0));
+        assert_eq!(store.get(tx_id).unwrap(), Some(stored()));
+    }
+
+    #[test]
+    fn contains_only_inserted_tx_ids() {
+        let mut store = TxStore::default();
+
+        store.insert(7, stored()).unwrap();
+
+        assert!(store.contains(7));
+        assert!(!store.contains(8));
+        assert!(store.get(8).unwrap().is_none());
+    }
+
+    #[test]
+    fn insert_replaces_existing_transaction() {
+        let mut store = TxStore::new(Some(1), EvictionPolicy::OldestFirst);
+
+        store.insert(1, stored()).unwrap();
+        store
+            .insert(1, stored().with_state(TxState::Disputed))
+            .unwrap();
+
+        assert_eq!(store.evictions(), 0);
+        assert_eq!(store.get(1).unwrap().unwrap().state, TxState::Disputed);
+    }
+
+    #[test]
+    fn oldest_first_evicts_oldest_transactions() {
+        let mut store = TxStore::new(Some(2), EvictionPolicy::OldestFirst);
+
+        store.insert(1, stored()).unwrap();
+        store.insert(2, stored()).unwrap();
+        store.insert(3, stored()).unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.evictions(), 1);
+        assert!(store.contains(1));
+        assert!(store.get(1).unwrap().is_none());
+        assert!(store.get(3).unwrap().is_some());
+    }
+
+    #[test]
+    fn spill_to_disk_keeps_evicted_transactions_readable() {
+        let path = std::env::temp_dir().join(format!("spill-test-{}.csv", std::process::id()));
+        let spill = SpillFile::create(&path).unwrap();
+        let mut store = TxStore::new(Some(1), EvictionPolicy::SpillToDisk(spill));
+
+        store
+            .insert(1, stored().with_state(TxState::Disputed))
+            .unwrap();
+        store.insert(2, stored()).unwrap();
+        store.insert(3, stored()).unwrap();
+
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.evictions(), 2);
+        assert!(store.contains(1));
+
+        let tx = store.get(1).unwrap().unwrap();
+        assert_eq!(tx.client_id, 1);
+        assert_eq!(tx.amount, dec!(1.5));
+        assert_eq!(tx.state, TxState::Disputed);
+        assert_eq!(store.get(2).unwrap().unwrap(), stored());
+        assert_eq!(
+            store.entries().unwrap(),
+            vec![
+                (1, stored().with_state(TxState::Disputed)),
+                (2, stored()),
+                (3, stored())
+            ]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,60 +1,316 @@
-use rust_decimal::Decimal;
-use serde::Deserialize;
+use alloc::{collections::BTreeMap, format, string::String};
+use core::str::FromStr;
 
-#[derive(Debug, Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amount::Amount,
+    rejection::{RejectCode, Rejection},
+};
+
+/// Numeric id of a client account. `u16` by default; the `client-id-u32` and `client-id-u64`
+/// features widen it for larger customer bases.
+#[cfg(not(any(feature = "client-id-u32", feature = "client-id-u64")))]
+pub type ClientId = u16;
+#[cfg(all(feature = "client-id-u32", not(feature = "client-id-u64")))]
+pub type ClientId = u32;
+#[cfg(feature = "client-id-u64")]
+pub type ClientId = u64;
+
+pub type TxId = u64;
+
+/// The 64-bit FNV-1a hash of no bytes.
+pub(crate) const FNV1A_EMPTY: u64 = 0xcbf29ce484222325;
+
+/// Extends a 64-bit FNV-1a `hash` with `bytes`.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Maps a string transaction id, such as a UUID, to a numeric [`TxId`] using 64-bit FNV-1a.
+///
+/// Distinct ids can collide, which is unlikely until billions of ids have been hashed.
+pub fn hash_tx_id(id: &str) -> TxId {
+    fnv1a(FNV1A_EMPTY, id.as_bytes())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum TxType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    /// The issuing bank reversed an earlier chargeback, re-crediting the amount.
+    ChargebackReversal,
+    /// Opens the client's account, with metadata such as its currency, tier and limits.
+    OpenAccount,
+}
+
+impl TxType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxType::Deposit => "deposit",
+            TxType::Withdrawal => "withdrawal",
+            TxType::Dispute => "dispute",
+            TxType::Resolve => "resolve",
+            TxType::Chargeback => "chargeback",
+            TxType::ChargebackReversal => "chargeback_reversal",
+            TxType::OpenAccount => "open_account",
+        }
+    }
+}
+
+impl FromStr for TxType {
+    type Err = Rejection;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(TxType::Deposit),
+            "withdrawal" => Ok(TxType::Withdrawal),
+            "dispute" => Ok(TxType::Dispute),
+            "resolve" => Ok(TxType::Resolve),
+            "chargeback" => Ok(TxType::Chargeback),
+            "chargeback_reversal" => Ok(TxType::ChargebackReversal),
+            "open_account" => Ok(TxType::OpenAccount),
+            _ => Err(Rejection::new(
+                RejectCode::UnknownType,
+                format!("Unknown transaction type {}", s),
+            )),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct Transaction {
-    #[serde(rename = "type")]
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub tx_type: TxType,
-    #[serde(rename = "client")]
-    pub client_id: u16,
-    #[serde(rename = "tx")]
-    pub tx_id: u32,
-    pub amount: Decimal,
+    #[cfg_attr(feature = "serde", serde(rename = "client"))]
+    pub client_id: ClientId,
+    #[cfg_attr(feature = "serde", serde(rename = "tx"))]
+    pub tx_id: TxId,
+    pub amount: Amount,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trace_id: Option<String>,
+    /// Evidence attached to a dispute, as `key=value` pairs separated by `;`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: Option<String>,
 }
 
 impl Transaction {
-    pub fn new(tx_type: TxType, client_id: u16, tx_id: u32, amount: Decimal) -> Self {
+    pub fn new(tx_type: TxType, client_id: ClientId, tx_id: TxId, amount: Amount) -> Self {
         Self {
             tx_type,
             client_id,
             tx_id,
             amount,
+            trace_id: None,
+            metadata: None,
         }
     }
+
+    pub fn with_metadata(mut self, metadata: impl Into<String>) -> Self {
+        self.metadata = Some(metadata.into());
+        self
+    }
+
+    /// Parses the metadata column into its key/value pairs.
+    pub fn metadata_entries(&self) -> Result<BTreeMap<String, String>, Rejection> {
+        let metadata = match self.metadata.as_deref() {
+            Some(metadata) => metadata,
+            None => return Ok(BTreeMap::new()),
+        };
+
+        metadata
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| match entry.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    Ok((key.trim().into(), value.trim().into()))
+                }
+                _ => Err(Rejection::new(
+                    RejectCode::MalformedRecord,
+                    format!("Invalid metadata entry {}", entry),
+                )),
+            })
+            .collect()
+    }
+
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// The id used to correlate all log lines and outputs for this transaction, or `-` if it has
+    /// none.
+    pub fn trace_id(&self) -> &str {
+        self.trace_id.as_deref().unwrap_or("-")
+    }
 }
 
-#[derive(Default)]
+/// Where a stored transaction is in the dispute lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TxState {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    /// The chargeback was reversed by the issuing bank.
+    ChargebackReversed,
+    /// Closed by an operator without a chargeback, removing the held funds.
+    WrittenOff,
+    /// Reversed by a compensating transaction, see [`crate::engine::Engine::compensate`].
+    Compensated,
+}
+
+impl TxState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxState::Processed => "processed",
+            TxState::Disputed => "disputed",
+            TxState::Resolved => "resolved",
+            TxState::ChargedBack => "chargedback",
+            TxState::ChargebackReversed => "chargebackreversed",
+            TxState::WrittenOff => "writtenoff",
+            TxState::Compensated => "compensated",
+        }
+    }
+
+    /// Returns the state a stored transaction moves to when referenced by a dispute, resolve or
+    /// chargeback of `tx_type`, or rejects the transition if it is not allowed from this state.
+    pub fn transition(self, tx_type: &TxType, tx_id: TxId) -> Result<TxState, Rejection> {
+        let (expected, next) = match tx_type {
+            TxType::Dispute => (TxState::Processed, TxState::Disputed),
+            TxType::Resolve => (TxState::Disputed, TxState::Resolved),
+            TxType::Chargeback => (TxState::Disputed, TxState::ChargedBack),
+            TxType::ChargebackReversal => (TxState::ChargedBack, TxState::ChargebackReversed),
+            TxType::Deposit | TxType::Withdrawal | TxType::OpenAccount => {
+                return Err(Rejection::new(
+                    RejectCode::InvalidTxState,
+                    format!(
+                        "Cannot {} transaction {} as it is already {}",
+                        tx_type.as_str(),
+                        tx_id,
+                        self.as_str()
+                    ),
+                ))
+            }
+        };
+
+        if self != expected {
+            return Err(Rejection::new(
+                RejectCode::InvalidTxState,
+                format!(
+                    "Cannot {} transaction {} as it is {} but must be {}",
+                    tx_type.as_str(),
+                    tx_id,
+                    self.as_str(),
+                    expected.as_str()
+                ),
+            ));
+        }
+
+        Ok(next)
+    }
+}
+
+impl FromStr for TxState {
+    type Err = Rejection;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "processed" => Ok(TxState::Processed),
+            "disputed" => Ok(TxState::Disputed),
+            "resolved" => Ok(TxState::Resolved),
+            "chargedback" => Ok(TxState::ChargedBack),
+            "chargebackreversed" => Ok(TxState::ChargebackReversed),
+            "writtenoff" => Ok(TxState::WrittenOff),
+            "compensated" => Ok(TxState::Compensated),
+            _ => Err(Rejection::new(
+                RejectCode::MalformedRecord,
+                format!("Unknown transaction state {}", s),
+            )),
+        }
+    }
+}
+
+/// The part of a processed transaction that is kept for later disputes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoredTx {
+    pub tx_type: TxType,
+    pub client_id: ClientId,
+    pub amount: Amount,
+    pub state: TxState,
+}
+
+impl StoredTx {
+    pub fn new(tx_type: TxType, client_id: ClientId, amount: Amount) -> Self {
+        Self {
+            tx_type,
+            client_id,
+            amount,
+            state: TxState::Processed,
+        }
+    }
+
+    pub fn with_state(self, state: TxState) -> Self {
+        Self { state, ..self }
+    }
+}
+
+/// Lifecycle status of an account, as written in the `status` output column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountStatus {
+    Active,
+    /// Locked by a chargeback or an operator. Further transactions are rejected.
+    Locked,
+}
+
+impl AccountStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Locked => "locked",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Account {
-    pub client_id: u16,
+    pub client_id: ClientId,
     pub is_locked: bool,
-    pub available_amount: Decimal,
-    pub held_amount: Decimal,
+    pub available_amount: Amount,
+    pub held_amount: Amount,
 }
 
 impl Account {
-    pub fn new(client_id: u16) -> Self {
+    pub fn new(client_id: ClientId) -> Self {
         Self {
             client_id,
             ..Default::default()
         }
     }
+
+    pub fn status(&self) -> AccountStatus {
+        match self.is_locked {
+            true => AccountStatus::Locked,
+            false => AccountStatus::Active,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use rust_decimal_macros::dec;
-
     use super::*;
+    use crate::amount::dec;
 
     #[test]
     fn new_transaction_sets_fields() {
@@ -63,14 +319,126 @@ mod tests {
         assert_eq!(tx.client_id, 1);
         assert_eq!(tx.tx_id, 2);
         assert_eq!(tx.amount, dec!(3.0));
+        assert_eq!(tx.trace_id, None);
+        assert_eq!(tx.trace_id(), "-");
+    }
+
+    #[test]
+    fn with_trace_id_sets_trace_id() {
+        let tx = Transaction::new(TxType::Deposit, 1, 2, dec!(3.0)).with_trace_id("abc");
+        assert_eq!(tx.trace_id(), "abc");
+    }
+
+    #[test]
+    fn metadata_entries_parses_key_value_pairs() {
+        let tx = Transaction::new(TxType::Dispute, 1, 2, dec!(0))
+            .with_metadata("case_id=C-42; evidence=https://example.com/e/1;");
+        let entries = tx.metadata_entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["case_id"], "C-42");
+        assert_eq!(entries["evidence"], "https://example.com/e/1");
+
+        let tx = tx.with_metadata("case_id");
+        assert_eq!(
+            tx.metadata_entries().unwrap_err().code,
+            RejectCode::MalformedRecord
+        );
+    }
+
+    #[test]
+    fn tx_type_round_trips_through_str() {
+        for tx_type in [
+            TxType::Deposit,
+            TxType::Withdrawal,
+            TxType::Dispute,
+            TxType::Resolve,
+            TxType::Chargeback,
+            TxType::ChargebackReversal,
+            TxType::OpenAccount,
+        ] {
+            assert_eq!(tx_type.as_str().parse::<TxType>().unwrap(), tx_type);
+        }
+
+        assert!("refund".parse::<TxType>().is_err());
+    }
+
+    #[test]
+    fn tx_state_round_trips_through_str() {
+        for state in [
+            TxState::Processed,
+            TxState::Disputed,
+            TxState::Resolved,
+            TxState::ChargedBack,
+            TxState::ChargebackReversed,
+            TxState::WrittenOff,
+            TxState::Compensated,
+        ] {
+            assert_eq!(state.as_str().parse::<TxState>().unwrap(), state);
+        }
+
+        assert!("open".parse::<TxState>().is_err());
+    }
+
+    #[test]
+    fn transition_follows_dispute_lifecycle() {
+        let disputed = TxState::Processed.transition(&TxType::Dispute, 1).unwrap();
+        assert_eq!(disputed, TxState::Disputed);
+        assert_eq!(
+            disputed.transition(&TxType::Resolve, 1).unwrap(),
+            TxState::Resolved
+        );
+        assert_eq!(
+            disputed.transition(&TxType::Chargeback, 1).unwrap(),
+            TxState::ChargedBack
+        );
+        assert_eq!(
+            TxState::ChargedBack
+                .transition(&TxType::ChargebackReversal, 1)
+                .unwrap(),
+            TxState::ChargebackReversed
+        );
+
+        let err = TxState::Resolved
+            .transition(&TxType::Dispute, 1)
+            .unwrap_err();
+        assert_eq!(err.code, RejectCode::InvalidTxState);
+        assert_eq!(
+            err.message,
+            "Cannot dispute transaction 1 as it is resolved but must be processed"
+        );
+    }
+
+    #[test]
+    fn hash_tx_id_is_stable() {
+        assert_eq!(hash_tx_id(""), 0xcbf29ce484222325);
+        assert_eq!(hash_tx_id("a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(
+            hash_tx_id("6f1c2a7e-3b1d-4c5e-9f0a-1b2c3d4e5f60"),
+            hash_tx_id("6f1c2a7e-3b1d-4c5e-9f0a-1b2c3d4e5f61")
+        );
+    }
+
+    #[test]
+    fn new_stored_tx_is_processed() {
+        let stored = StoredTx::new(TxType::Withdrawal, 1, dec!(2.0));
+        assert_eq!(stored.tx_type, TxType::Withdrawal);
+        assert_eq!(stored.client_id, 1);
+        assert_eq!(stored.amount, dec!(2.0));
+        assert_eq!(stored.state, TxState::Processed);
+        assert_eq!(
+            stored.with_state(TxState::Disputed).state,
+            TxState::Disputed
+        );
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn new_account_sets_fields() {
         let acc = Account::new(1);
         assert_eq!(acc.client_id, 1);
         assert_eq!(acc.available_amount, dec!(0.0));
         assert_eq!(acc.held_amount, dec!(0.0));
         assert_eq!(acc.is_locked, false);
+        assert_eq!(acc.status(), AccountStatus::Active);
     }
 }
@@ -0,0 +1,139 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::AsRawFd,
+};
+
+use io_uring::{opcode, types, IoUring};
+
+/// Size of each read submitted to the kernel.
+const CHUNK: usize = 1024 * 1024;
+
+/// Reads a file with io_uring, keeping the next chunk in flight while the current one is parsed
+/// so disk reads overlap with processing.
+pub struct UringReader {
+    ring: IoUring,
+    file: File,
+    /// The buffer being consumed and the buffer the kernel is reading into.
+    buffers: [Box<[u8]>; 2],
+    current: usize,
+    pos: usize,
+    len: usize,
+    /// File offset of the next read to submit.
+    offset: u64,
+    in_flight: bool,
+}
+
+impl UringReader {
+    pub fn open(file: File) -> io::Result<Self> {
+        let mut reader = Self {
+            ring: IoUring::new(2)?,
+            file,
+            buffers: [vec![0; CHUNK].into(), vec![0; CHUNK].into()],
+            current: 0,
+            pos: 0,
+            len: 0,
+            offset: 0,
+            in_flight: false,
+        };
+        reader.submit(1)?;
+        Ok(reader)
+    }
+
+    /// Starts reading the next chunk of the file into `buffer`.
+    fn submit(&mut self, buffer: usize) -> io::Result<()> {
+        let buf = &mut self.buffers[buffer];
+        let read = opcode::Read::new(
+            types::Fd(self.file.as_raw_fd()),
+            buf.as_mut_ptr(),
+            buf.len() as u32,
+        )
+        .offset(self.offset)
+        .build();
+
+        // Safety: the buffer is heap allocated and neither read nor freed until the read has
+        // completed, see `complete` and `Drop`.
+        unsafe {
+            self.ring
+                .submission()
+                .push(&read)
+                .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        }
+        self.ring.submit()?;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Waits for the read in flight and returns the number of bytes read.
+    fn complete(&mut self) -> io::Result<usize> {
+        self.ring.submit_and_wait(1)?;
+        self.in_flight = false;
+
+        let result = self
+            .ring
+            .completion()
+            .next()
+            .ok_or_else(|| io::Error::other("io_uring read did not complete"))?
+            .result();
+        if result < 0 {
+            return Err(io::Error::from_raw_os_error(-result));
+        }
+        Ok(result as usize)
+    }
+}
+
+impl Read for UringReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.len {
+            if !self.in_flight {
+                return Ok(0);
+            }
+
+            let len = self.complete()?;
+            self.current = 1 - self.current;
+            self.pos = 0;
+            self.len = len;
+            self.offset += len as u64;
+            if len == 0 {
+                return Ok(0);
+            }
+
+            // The consumed buffer is free again, so start reading the chunk after this one.
+            self.submit(1 - self.current)?;
+        }
+
+        let n = out.len().min(self.len - self.pos);
+        out[..n].copy_from_slice(&self.buffers[self.current][self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Drop for UringReader {
+    fn drop(&mut self) {
+        // The kernel may still write into a buffer, so it must not be freed before the read ends.
+        if self.in_flight {
+            let _ = self.complete();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn reads_whole_file_across_chunks() {
+        let contents: Vec<u8> = (0..CHUNK * 2 + 123).map(|i| (i % 251) as u8).collect();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&contents).unwrap();
+
+        let mut reader = UringReader::open(File::open(file.path()).unwrap()).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+
+        assert_eq!(read, contents);
+    }
+}
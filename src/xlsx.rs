@@ -0,0 +1,287 @@
+use std::io::{Read, Seek};
+
+use anyhow::anyhow;
+use xml::reader::{EventReader, XmlEvent};
+use zip::{result::ZipError, ZipArchive};
+
+/// Sheet read when the workbook does not say where its first sheet is.
+const DEFAULT_SHEET: &str = "xl/worksheets/sheet1.xml";
+
+/// Converts the first sheet of an `.xlsx` workbook to CSV, so spreadsheets are read with the same
+/// column mapping as CSV files.
+///
+/// Cells are written as Excel displays them by default: numbers are rounded to 15 significant
+/// digits, so an amount such as `0.30000000000000004` left behind by a formula is read as `0.3`.
+pub fn first_sheet_to_csv(r: impl Read + Seek) -> anyhow::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(r)?;
+    let shared_strings = match read_entry(&mut archive, "xl/sharedStrings.xml")? {
+        Some(xml) => shared_strings(&xml)?,
+        None => Vec::new(),
+    };
+    let sheet_path = first_sheet_path(&mut archive)?;
+    let sheet = read_entry(&mut archive, &sheet_path)?
+        .ok_or_else(|| anyhow!("Workbook has no sheet {}", sheet_path))?;
+
+    let mut w = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for row in sheet_rows(&sheet, &shared_strings)? {
+        w.write_record(&row)?;
+    }
+    w.into_inner().map_err(|err| anyhow!("{}", err.error()))
+}
+
+fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(Some(contents))
+}
+
+/// Finds the first sheet listed in the workbook through the workbook's relationships.
+fn first_sheet_path<R: Read + Seek>(archive: &mut ZipArchive<R>) -> anyhow::Result<String> {
+    let workbook = read_entry(archive, "xl/workbook.xml")?
+        .ok_or_else(|| anyhow!("Not an xlsx workbook: it has no xl/workbook.xml"))?;
+    let Some(relationship) = first_attribute(&workbook, "sheet", "id", Some("r"))? else {
+        return Ok(DEFAULT_SHEET.to_string());
+    };
+    let Some(relationships) = read_entry(archive, "xl/_rels/workbook.xml.rels")? else {
+        return Ok(DEFAULT_SHEET.to_string());
+    };
+
+    for event in EventReader::new(relationships.as_slice()) {
+        if let XmlEvent::StartElement {
+            name, attributes, ..
+        } = event?
+        {
+            let attribute = |local_name: &str| {
+                attributes
+                    .iter()
+                    .find(|attribute| attribute.name.local_name == local_name)
+                    .map(|attribute| attribute.value.as_str())
+            };
+            if name.local_name == "Relationship" && attribute("Id") == Some(&relationship) {
+                return Ok(match attribute("Target").unwrap_or(DEFAULT_SHEET) {
+                    target if target.starts_with('/') => target[1..].to_string(),
+                    target => format!("xl/{}", target),
+                });
+            }
+        }
+    }
+
+    Ok(DEFAULT_SHEET.to_string())
+}
+
+/// Returns an attribute of the first `element` in the document.
+fn first_attribute(
+    xml: &[u8],
+    element: &str,
+    attribute: &str,
+    prefix: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    for event in EventReader::new(xml) {
+        if let XmlEvent::StartElement {
+            name, attributes, ..
+        } = event?
+        {
+            if name.local_name == element {
+                return Ok(attributes
+                    .into_iter()
+                    .find(|a| a.name.local_name == attribute && a.name.prefix.as_deref() == prefix)
+                    .map(|a| a.value));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the shared string table, joining the runs of rich text strings and leaving out phonetic
+/// guides.
+fn shared_strings(xml: &[u8]) -> anyhow::Result<Vec<String>> {
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    let (mut in_text, mut in_phonetic) = (false, false);
+
+    for event in EventReader::new(xml) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "si" => current.clear(),
+                "t" => in_text = !in_phonetic,
+                "rPh" => in_phonetic = true,
+                _ => {}
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "si" => strings.push(current.clone()),
+                "t" => in_text = false,
+                "rPh" => in_phonetic = false,
+                _ => {}
+            },
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text)
+                if in_text =>
+            {
+                current.push_str(&text)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(strings)
+}
+
+/// Reads the cells of a sheet row by row, leaving skipped cells empty.
+fn sheet_rows(xml: &[u8], shared_strings: &[String]) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let (mut column, mut cell_type) = (0, String::new());
+    let mut value = String::new();
+    let mut in_value = false;
+
+    for event in EventReader::new(xml) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "row" => row.clear(),
+                "c" => {
+                    let attribute = |local_name: &str| {
+                        attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == local_name)
+                            .map(|attribute| attribute.value.as_str())
+                    };
+                    column = attribute("r").and_then(column_index).unwrap_or(row.len());
+                    cell_type = attribute("t").unwrap_or("n").to_string();
+                    value.clear();
+                }
+                "v" | "t" => in_value = true,
+                _ => {}
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "v" | "t" => in_value = false,
+                "c" => {
+                    let text = match cell_type.as_str() {
+                        "s" => value
+                            .trim()
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|index| shared_strings.get(index))
+                            .cloned()
+                            .ok_or_else(|| anyhow!("Invalid shared string {}", value))?,
+                        "b" => (if value.trim() == "1" { "TRUE" } else { "FALSE" }).to_string(),
+                        "n" => number(&value),
+                        _ => value.clone(),
+                    };
+                    if row.len() <= column {
+                        row.resize(column + 1, String::new());
+                    }
+                    row[column] = text;
+                }
+                "row" => rows.push(row.clone()),
+                _ => {}
+            },
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text)
+                if in_value =>
+            {
+                value.push_str(&text)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Returns the 0-based column of a cell reference such as `AB12`.
+fn column_index(reference: &str) -> Option<usize> {
+    let letters: Vec<_> = reference
+        .bytes()
+        .take_while(|b| b.is_ascii_alphabetic())
+        .collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let column = letters.iter().fold(0, |column, letter| {
+        column * 26 + (letter.to_ascii_uppercase() - b'A') as usize + 1
+    });
+    Some(column - 1)
+}
+
+/// Rounds a stored number to the 15 significant digits Excel displays.
+fn number(value: &str) -> String {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() => format!("{:.14e}", number)
+            .parse::<f64>()
+            .map_or_else(|_| value.to_string(), |number| number.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn workbook(sheet: &str, shared_strings: &str) -> Vec<u8> {
+        let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+        let entries = [
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Transactions" sheetId="1" r:id="rId3"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships><Relationship Id="rId1" Target="styles.xml"/><Relationship Id="rId3" Target="worksheets/transactions.xml"/></Relationships>"#,
+            ),
+            ("xl/worksheets/transactions.xml", sheet),
+            ("xl/sharedStrings.xml", shared_strings),
+        ];
+        for (name, contents) in entries {
+            w.start_file(name, SimpleFileOptions::default()).unwrap();
+            w.write_all(contents.as_bytes()).unwrap();
+        }
+        w.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn converts_first_sheet_to_csv() {
+        let xlsx = workbook(
+            r#"<worksheet><sheetData>
+                <row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c><c r="C1" t="inlineStr"><is><t>tx</t></is></c><c r="D1" t="s"><v>2</v></c></row>
+                <row r="2"><c r="A2" t="s"><v>3</v></c><c r="B2"><v>1</v></c><c r="C2"><v>1</v></c><c r="D2"><v>0.30000000000000004</v></c></row>
+                <row r="3"><c r="A3" t="s"><v>3</v></c><c r="C3"><v>2</v></c><c r="D3" t="str"><v>1,5</v></c></row>
+            </sheetData></worksheet>"#,
+            r#"<sst><si><t>type</t></si><si><t>client</t></si><si><r><t>amo</t></r><r><t>unt</t></r><rPh><t>x</t></rPh></si><si><t>deposit</t></si></sst>"#,
+        );
+
+        let csv = first_sheet_to_csv(Cursor::new(xlsx)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "type,client,tx,amount\ndeposit,1,1,0.3\ndeposit,,2,\"1,5\"\n"
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_workbooks() {
+        assert!(first_sheet_to_csv(Cursor::new(b"type,client,tx,amount\n".to_vec())).is_err());
+    }
+
+    #[test]
+    fn reads_column_references() {
+        assert_eq!(column_index("A1"), Some(0));
+        assert_eq!(column_index("Z9"), Some(25));
+        assert_eq!(column_index("AB12"), Some(27));
+        assert_eq!(column_index("12"), None);
+    }
+}
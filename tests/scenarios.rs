@@ -0,0 +1,15 @@
+use payment_transaction_engine::scenario;
+
+#[test]
+fn scenarios_produce_expected_accounts() {
+    let failures = scenario::run_scenarios("tests/scenarios").unwrap();
+
+    for failure in &failures {
+        eprintln!("Scenario {} failed:", failure.scenario.display());
+        for difference in &failure.differences {
+            eprintln!("  {}", difference);
+        }
+    }
+
+    assert!(failures.is_empty(), "{} scenarios failed", failures.len());
+}